@@ -1,7 +1,13 @@
-use crate::{IntoRequest, ToSchema};
+use crate::tokenizer::Tokenizer;
+use crate::{EndpointKind, IntoRequest, LlmError, SamplingDefaults, ToSchema};
+use base64::{engine::general_purpose::STANDARD, Engine};
 use derive_builder::Builder;
 use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use strum::{EnumString, IntoStaticStr};
+
+/// Tokens left free for the reply when picking a model by context window.
+const REPLY_HEADROOM: usize = 500;
 
 #[derive(Debug, Clone, Serialize, Builder)]
 pub struct ChatCompletionRequest {
@@ -18,10 +24,23 @@ pub struct ChatCompletionRequest {
     // #[builder(default, setter(strip_option))]
     // #[serde(skip_serializing_if = "Option::is_none")]
     // logit_bias: Option<f32>,
+    /// Whether to return log probabilities of the output tokens or not. If true, returns the log probabilities of each output token returned in the content of message.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    logprobs: Option<bool>,
+    /// An integer between 0 and 20 specifying the number of most likely tokens to return at each token position, each with an associated log probability. logprobs must be set to true if this parameter is used.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    top_logprobs: Option<u8>,
     /// The maximum number of tokens to generate in the chat completion.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
     max_tokens: Option<usize>,
+    /// The maximum number of tokens to generate including reasoning tokens. Replaces `max_tokens`
+    /// for the reasoning models, which reject it.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_completion_tokens: Option<usize>,
     /// How many chat completion choices to generate for each input message. Note that you will be charged based on the number of generated tokens across all of the choices. Keep n as 1 to minimize costs.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -46,7 +65,7 @@ pub struct ChatCompletionRequest {
     /// If set, partial message deltas will be sent, like in ChatGPT. Tokens will be sent as data-only server-sent events as they become available, with the stream terminated by a data: [DONE] message.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
-    stream: Option<bool>,
+    pub(crate) stream: Option<bool>,
     /// What sampling temperature to use, between 0 and 2. Higher values like 0.8 will make the output more random, while lower values like 0.2 will make it more focused and deterministic. We generally recommend altering this or top_p but not both.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -63,13 +82,22 @@ pub struct ChatCompletionRequest {
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
     tool_choice: Option<ToolChoice>,
+    /// Whether to enable parallel function calling during tool use. Set it to false for agents that execute one tool call at a time.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parallel_tool_calls: Option<bool>,
     /// A unique identifier representing your end-user, which can help OpenAI to monitor and detect abuse.
     #[builder(default, setter(strip_option, into))]
     #[serde(skip_serializing_if = "Option::is_none")]
     user: Option<String>,
+    /// Provider specific parameters merged into the top level of the body, e.g. `top_k` or
+    /// `repetition_penalty` for vLLM. Keys of the fields above are sent twice if repeated here.
+    #[builder(default, setter(strip_option))]
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    extra: Option<serde_json::Map<String, serde_json::Value>>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case", tag = "role")]
 pub enum ChatCompletionMessage {
     System(SystemMessage),
@@ -78,17 +106,40 @@ pub enum ChatCompletionMessage {
     Tool(ToolMessage),
 }
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(
+    Debug, Clone, Default, PartialEq, Eq, EnumString, IntoStaticStr, Serialize, Deserialize,
+)]
+#[serde(from = "String", into = "String")]
 pub enum ChatCompleteModel {
     #[default]
-    #[serde(rename = "gpt-3.5-turbo-1106")]
+    #[strum(serialize = "gpt-3.5-turbo-1106")]
     Gpt3Turbo,
-    #[serde(rename = "gpt-3.5-turbo-instruct")]
+    #[strum(serialize = "gpt-3.5-turbo-instruct")]
     Gpt3TurboInstruct,
-    #[serde(rename = "gpt-4-1106-preview")]
+    #[strum(serialize = "gpt-4-1106-preview")]
     Gpt4Turbo,
-    #[serde(rename = "gpt-4-1106-vision-preview")]
+    #[strum(serialize = "gpt-4-1106-vision-preview")]
     Gpt4TurboVision,
+    #[strum(serialize = "gpt-4o-mini")]
+    Gpt4oMini,
+    /// Any other model name, e.g. a newly released or fine-tuned model.
+    #[strum(default)]
+    Custom(String),
+}
+
+impl From<String> for ChatCompleteModel {
+    fn from(name: String) -> Self {
+        name.parse().unwrap_or(Self::Custom(name))
+    }
+}
+
+impl From<ChatCompleteModel> for String {
+    fn from(model: ChatCompleteModel) -> Self {
+        match model {
+            ChatCompleteModel::Custom(name) => name,
+            model => model.as_str().to_owned(),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -117,6 +168,9 @@ pub struct Tool {
 pub enum ToolType {
     #[default]
     Function,
+    /// A tool type this version of the SDK doesn't know yet, so newer responses still parse.
+    #[serde(other)]
+    Unknown,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -141,20 +195,68 @@ pub enum ToolChoice {
     },
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SystemMessage {
     content: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(skip_serializing_if = "Option::is_none", default)]
     name: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserMessage {
-    content: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
+    content: MessageContent,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
     name: Option<String>,
 }
 
+/// The content of a user message: plain text, or a list of parts for multimodal models.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MessageContent {
+    Text(String),
+    Parts(Vec<ContentPart>),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+    InputAudio { input_audio: InputAudio },
+}
+
+/// An image given by URL or as raw bytes, which are sent as a base64 data URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageInput {
+    Url(String),
+    /// Raw image data with its mime type, e.g. `image/png`.
+    Bytes {
+        data: Vec<u8>,
+        mime_type: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImageUrl {
+    /// Either a URL of the image or the base64 encoded image data as a data URL.
+    url: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputAudio {
+    /// Base64 encoded audio data.
+    data: String,
+    format: AudioFormat,
+}
+
+/// The format of audio sent to audio capable chat models like gpt-4o-audio-preview.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AudioFormat {
+    Wav,
+    Mp3,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AssistantMessage {
     #[serde(default)]
@@ -163,11 +265,43 @@ pub struct AssistantMessage {
     pub name: Option<String>,
     #[serde(skip_serializing_if = "Vec::is_empty", default)]
     pub tool_calls: Vec<ToolCall>,
+    /// Why the model refused the request, in which case there is no content. Structured output
+    /// models report refusals here instead of in content that doesn't match the schema.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub refusal: Option<String>,
+    /// Annotations of the content, e.g. the sources cited by search models. Not sent back to
+    /// the API.
+    #[serde(skip_serializing, default)]
+    pub annotations: Vec<Annotation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Annotation {
+    UrlCitation {
+        url_citation: UrlCitation,
+    },
+    /// An annotation type this SDK doesn't know yet.
+    #[serde(other)]
+    Unknown,
+}
+
+/// A web source cited in the message content.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct UrlCitation {
+    pub url: String,
+    pub title: String,
+    /// The index of the first character of the citation in the message content.
+    pub start_index: usize,
+    /// The index one past the last character of the citation in the message content, so
+    /// `start_index..end_index` is the cited range.
+    pub end_index: usize,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolCall {
     pub id: String,
+    /// The type of the tool, serialized as `type`. Currently, only functions are supported.
     pub r#type: ToolType,
     pub function: FunctionCall,
 }
@@ -178,7 +312,7 @@ pub struct FunctionCall {
     pub arguments: String,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ToolMessage {
     content: String,
     tool_call_id: String,
@@ -210,6 +344,50 @@ pub struct ChatCompletionChoice {
     pub index: usize,
     /// A chat completion message generated by the model.
     pub message: AssistantMessage,
+    /// Log probability information for the choice, if `logprobs` was requested.
+    #[serde(default)]
+    pub logprobs: Option<ChatCompletionLogprobs>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ChatCompletionLogprobs {
+    /// A list of message content tokens with log probability information. Empty if the model
+    /// refused.
+    #[serde(default, deserialize_with = "null_as_empty")]
+    pub content: Vec<TokenLogprob>,
+    /// A list of refusal tokens with log probability information, if the model refused.
+    #[serde(default, deserialize_with = "null_as_empty")]
+    pub refusal: Vec<TokenLogprob>,
+}
+
+/// Deserialize a list that may be `null`, as the logprobs of the part of a reply that wasn't
+/// generated are.
+fn null_as_empty<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<TokenLogprob>, D::Error> {
+    Ok(Option::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenLogprob {
+    /// The token.
+    pub token: String,
+    /// The log probability of this token.
+    pub logprob: f64,
+    /// The UTF-8 bytes of the token. Null if there is no bytes representation for the token, e.g. a part of a multi-byte character.
+    #[serde(default)]
+    pub bytes: Option<Vec<u8>>,
+    /// The most likely tokens at this position, as many as `top_logprobs` requested.
+    #[serde(default)]
+    pub top_logprobs: Vec<TopLogprob>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TopLogprob {
+    pub token: String,
+    pub logprob: f64,
+    #[serde(default)]
+    pub bytes: Option<Vec<u8>>,
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
@@ -237,6 +415,22 @@ impl IntoRequest for ChatCompletionRequest {
         let url = format!("{}/chat/completions", base_url);
         client.post(url).json(&self)
     }
+
+    fn endpoint_kind(&self) -> Option<EndpointKind> {
+        Some(EndpointKind::Chat)
+    }
+
+    fn set_default_user(&mut self, user: &str) {
+        self.user.get_or_insert_with(|| user.to_owned());
+    }
+
+    fn set_sampling_defaults(&mut self, defaults: &SamplingDefaults) {
+        self.temperature = self.temperature.or(defaults.temperature);
+        self.top_p = self.top_p.or(defaults.top_p);
+        if self.max_completion_tokens.is_none() {
+            self.max_tokens = self.max_tokens.or(defaults.max_tokens);
+        }
+    }
 }
 
 impl ChatCompletionRequest {
@@ -262,6 +456,176 @@ impl ChatCompletionRequest {
     }
 }
 
+impl ChatCompletionRequest {
+    pub(crate) fn push_message(&mut self, message: ChatCompletionMessage) {
+        self.messages.push(message);
+    }
+
+    /// Sample with temperature 0 and `seed` unless the request has its own seed, so repeated
+    /// runs only differ by the backend.
+    pub(crate) fn pin_sampling(&mut self, seed: usize) {
+        self.seed.get_or_insert(seed);
+        self.temperature = Some(0.0);
+    }
+
+    /// Whether the token limit is sent as `max_tokens`, which the reasoning models reject.
+    pub(crate) fn uses_max_tokens(&self) -> bool {
+        self.max_tokens.is_some()
+    }
+
+    /// Send the token limit as `max_completion_tokens` instead of `max_tokens`. Returns whether
+    /// `max_tokens` was set.
+    pub(crate) fn use_max_completion_tokens(&mut self) -> bool {
+        let Some(max_tokens) = self.max_tokens.take() else {
+            return false;
+        };
+        self.max_completion_tokens.get_or_insert(max_tokens);
+        true
+    }
+
+    /// Estimate the prompt tokens of the messages and tools. Exact with the `tokenizer`
+    /// feature, a rough estimate without it.
+    pub fn estimated_tokens(&self) -> usize {
+        let tokenizer = Tokenizer::for_model(&self.model);
+        // every reply is primed with <|start|>assistant<|message|>
+        let mut tokens = 3;
+        for message in &self.messages {
+            tokens += message.estimated_tokens(&tokenizer);
+        }
+        if !self.tools.is_empty() {
+            let tools = serde_json::to_string(&self.tools).unwrap_or_default();
+            tokens += tokenizer.count(&tools);
+        }
+        tokens
+    }
+
+    /// The text of the last user message, with the text parts of multimodal content joined by
+    /// newlines.
+    pub(crate) fn last_user_text(&self) -> Option<String> {
+        self.messages
+            .iter()
+            .rev()
+            .find_map(|message| match message {
+                ChatCompletionMessage::User(msg) => Some(match &msg.content {
+                    MessageContent::Text(text) => text.clone(),
+                    MessageContent::Parts(parts) => parts
+                        .iter()
+                        .filter_map(|part| match part {
+                            ContentPart::Text { text } => Some(text.as_str()),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n"),
+                }),
+                _ => None,
+            })
+    }
+
+    /// Whether the prompt and `max_tokens` fit in the context window of `model`. Models with an
+    /// unknown context window are assumed to fit.
+    pub fn fits_in(&self, model: &ChatCompleteModel) -> bool {
+        match model.context_window() {
+            Some(limit) => self.estimated_tokens() + self.max_tokens.unwrap_or(0) <= limit,
+            None => true,
+        }
+    }
+
+    /// Build a request for the first of `models` whose context window has room for the
+    /// messages and a reply of `REPLY_HEADROOM` tokens. Models with an unknown context window
+    /// are assumed to fit.
+    pub(crate) fn for_first_fitting_model(
+        messages: Vec<ChatCompletionMessage>,
+        models: impl IntoIterator<Item = ChatCompleteModel>,
+    ) -> Result<(Self, ChatCompleteModel), LlmError> {
+        let mut largest = None;
+        for model in models {
+            let req = Self::new(model.clone(), messages.clone());
+            let Some(limit) = model.context_window() else {
+                return Ok((req, model));
+            };
+            let estimated = req.estimated_tokens() + REPLY_HEADROOM;
+            if estimated < limit {
+                return Ok((req, model));
+            }
+            if largest.is_none_or(|(_, l)| limit > l) {
+                largest = Some((estimated, limit));
+            }
+        }
+        let (estimated, limit) = largest.unwrap_or_default();
+        Err(LlmError::ContextWindowExceeded { estimated, limit })
+    }
+
+    /// Fail locally if the request can't fit in the context window of its model, instead of
+    /// waiting for the API to reject it.
+    pub fn preflight(&self) -> Result<(), LlmError> {
+        let Some(limit) = self.model.context_window() else {
+            return Ok(());
+        };
+        let estimated = self.estimated_tokens() + self.max_tokens.unwrap_or(0);
+        if estimated > limit {
+            return Err(LlmError::ContextWindowExceeded { estimated, limit });
+        }
+        Ok(())
+    }
+}
+
+impl ChatCompletionResponse {
+    /// The fraction of the context window of `model` used by this exchange, e.g. to summarize the
+    /// history once it passes 0.8. `model` is taken explicitly because the response names a dated
+    /// snapshot, which is parsed as a custom model. `None` if the context window is unknown.
+    pub fn context_utilization(&self, model: &ChatCompleteModel) -> Option<f32> {
+        let limit = model.context_window()?;
+        Some(self.usage.total_tokens as f32 / limit as f32)
+    }
+
+    /// The name of the model snapshot that served the request, e.g. `gpt-4o-2024-08-06` when
+    /// `gpt-4o` was requested. Record this rather than the requested alias for reproducibility.
+    pub fn resolved_model(&self) -> &str {
+        self.model.as_str()
+    }
+
+    /// `created` as a date. Out of range timestamps give the Unix epoch.
+    #[cfg(feature = "chrono")]
+    pub fn created_at(&self) -> chrono::DateTime<chrono::Utc> {
+        chrono::DateTime::from_timestamp(self.created as i64, 0).unwrap_or_default()
+    }
+}
+
+impl ChatCompleteModel {
+    /// The model name as sent to and returned by the API.
+    pub fn as_str(&self) -> &str {
+        match self {
+            ChatCompleteModel::Custom(name) => name,
+            model => model.into(),
+        }
+    }
+
+    /// The context window of the model in tokens, if known.
+    pub fn context_window(&self) -> Option<usize> {
+        match self {
+            ChatCompleteModel::Gpt3Turbo => Some(16_385),
+            ChatCompleteModel::Gpt3TurboInstruct => Some(4_096),
+            ChatCompleteModel::Gpt4Turbo
+            | ChatCompleteModel::Gpt4TurboVision
+            | ChatCompleteModel::Gpt4oMini => Some(128_000),
+            ChatCompleteModel::Custom(_) => None,
+        }
+    }
+}
+
+impl ToolCall {
+    /// The function the model called, with its arguments as a JSON string.
+    pub fn function(&self) -> &FunctionCall {
+        &self.function
+    }
+}
+
+impl ChatResponseFormatObject {
+    pub fn new(format: ChatResponseFormat) -> Self {
+        Self { r#type: format }
+    }
+}
+
 impl ChatCompletionMessage {
     pub fn new_system(content: impl Into<String>, name: &str) -> ChatCompletionMessage {
         ChatCompletionMessage::System(SystemMessage {
@@ -272,11 +636,100 @@ impl ChatCompletionMessage {
 
     pub fn new_user(content: impl Into<String>, name: &str) -> ChatCompletionMessage {
         ChatCompletionMessage::User(UserMessage {
-            content: content.into(),
+            content: MessageContent::Text(content.into()),
+            name: Self::get_name(name),
+        })
+    }
+
+    /// A user message made of text, image and audio parts, for multimodal models.
+    pub fn new_user_with_parts(
+        parts: impl Into<Vec<ContentPart>>,
+        name: &str,
+    ) -> ChatCompletionMessage {
+        ChatCompletionMessage::User(UserMessage {
+            content: MessageContent::Parts(parts.into()),
             name: Self::get_name(name),
         })
     }
 
+    pub fn new_assistant(content: impl Into<String>, name: &str) -> ChatCompletionMessage {
+        ChatCompletionMessage::Assistant(AssistantMessage {
+            content: Some(content.into()),
+            name: Self::get_name(name),
+            tool_calls: vec![],
+            refusal: None,
+            annotations: vec![],
+        })
+    }
+
+    pub fn new_tool(
+        content: impl Into<String>,
+        tool_call_id: impl Into<String>,
+    ) -> ChatCompletionMessage {
+        ChatCompletionMessage::Tool(ToolMessage {
+            content: content.into(),
+            tool_call_id: tool_call_id.into(),
+        })
+    }
+
+    /// The web sources cited in an assistant message, in order of appearance.
+    pub fn citations(&self) -> Vec<&UrlCitation> {
+        match self {
+            ChatCompletionMessage::Assistant(msg) => msg
+                .annotations
+                .iter()
+                .filter_map(|annotation| match annotation {
+                    Annotation::UrlCitation { url_citation } => Some(url_citation),
+                    Annotation::Unknown => None,
+                })
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// The participant name of the message, if any. Tool messages don't carry a name.
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            ChatCompletionMessage::System(msg) => msg.name.as_deref(),
+            ChatCompletionMessage::User(msg) => msg.name.as_deref(),
+            ChatCompletionMessage::Assistant(msg) => msg.name.as_deref(),
+            ChatCompletionMessage::Tool(_) => None,
+        }
+    }
+
+    fn estimated_tokens(&self, tokenizer: &Tokenizer) -> usize {
+        // every message follows <|start|>{role/name}\n{content}<|end|>\n
+        let mut tokens = 4 + self.name().map_or(0, |text| tokenizer.count(text));
+        tokens += match self {
+            ChatCompletionMessage::System(msg) => tokenizer.count(&msg.content),
+            ChatCompletionMessage::User(msg) => match &msg.content {
+                MessageContent::Text(text) => tokenizer.count(text),
+                MessageContent::Parts(parts) => parts
+                    .iter()
+                    .map(|part| match part {
+                        ContentPart::Text { text } => tokenizer.count(text),
+                        _ => 0,
+                    })
+                    .sum(),
+            },
+            ChatCompletionMessage::Assistant(msg) => {
+                msg.content
+                    .as_deref()
+                    .map_or(0, |text| tokenizer.count(text))
+                    + msg
+                        .tool_calls
+                        .iter()
+                        .map(|call| {
+                            tokenizer.count(&call.function.name)
+                                + tokenizer.count(&call.function.arguments)
+                        })
+                        .sum::<usize>()
+            }
+            ChatCompletionMessage::Tool(msg) => tokenizer.count(&msg.content),
+        };
+        tokens
+    }
+
     fn get_name(name: &str) -> Option<String> {
         if name.is_empty() {
             None
@@ -286,6 +739,61 @@ impl ChatCompletionMessage {
     }
 }
 
+impl ImageInput {
+    pub fn url(url: impl Into<String>) -> Self {
+        ImageInput::Url(url.into())
+    }
+
+    pub fn bytes(data: Vec<u8>, mime_type: impl Into<String>) -> Self {
+        ImageInput::Bytes {
+            data,
+            mime_type: mime_type.into(),
+        }
+    }
+
+    /// The URL, or the data URL of the bytes.
+    pub fn to_url(&self) -> String {
+        match self {
+            ImageInput::Url(url) => url.clone(),
+            ImageInput::Bytes { data, mime_type } => {
+                format!("data:{};base64,{}", mime_type, STANDARD.encode(data))
+            }
+        }
+    }
+}
+
+impl Serialize for ImageInput {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_url())
+    }
+}
+
+impl ContentPart {
+    pub fn text(text: impl Into<String>) -> Self {
+        ContentPart::Text { text: text.into() }
+    }
+
+    pub fn image(image: &ImageInput) -> Self {
+        Self::image_url(image.to_url())
+    }
+
+    pub fn image_url(url: impl Into<String>) -> Self {
+        ContentPart::ImageUrl {
+            image_url: ImageUrl { url: url.into() },
+        }
+    }
+
+    /// Audio input for audio capable models. The bytes are base64 encoded.
+    pub fn input_audio(bytes: impl AsRef<[u8]>, format: AudioFormat) -> Self {
+        ContentPart::InputAudio {
+            input_audio: InputAudio {
+                data: STANDARD.encode(bytes),
+                format,
+            },
+        }
+    }
+}
+
 impl Tool {
     pub fn new_function<T: ToSchema>(
         name: impl Into<String>,
@@ -307,8 +815,10 @@ impl Tool {
 mod test {
     use crate::SDK;
     use crate::{
-        ChatCompleteModel, ChatCompletionMessage, ChatCompletionRequest,
-        ChatCompletionRequestBuilder, FinishReason, Tool, ToolChoice,
+        Annotation, AssistantMessage, AudioFormat, ChatCompleteModel, ChatCompletionLogprobs,
+        ChatCompletionMessage, ChatCompletionRequest, ChatCompletionRequestBuilder,
+        ChatCompletionResponse, ContentPart, FinishReason, IntoRequest, SamplingDefaults, Tool,
+        ToolChoice, ToolType,
     };
     use anyhow::Result;
     use schemars::JsonSchema;
@@ -381,6 +891,306 @@ mod test {
             })
         )
     }
+
+    #[test]
+    fn message_name_should_only_serialize_when_set() {
+        let alice = ChatCompletionMessage::new_user("Hi Bob.", "alice");
+        let bob = ChatCompletionMessage::new_assistant("Hi Alice.", "bob");
+        let anonymous = ChatCompletionMessage::new_user("Hi all.", "");
+        assert_eq!(alice.name(), Some("alice"));
+        assert_eq!(anonymous.name(), None);
+        assert_eq!(
+            serde_json::to_value(vec![alice, bob, anonymous]).unwrap(),
+            serde_json::json!([
+                {"role": "user", "content": "Hi Bob.", "name": "alice"},
+                {"role": "assistant", "content": "Hi Alice.", "name": "bob"},
+                {"role": "user", "content": "Hi all."}
+            ])
+        );
+    }
+
+    #[test]
+    fn input_audio_should_serialize_as_base64_part() {
+        let msg = ChatCompletionMessage::new_user_with_parts(
+            vec![
+                ContentPart::text("What is in this recording?"),
+                ContentPart::input_audio(b"hello", AudioFormat::Wav),
+            ],
+            "",
+        );
+        assert_eq!(
+            serde_json::to_value(msg).unwrap(),
+            serde_json::json!({
+                "role": "user",
+                "content": [
+                    {"type": "text", "text": "What is in this recording?"},
+                    {"type": "input_audio", "input_audio": {"data": "aGVsbG8=", "format": "wav"}}
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn default_user_should_not_override_request_user() {
+        let messages = vec![ChatCompletionMessage::new_user("Hi", "")];
+        let mut req = ChatCompletionRequest::new(ChatCompleteModel::default(), messages.clone());
+        req.set_default_user("tenant-1");
+        assert_eq!(serde_json::to_value(&req).unwrap()["user"], "tenant-1");
+
+        let mut req = ChatCompletionRequestBuilder::default()
+            .messages(messages)
+            .user("end-user-42")
+            .build()
+            .unwrap();
+        req.set_default_user("tenant-1");
+        assert_eq!(serde_json::to_value(&req).unwrap()["user"], "end-user-42");
+    }
+
+    #[test]
+    fn context_utilization_should_divide_usage_by_context_window() {
+        let res: ChatCompletionResponse = serde_json::from_value(serde_json::json!({
+            "id": "chatcmpl-1",
+            "choices": [],
+            "created": 0,
+            "model": "gpt-4o-mini-2024-07-18",
+            "system_fingerprint": null,
+            "object": "chat.completion",
+            "usage": {"completion_tokens": 0, "prompt_tokens": 32_000, "total_tokens": 32_000}
+        }))
+        .unwrap();
+        assert_eq!(
+            res.context_utilization(&ChatCompleteModel::Gpt4oMini),
+            Some(0.25)
+        );
+        assert_eq!(res.context_utilization(&res.model), None);
+        assert_eq!(res.resolved_model(), "gpt-4o-mini-2024-07-18");
+    }
+
+    #[test]
+    fn model_name_should_match_its_json() {
+        for model in [
+            ChatCompleteModel::Gpt4oMini,
+            ChatCompleteModel::Custom("ft:gpt-4o-mini:acme".into()),
+        ] {
+            let json = serde_json::to_value(&model).unwrap();
+            assert_eq!(json, model.as_str());
+            assert_eq!(
+                serde_json::from_value::<ChatCompleteModel>(json).unwrap(),
+                model
+            );
+        }
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn created_at_should_convert_the_timestamp() {
+        let res: ChatCompletionResponse =
+            serde_json::from_str(&crate::test_server::chat_response("hi")).unwrap();
+        assert_eq!(res.created_at().to_rfc3339(), "2023-11-14T22:13:20+00:00");
+    }
+
+    #[test]
+    fn preflight_should_reject_requests_over_the_context_window() {
+        let req = get_simple_completion_request();
+        assert!(req.estimated_tokens() > 10);
+        assert!(req.fits_in(&ChatCompleteModel::Gpt4Turbo));
+        assert!(req.preflight().is_ok());
+
+        let long = "word ".repeat(20_000);
+        let messages = vec![ChatCompletionMessage::new_user(long, "")];
+        let req = ChatCompletionRequest::new(ChatCompleteModel::Gpt3Turbo, messages);
+        assert!(!req.fits_in(&ChatCompleteModel::Gpt3Turbo));
+        assert!(req.fits_in(&ChatCompleteModel::Custom("local-model".into())));
+        assert!(matches!(
+            req.preflight(),
+            Err(crate::LlmError::ContextWindowExceeded { limit: 16_385, .. })
+        ));
+    }
+
+    #[test]
+    fn first_fitting_model_should_walk_the_fallback_chain() {
+        let long = "word ".repeat(20_000);
+        let messages = vec![ChatCompletionMessage::new_user(long, "")];
+        let (_, model) = ChatCompletionRequest::for_first_fitting_model(
+            messages.clone(),
+            [ChatCompleteModel::Gpt3Turbo, ChatCompleteModel::Gpt4oMini],
+        )
+        .unwrap();
+        assert_eq!(model, ChatCompleteModel::Gpt4oMini);
+        assert!(matches!(
+            ChatCompletionRequest::for_first_fitting_model(
+                messages,
+                [
+                    ChatCompleteModel::Gpt3TurboInstruct,
+                    ChatCompleteModel::Gpt3Turbo
+                ],
+            ),
+            Err(crate::LlmError::ContextWindowExceeded { limit: 16_385, .. })
+        ));
+    }
+
+    #[test]
+    fn extra_should_merge_into_top_level() {
+        let extra = serde_json::json!({"top_k": 40, "repetition_penalty": 1.1});
+        let req = ChatCompletionRequestBuilder::default()
+            .messages(vec![ChatCompletionMessage::new_user("hi", "")])
+            .extra(extra.as_object().unwrap().clone())
+            .build()
+            .unwrap();
+        let value = serde_json::to_value(&req).unwrap();
+        assert_eq!(value["top_k"], 40);
+        assert_eq!(value["repetition_penalty"], 1.1);
+        assert!(value.get("extra").is_none());
+    }
+
+    #[test]
+    fn parallel_tool_calls_should_only_serialize_when_set() {
+        let mut builder = ChatCompletionRequestBuilder::default();
+        builder.messages(vec![ChatCompletionMessage::new_user("hi", "")]);
+        let value = serde_json::to_value(builder.build().unwrap()).unwrap();
+        assert!(value.get("parallel_tool_calls").is_none());
+
+        let value =
+            serde_json::to_value(builder.parallel_tool_calls(false).build().unwrap()).unwrap();
+        assert_eq!(value["parallel_tool_calls"], false);
+    }
+
+    #[test]
+    fn tool_type_should_accept_unknown_types() {
+        let known: ToolType = serde_json::from_str(r#""function""#).unwrap();
+        assert_eq!(known, ToolType::Function);
+        let unknown: ToolType = serde_json::from_str(r#""custom""#).unwrap();
+        assert_eq!(unknown, ToolType::Unknown);
+    }
+
+    #[test]
+    fn logprobs_should_be_parsed_from_choice() {
+        let res: ChatCompletionResponse = serde_json::from_value(serde_json::json!({
+            "id": "chatcmpl-9rNiJ4yL0Mhy9q0NoWq2oQNMv7gfA",
+            "object": "chat.completion",
+            "created": 1722592147,
+            "model": "gpt-4o-mini-2024-07-18",
+            "system_fingerprint": "fp_0f03d4f0ee",
+            "choices": [{
+                "index": 0,
+                "message": {"role": "assistant", "content": "Hi!"},
+                "logprobs": {"content": [
+                    {"token": "Hi", "logprob": -0.0031, "bytes": [72, 105], "top_logprobs": [
+                        {"token": "Hi", "logprob": -0.0031, "bytes": [72, 105]},
+                        {"token": "Hello", "logprob": -5.78, "bytes": [72, 101, 108, 108, 111]}
+                    ]},
+                    {"token": "!", "logprob": -0.12, "bytes": [33], "top_logprobs": [
+                        {"token": "!", "logprob": -0.12, "bytes": [33]},
+                        {"token": ".", "logprob": -2.18, "bytes": null}
+                    ]}
+                ], "refusal": null},
+                "finish_reason": "stop"
+            }],
+            "usage": {"prompt_tokens": 9, "completion_tokens": 2, "total_tokens": 11}
+        }))
+        .unwrap();
+        let logprobs = res.choices[0].logprobs.as_ref().unwrap();
+        assert_eq!(logprobs.content.len(), 2);
+        assert_eq!(logprobs.content[0].bytes.as_deref(), Some(&b"Hi"[..]));
+        assert_eq!(logprobs.content[0].top_logprobs[1].token, "Hello");
+        assert_eq!(logprobs.content[1].top_logprobs[1].bytes, None);
+        assert!(logprobs.refusal.is_empty());
+
+        let refused: ChatCompletionLogprobs = serde_json::from_value(serde_json::json!({
+            "content": null,
+            "refusal": [{"token": "Sorry", "logprob": -0.01, "bytes": null, "top_logprobs": []}]
+        }))
+        .unwrap();
+        assert!(refused.content.is_empty());
+        assert_eq!(refused.refusal[0].token, "Sorry");
+
+        let req = ChatCompletionRequestBuilder::default()
+            .messages(vec![ChatCompletionMessage::new_user("hi", "")])
+            .logprobs(true)
+            .top_logprobs(5)
+            .build()
+            .unwrap();
+        let value = serde_json::to_value(&req).unwrap();
+        assert_eq!(value["logprobs"], true);
+        assert_eq!(value["top_logprobs"], 5);
+    }
+
+    #[test]
+    fn citations_should_be_parsed_from_annotations() {
+        let msg: AssistantMessage = serde_json::from_value(serde_json::json!({
+            "role": "assistant",
+            "content": "Rust 1.0 was released in 2015.",
+            "annotations": [
+                {"type": "url_citation", "url_citation": {
+                    "url": "https://blog.rust-lang.org/2015/05/15/Rust-1.0.html",
+                    "title": "Announcing Rust 1.0",
+                    "start_index": 0,
+                    "end_index": 30
+                }},
+                {"type": "file_citation", "file_citation": {"file_id": "file-1"}}
+            ]
+        }))
+        .unwrap();
+        assert_eq!(msg.annotations[1], Annotation::Unknown);
+        let msg = ChatCompletionMessage::Assistant(msg);
+        let citations = msg.citations();
+        assert_eq!(citations.len(), 1);
+        assert_eq!(citations[0].title, "Announcing Rust 1.0");
+        assert!(serde_json::to_value(&msg)
+            .unwrap()
+            .get("annotations")
+            .is_none());
+    }
+
+    #[test]
+    fn use_max_completion_tokens_should_move_the_limit() {
+        let mut req = ChatCompletionRequestBuilder::default()
+            .messages(vec![ChatCompletionMessage::new_user("hi", "")])
+            .max_tokens(100usize)
+            .build()
+            .unwrap();
+        assert!(req.use_max_completion_tokens());
+        req.set_sampling_defaults(&SamplingDefaults {
+            max_tokens: Some(50),
+            ..Default::default()
+        });
+        let json = serde_json::to_value(&req).unwrap();
+        assert!(json.get("max_tokens").is_none());
+        assert_eq!(json["max_completion_tokens"], 100);
+        assert!(!req.use_max_completion_tokens());
+    }
+
+    #[test]
+    fn sampling_defaults_should_not_override_request_values() {
+        let defaults = SamplingDefaults {
+            temperature: Some(0.2),
+            top_p: Some(1.0),
+            max_tokens: Some(512),
+        };
+        let mut req = ChatCompletionRequestBuilder::default()
+            .messages(vec![ChatCompletionMessage::new_user("Hi", "")])
+            .temperature(0.9)
+            .build()
+            .unwrap();
+        req.set_sampling_defaults(&defaults);
+        let json = serde_json::to_value(&req).unwrap();
+        assert_eq!(json["temperature"], 0.9f32 as f64);
+        assert_eq!(json["top_p"], 1.0);
+        assert_eq!(json["max_tokens"], 512);
+    }
+
+    #[test]
+    fn custom_model_should_round_trip() {
+        let model: ChatCompleteModel = serde_json::from_str(r#""gpt-4o-2024-08-06""#).unwrap();
+        assert_eq!(model, ChatCompleteModel::Custom("gpt-4o-2024-08-06".into()));
+        assert_eq!(
+            serde_json::to_value(&model).unwrap(),
+            serde_json::json!("gpt-4o-2024-08-06")
+        );
+        let model: ChatCompleteModel = serde_json::from_str(r#""gpt-4-1106-preview""#).unwrap();
+        assert_eq!(model, ChatCompleteModel::Gpt4Turbo);
+    }
+
     #[tokio::test]
     async fn simple_chat_completion_should_work() -> Result<()> {
         let req = get_simple_completion_request();
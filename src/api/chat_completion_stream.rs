@@ -0,0 +1,342 @@
+use crate::{
+    AssistantMessage, ChatCompletionMessage, FinishReason, FunctionCall, ToolCall, ToolType,
+};
+use futures::{Stream, StreamExt};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// A streamed chunk of a chat completion, sent when `stream` is set on the request.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChatCompletionChunk {
+    /// A unique identifier for the chat completion. Each chunk has the same ID.
+    pub id: String,
+    /// A list of chat completion choices. Deltas of different choices are interleaved when n is greater than 1.
+    pub choices: Vec<ChatCompletionChunkChoice>,
+    /// The Unix timestamp (in seconds) of when the chat completion was created. Each chunk has the same timestamp.
+    pub created: usize,
+    /// The model to generate the completion.
+    pub model: String,
+    /// This fingerprint represents the backend configuration that the model runs with.
+    #[serde(default)]
+    pub system_fingerprint: Option<String>,
+    /// The object type, which is always chat.completion.chunk.
+    pub object: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChatCompletionChunkChoice {
+    /// The index of the choice this delta belongs to.
+    pub index: usize,
+    /// A chat completion delta generated by streamed model responses.
+    pub delta: ChatCompletionDelta,
+    /// The reason the model stopped generating tokens, only set in the last chunk of the choice.
+    #[serde(default)]
+    pub finish_reason: Option<FinishReason>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ChatCompletionDelta {
+    /// The role of the author of this message, only sent in the first chunk.
+    #[serde(default)]
+    pub role: Option<String>,
+    /// The contents of the chunk message.
+    #[serde(default)]
+    pub content: Option<String>,
+    /// A fragment of the refusal, streamed instead of content when the model refuses.
+    #[serde(default)]
+    pub refusal: Option<String>,
+    #[serde(default)]
+    pub tool_calls: Vec<ToolCallDelta>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ToolCallDelta {
+    /// The index of the tool call within the message.
+    pub index: usize,
+    #[serde(default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub r#type: Option<ToolType>,
+    #[serde(default)]
+    pub function: Option<FunctionCallDelta>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct FunctionCallDelta {
+    #[serde(default)]
+    pub name: Option<String>,
+    /// A fragment of the JSON encoded arguments.
+    #[serde(default)]
+    pub arguments: Option<String>,
+}
+
+/// Reassemble streamed chunks into the assistant messages of each choice.
+#[derive(Debug, Clone, Default)]
+pub struct StreamAccumulator {
+    choices: BTreeMap<usize, AssistantMessage>,
+    finish_reasons: BTreeMap<usize, FinishReason>,
+}
+
+impl StreamAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, chunk: &ChatCompletionChunk) {
+        for choice in &chunk.choices {
+            let message = self
+                .choices
+                .entry(choice.index)
+                .or_insert_with(|| AssistantMessage {
+                    content: None,
+                    name: None,
+                    tool_calls: vec![],
+                    refusal: None,
+                    annotations: vec![],
+                });
+            let delta = &choice.delta;
+            if let Some(content) = &delta.content {
+                message
+                    .content
+                    .get_or_insert_with(String::new)
+                    .push_str(content);
+            }
+            if let Some(refusal) = &delta.refusal {
+                message
+                    .refusal
+                    .get_or_insert_with(String::new)
+                    .push_str(refusal);
+            }
+            for call in &delta.tool_calls {
+                push_tool_call(&mut message.tool_calls, call);
+            }
+            if let Some(reason) = choice.finish_reason {
+                self.finish_reasons.insert(choice.index, reason);
+            }
+        }
+    }
+
+    /// The reason the choice at `index` stopped, `None` while it is still being generated.
+    pub fn finish_reason(&self, index: usize) -> Option<FinishReason> {
+        self.finish_reasons.get(&index).copied()
+    }
+
+    /// The refusal of the choice at `index` so far, if the model refused. The refusal is kept
+    /// apart from the content, which stays empty.
+    pub fn refusal(&self, index: usize) -> Option<&str> {
+        self.choices.get(&index)?.refusal.as_deref()
+    }
+
+    /// Whether the model refused to answer in the choice at `index`.
+    pub fn is_refusal(&self, index: usize) -> bool {
+        self.refusal(index).is_some()
+    }
+
+    /// The messages accumulated so far, ordered by choice index.
+    pub fn into_messages(self) -> Vec<ChatCompletionMessage> {
+        self.choices
+            .into_values()
+            .map(ChatCompletionMessage::Assistant)
+            .collect()
+    }
+
+    /// Drain a chunk stream, keeping whatever was generated before it failed. Unlike
+    /// collecting the stream into a `Result`, an interrupted stream doesn't discard the
+    /// partial messages.
+    pub async fn collect_lossy<S>(mut stream: S) -> PartialCompletion
+    where
+        S: Stream<Item = anyhow::Result<ChatCompletionChunk>> + Unpin,
+    {
+        let mut acc = Self::new();
+        let mut error = None;
+        while let Some(chunk) = stream.next().await {
+            match chunk {
+                Ok(chunk) => acc.push(&chunk),
+                Err(e) => {
+                    error = Some(e);
+                    break;
+                }
+            }
+        }
+        PartialCompletion {
+            messages: acc.into_messages(),
+            error,
+        }
+    }
+}
+
+/// The messages accumulated from a stream, along with the error that ended it early, if any.
+#[derive(Debug)]
+pub struct PartialCompletion {
+    pub messages: Vec<ChatCompletionMessage>,
+    pub error: Option<anyhow::Error>,
+}
+
+impl PartialCompletion {
+    /// Whether the stream ran to completion.
+    pub fn is_complete(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+fn push_tool_call(tool_calls: &mut Vec<ToolCall>, delta: &ToolCallDelta) {
+    while tool_calls.len() <= delta.index {
+        tool_calls.push(ToolCall {
+            id: String::new(),
+            r#type: ToolType::Function,
+            function: FunctionCall {
+                name: String::new(),
+                arguments: String::new(),
+            },
+        });
+    }
+    let call = &mut tool_calls[delta.index];
+    if let Some(id) = &delta.id {
+        call.id.push_str(id);
+    }
+    if let Some(r#type) = delta.r#type {
+        call.r#type = r#type;
+    }
+    if let Some(function) = &delta.function {
+        if let Some(name) = &function.name {
+            call.function.name.push_str(name);
+        }
+        if let Some(arguments) = &function.arguments {
+            call.function.arguments.push_str(arguments);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn chunk(choices: serde_json::Value) -> ChatCompletionChunk {
+        serde_json::from_value(json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion.chunk",
+            "created": 1700000000,
+            "model": "gpt-3.5-turbo-1106",
+            "choices": choices,
+        }))
+        .unwrap()
+    }
+
+    fn content(message: &ChatCompletionMessage) -> Option<&str> {
+        match message {
+            ChatCompletionMessage::Assistant(msg) => msg.content.as_deref(),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn accumulator_should_route_interleaved_choices_by_index() {
+        let mut acc = StreamAccumulator::new();
+        acc.push(&chunk(
+            json!([{"index": 1, "delta": {"role": "assistant", "content": "Good"}}]),
+        ));
+        acc.push(&chunk(
+            json!([{"index": 0, "delta": {"role": "assistant", "content": "Hello"}}]),
+        ));
+        acc.push(&chunk(
+            json!([{"index": 1, "delta": {"content": " morning"}}]),
+        ));
+        acc.push(&chunk(
+            json!([{"index": 0, "delta": {"content": " there"}}]),
+        ));
+        assert_eq!(acc.finish_reason(0), None);
+        acc.push(&chunk(
+            json!([{"index": 0, "delta": {}, "finish_reason": "length"}]),
+        ));
+        acc.push(&chunk(
+            json!([{"index": 1, "delta": {}, "finish_reason": "stop"}]),
+        ));
+        assert_eq!(acc.finish_reason(0), Some(FinishReason::Length));
+        assert_eq!(acc.finish_reason(1), Some(FinishReason::Stop));
+        let messages = acc.into_messages();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(content(&messages[0]), Some("Hello there"));
+        assert_eq!(content(&messages[1]), Some("Good morning"));
+    }
+
+    #[test]
+    fn accumulator_should_join_tool_call_arguments() {
+        let mut acc = StreamAccumulator::new();
+        acc.push(&chunk(json!([{"index": 0, "delta": {"tool_calls": [
+            {"index": 0, "id": "call_1", "type": "function", "function": {"name": "get_weather", "arguments": ""}}
+        ]}}])));
+        acc.push(&chunk(json!([{"index": 0, "delta": {"tool_calls": [
+            {"index": 0, "function": {"arguments": "{\"city\":"}}
+        ]}}])));
+        acc.push(&chunk(json!([{"index": 0, "delta": {"tool_calls": [
+            {"index": 0, "function": {"arguments": "\"Boston\"}"}}
+        ]}}])));
+        let messages = acc.into_messages();
+        let ChatCompletionMessage::Assistant(msg) = &messages[0] else {
+            panic!("expected assistant message");
+        };
+        assert_eq!(msg.tool_calls[0].id, "call_1");
+        assert_eq!(msg.tool_calls[0].function.name, "get_weather");
+        assert_eq!(msg.tool_calls[0].function.arguments, r#"{"city":"Boston"}"#);
+    }
+
+    #[test]
+    fn accumulator_should_collect_refusals_separately() {
+        let mut acc = StreamAccumulator::new();
+        acc.push(&chunk(
+            json!([{"index": 0, "delta": {"role": "assistant", "refusal": ""}}]),
+        ));
+        acc.push(&chunk(
+            json!([{"index": 0, "delta": {"refusal": "I'm sorry, "}}]),
+        ));
+        acc.push(&chunk(
+            json!([{"index": 0, "delta": {"refusal": "I can't help with that."}}]),
+        ));
+        acc.push(&chunk(json!([{"index": 1, "delta": {"content": "Sure"}}])));
+        assert!(acc.is_refusal(0));
+        assert_eq!(acc.refusal(0), Some("I'm sorry, I can't help with that."));
+        assert!(!acc.is_refusal(1));
+        let messages = acc.into_messages();
+        assert_eq!(content(&messages[0]), None);
+        assert_eq!(content(&messages[1]), Some("Sure"));
+    }
+
+    #[test]
+    fn accumulated_tool_calls_should_round_trip() {
+        let mut acc = StreamAccumulator::new();
+        acc.push(&chunk(json!([{"index": 0, "delta": {"tool_calls": [
+            {"index": 0, "id": "call_1", "type": "function", "function": {"name": "get_weather", "arguments": "{}"}}
+        ]}}])));
+        let messages = acc.into_messages();
+        let ChatCompletionMessage::Assistant(msg) = &messages[0] else {
+            panic!("expected assistant message");
+        };
+        let value = serde_json::to_value(&msg.tool_calls[0]).unwrap();
+        let expected = json!({
+            "id": "call_1",
+            "type": "function",
+            "function": {"name": "get_weather", "arguments": "{}"}
+        });
+        assert_eq!(value, expected);
+        let call: ToolCall = serde_json::from_value(value).unwrap();
+        assert_eq!(call.r#type, ToolType::Function);
+        assert_eq!(call.function().name, "get_weather");
+    }
+
+    #[tokio::test]
+    async fn collect_lossy_should_keep_partial_message_on_error() {
+        let chunks = vec![
+            Ok(chunk(
+                json!([{"index": 0, "delta": {"role": "assistant", "content": "Once upon"}}]),
+            )),
+            Ok(chunk(
+                json!([{"index": 0, "delta": {"content": " a time"}}]),
+            )),
+            Err(crate::LlmError::StreamInterrupted.into()),
+        ];
+        let partial = StreamAccumulator::collect_lossy(futures::stream::iter(chunks)).await;
+        assert!(!partial.is_complete());
+        assert_eq!(content(&partial.messages[0]), Some("Once upon a time"));
+    }
+}
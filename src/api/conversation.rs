@@ -0,0 +1,91 @@
+use crate::{ChatCompletionMessage, ChatCompletionResponse};
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// The messages of a multi-turn chat, to be saved and resumed later.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Conversation {
+    pub messages: Vec<ChatCompletionMessage>,
+}
+
+impl Conversation {
+    pub fn new(messages: impl Into<Vec<ChatCompletionMessage>>) -> Self {
+        Self {
+            messages: messages.into(),
+        }
+    }
+
+    pub fn push(&mut self, message: ChatCompletionMessage) {
+        self.messages.push(message);
+    }
+
+    /// Append the assistant message of the first choice of `res`, including its tool calls.
+    /// Answer the tool calls with `ChatCompletionMessage::new_tool` before the next request.
+    pub fn append_response(&mut self, res: &ChatCompletionResponse) -> Result<()> {
+        let choice = res
+            .choices
+            .first()
+            .ok_or_else(|| anyhow!("no choices returned in chat completion"))?;
+        self.messages
+            .push(ChatCompletionMessage::Assistant(choice.message.clone()));
+        Ok(())
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ContentPart;
+    use serde_json::json;
+
+    #[test]
+    fn conversation_should_round_trip_tool_calls() -> Result<()> {
+        let res: ChatCompletionResponse = serde_json::from_value(json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion",
+            "created": 1700000000,
+            "model": "gpt-3.5-turbo-1106",
+            "system_fingerprint": null,
+            "choices": [{
+                "index": 0,
+                "finish_reason": "tool_calls",
+                "message": {"role": "assistant", "content": null, "tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": {"name": "get_weather", "arguments": "{\"city\":\"Boston\"}"}
+                }]}
+            }],
+            "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2}
+        }))?;
+        let mut conversation = Conversation::new(vec![
+            ChatCompletionMessage::new_system("You are a weather bot", ""),
+            ChatCompletionMessage::new_user_with_parts(
+                vec![ContentPart::text("What's the weather in Boston?")],
+                "alice",
+            ),
+        ]);
+        conversation.append_response(&res)?;
+        conversation.push(ChatCompletionMessage::new_tool("sunny", "call_1"));
+
+        let json = conversation.to_json()?;
+        let restored = Conversation::from_json(&json)?;
+        assert_eq!(restored.messages.len(), 4);
+        assert_eq!(restored.to_json()?, json);
+        match &restored.messages[2] {
+            ChatCompletionMessage::Assistant(msg) => {
+                assert_eq!(msg.tool_calls[0].id, "call_1");
+                assert_eq!(msg.tool_calls[0].function.arguments, r#"{"city":"Boston"}"#);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use crate::IntoRequest;
+use crate::{EndpointKind, IntoRequest, LlmError};
 use derive_builder::Builder;
 use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
 use serde::{Deserialize, Serialize};
@@ -8,7 +8,7 @@ use serde::{Deserialize, Serialize};
 pub struct CreateImageRequest {
     /// A text description of the desired image(s). The maximum length is 4000 characters for dall-e-3.
     #[builder(setter(into))]
-    prompt: String,
+    pub(crate) prompt: String,
     /// The model to use for image generation. Only support Dall-e-3
     #[builder(default)]
     model: ImageModel,
@@ -24,7 +24,7 @@ pub struct CreateImageRequest {
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
     response_format: Option<ImageResponseFormat>,
-    /// The size of the generated images. Must be one of 1024x1024, 1792x1024, or 1024x1792 for dall-e-3 models.
+    /// The size of the generated images. Must be one of 1024x1024, 1792x1024, or 1024x1792 for dall-e-3 models. gpt-image-1 also accepts auto.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
     size: Option<ImageSize>,
@@ -38,19 +38,34 @@ pub struct CreateImageRequest {
     user: Option<String>,
 }
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
 pub enum ImageModel {
     #[serde(rename = "dall-e-3")]
     #[default]
     DallE3,
+    #[serde(rename = "gpt-image-1")]
+    GptImage1,
+    /// Any other model name, e.g. a newly released or fine-tuned model.
+    #[serde(untagged)]
+    Custom(String),
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ImageQuality {
+    /// dall-e-3 only.
     #[default]
     Standard,
+    /// dall-e-3 only.
     Hd,
+    /// gpt-image-1 only.
+    Low,
+    /// gpt-image-1 only.
+    Medium,
+    /// gpt-image-1 only.
+    High,
+    /// gpt-image-1 only, lets the model pick the quality.
+    Auto,
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
@@ -70,6 +85,9 @@ pub enum ImageSize {
     LargeWide,
     #[serde(rename = "1024x1792")]
     LargeTall,
+    /// Let the model choose the size. gpt-image-1 only.
+    #[serde(rename = "auto")]
+    Auto,
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
@@ -84,6 +102,28 @@ pub enum ImageStyle {
 pub struct CreateImageResponse {
     pub created: u64,
     pub data: Vec<ImageObject>,
+    /// Token usage for gpt-image-1, which is billed by tokens. DALL·E models don't report it.
+    #[serde(default)]
+    pub usage: Option<ImageUsage>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImageUsage {
+    /// Number of tokens in the prompt and input images.
+    pub input_tokens: usize,
+    /// Number of tokens of the generated images.
+    pub output_tokens: usize,
+    /// Total number of tokens used (input + output).
+    pub total_tokens: usize,
+    /// The split of `input_tokens` into text and image tokens.
+    #[serde(default)]
+    pub input_tokens_details: Option<ImageInputTokensDetails>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ImageInputTokensDetails {
+    pub text_tokens: usize,
+    pub image_tokens: usize,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -93,6 +133,7 @@ pub struct ImageObject {
     /// The URL of the generated image, if response_format is url (default).
     pub url: Option<String>,
     /// The prompt that was used to generate the image, if there was any revision to the prompt.
+    #[serde(default)]
     pub revised_prompt: String,
 }
 
@@ -101,6 +142,14 @@ impl IntoRequest for CreateImageRequest {
         let url = format!("{}/images/generations", base_url);
         client.post(url).json(&self)
     }
+
+    fn endpoint_kind(&self) -> Option<EndpointKind> {
+        Some(EndpointKind::Image)
+    }
+
+    fn set_default_user(&mut self, user: &str) {
+        self.user.get_or_insert_with(|| user.to_owned());
+    }
 }
 
 impl CreateImageRequest {
@@ -110,6 +159,54 @@ impl CreateImageRequest {
             .build()
             .unwrap()
     }
+
+    /// Check that the quality and size are supported by the model. Custom models are not
+    /// checked.
+    pub fn validate(&self) -> Result<(), LlmError> {
+        if let Some(size) = self.size {
+            size.check_model(Some(&self.model))?;
+        }
+        let Some(quality) = self.quality else {
+            return Ok(());
+        };
+        let supported = match self.model {
+            ImageModel::DallE3 => matches!(quality, ImageQuality::Standard | ImageQuality::Hd),
+            ImageModel::GptImage1 => matches!(
+                quality,
+                ImageQuality::Low | ImageQuality::Medium | ImageQuality::High | ImageQuality::Auto
+            ),
+            ImageModel::Custom(_) => true,
+        };
+        if !supported {
+            return Err(LlmError::InvalidRequest(format!(
+                "quality {:?} is not supported by {:?}",
+                quality, self.model
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl CreateImageResponse {
+    /// `created` as a date. Out of range timestamps give the Unix epoch.
+    #[cfg(feature = "chrono")]
+    pub fn created_at(&self) -> chrono::DateTime<chrono::Utc> {
+        chrono::DateTime::from_timestamp(self.created as i64, 0).unwrap_or_default()
+    }
+}
+
+impl ImageSize {
+    /// Check that the size is supported by `model`, `None` being the dall-e-2 default of the
+    /// edit endpoint. Custom models are not checked.
+    pub(crate) fn check_model(&self, model: Option<&ImageModel>) -> Result<(), LlmError> {
+        match (self, model) {
+            (ImageSize::Auto, Some(ImageModel::GptImage1 | ImageModel::Custom(_))) => Ok(()),
+            (ImageSize::Auto, _) => Err(LlmError::InvalidRequest(
+                "size auto is only supported by gpt-image-1".into(),
+            )),
+            _ => Ok(()),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -119,6 +216,87 @@ mod tests {
     use crate::SDK;
     use anyhow::Result;
 
+    #[test]
+    fn validate_should_match_quality_to_model() {
+        let req = |model: ImageModel, quality: ImageQuality| {
+            CreateImageRequestBuilder::default()
+                .prompt("a red fox")
+                .model(model)
+                .quality(quality)
+                .build()
+                .unwrap()
+        };
+        assert!(req(ImageModel::DallE3, ImageQuality::Hd).validate().is_ok());
+        assert!(req(ImageModel::GptImage1, ImageQuality::Low)
+            .validate()
+            .is_ok());
+        assert!(req(ImageModel::GptImage1, ImageQuality::Hd)
+            .validate()
+            .is_err());
+        assert!(req(ImageModel::DallE3, ImageQuality::Auto)
+            .validate()
+            .is_err());
+
+        let sized = |model: ImageModel| {
+            CreateImageRequestBuilder::default()
+                .prompt("a red fox")
+                .model(model)
+                .size(ImageSize::Auto)
+                .build()
+                .unwrap()
+        };
+        assert!(sized(ImageModel::GptImage1).validate().is_ok());
+        assert!(matches!(
+            sized(ImageModel::DallE3).validate(),
+            Err(LlmError::InvalidRequest(_))
+        ));
+        assert_eq!(
+            serde_json::to_value(sized(ImageModel::GptImage1)).unwrap()["size"],
+            "auto"
+        );
+        assert_eq!(
+            serde_json::to_value(req(ImageModel::GptImage1, ImageQuality::Medium)).unwrap()
+                ["quality"],
+            "medium"
+        );
+    }
+
+    #[test]
+    fn response_should_parse_optional_usage() {
+        let res: CreateImageResponse = serde_json::from_value(serde_json::json!({
+            "created": 1713833628,
+            "data": [{"b64_json": "aGVsbG8="}],
+            "usage": {
+                "input_tokens": 50,
+                "output_tokens": 4160,
+                "total_tokens": 4210,
+                "input_tokens_details": {"text_tokens": 10, "image_tokens": 40}
+            }
+        }))
+        .unwrap();
+        let usage = res.usage.unwrap();
+        assert_eq!(usage.total_tokens, 4210);
+        assert_eq!(usage.input_tokens_details.unwrap().image_tokens, 40);
+
+        let res: CreateImageResponse = serde_json::from_value(serde_json::json!({
+            "created": 1713833628,
+            "data": [{"url": "https://example.com/image.png", "revised_prompt": "a fox"}]
+        }))
+        .unwrap();
+        assert!(res.usage.is_none());
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn created_at_should_convert_the_timestamp() {
+        let res: CreateImageResponse = serde_json::from_value(serde_json::json!({
+            "created": 1713833628,
+            "data": []
+        }))
+        .unwrap();
+        assert_eq!(res.created_at().to_rfc3339(), "2024-04-23T00:53:48+00:00");
+    }
+
     #[tokio::test]
     async fn test_create_image_request() -> Result<()> {
         let req = CreateImageRequestBuilder::default()
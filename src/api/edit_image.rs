@@ -0,0 +1,172 @@
+use crate::{EndpointKind, ImageModel, ImageResponseFormat, ImageSize, IntoRequest, LlmError};
+use bytes::Bytes;
+use derive_builder::Builder;
+use reqwest::multipart::{Form, Part};
+use reqwest::Body;
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
+use serde::Serialize;
+use std::fmt;
+
+/// Edit or extend an image, answered with a `CreateImageResponse`.
+#[derive(Clone, Builder)]
+#[builder(pattern = "mutable")]
+pub struct EditImageRequest {
+    /// The image to edit. Must be a valid PNG file, less than 4MB, and square. If mask is not provided, image must have transparency, which will be used as the mask.
+    image: Vec<u8>,
+    /// An additional image whose fully transparent areas (e.g. where alpha is zero) indicate where image should be edited. Must be a valid PNG file, less than 4MB, and have the same dimensions as image.
+    #[builder(default, setter(strip_option))]
+    mask: Option<Vec<u8>>,
+    /// A text description of the desired image(s). The maximum length is 1000 characters.
+    #[builder(setter(into))]
+    prompt: String,
+    /// The model to use for image editing. Defaults to dall-e-2, as dall-e-3 doesn't support edits.
+    #[builder(default, setter(strip_option))]
+    model: Option<ImageModel>,
+    /// The number of images to generate. Must be between 1 and 10.
+    #[builder(default, setter(strip_option))]
+    n: Option<usize>,
+    /// The size of the generated images.
+    #[builder(default, setter(strip_option))]
+    size: Option<ImageSize>,
+    /// The format in which the generated images are returned. Must be one of url or b64_json.
+    #[builder(default, setter(strip_option))]
+    response_format: Option<ImageResponseFormat>,
+    /// A unique identifier representing your end-user, which can help OpenAI to monitor and detect abuse.
+    #[builder(default, setter(strip_option, into))]
+    user: Option<String>,
+}
+
+impl EditImageRequest {
+    pub fn new(image: Vec<u8>, mask: Vec<u8>, prompt: impl Into<String>) -> Self {
+        EditImageRequestBuilder::default()
+            .image(image)
+            .mask(mask)
+            .prompt(prompt)
+            .build()
+            .unwrap()
+    }
+
+    /// Check that the size is supported by the model and that the options can be sent as form
+    /// fields.
+    pub fn validate(&self) -> Result<(), LlmError> {
+        if let Some(size) = &self.size {
+            size.check_model(self.model.as_ref())?;
+            form_value(size)?;
+        }
+        if let Some(model) = &self.model {
+            form_value(model)?;
+        }
+        if let Some(response_format) = &self.response_format {
+            form_value(response_format)?;
+        }
+        Ok(())
+    }
+
+    pub fn into_form(self) -> Result<Form, LlmError> {
+        let png = |bytes: Vec<u8>, name: &'static str| {
+            Part::bytes(bytes)
+                .file_name(name)
+                .mime_str("image/png")
+                .unwrap()
+        };
+        let mut form = Form::new()
+            .part("image", png(self.image, "image.png"))
+            .text("prompt", self.prompt);
+        if let Some(mask) = self.mask {
+            form = form.part("mask", png(mask, "mask.png"));
+        }
+        if let Some(model) = &self.model {
+            form = form.text("model", form_value(model)?);
+        }
+        if let Some(n) = self.n {
+            form = form.text("n", n.to_string());
+        }
+        if let Some(size) = &self.size {
+            form = form.text("size", form_value(size)?);
+        }
+        if let Some(response_format) = &self.response_format {
+            form = form.text("response_format", form_value(response_format)?);
+        }
+        if let Some(user) = self.user {
+            form = form.text("user", user);
+        }
+        Ok(form)
+    }
+}
+
+/// The serialized name of a unit variant, e.g. `1024x1024` for `ImageSize::Large`.
+fn form_value(value: &impl Serialize) -> Result<String, LlmError> {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(s)) => Ok(s),
+        other => Err(LlmError::InvalidRequest(format!(
+            "expected a string form value, got {:?}",
+            other
+        ))),
+    }
+}
+
+impl fmt::Debug for EditImageRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EditImageRequest")
+            .field("image", &format_args!("[<{} bytes>]", self.image.len()))
+            .field(
+                "mask",
+                &self
+                    .mask
+                    .as_ref()
+                    .map(|mask| format!("[<{} bytes>]", mask.len())),
+            )
+            .field("prompt", &self.prompt)
+            .field("model", &self.model)
+            .field("n", &self.n)
+            .field("size", &self.size)
+            .field("response_format", &self.response_format)
+            .field("user", &self.user)
+            .finish()
+    }
+}
+
+impl IntoRequest for EditImageRequest {
+    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        let url = format!("{}/images/edits", base_url);
+        match self.into_form() {
+            Ok(form) => client.post(url).multipart(form),
+            // `LlmSDK::edit_image` validates the form values first, fail the upload rather than
+            // send a form without them
+            Err(e) => client
+                .post(url)
+                .body(Body::wrap_stream(futures::stream::once(async {
+                    Err::<Bytes, _>(e)
+                }))),
+        }
+    }
+
+    fn endpoint_kind(&self) -> Option<EndpointKind> {
+        Some(EndpointKind::Image)
+    }
+
+    fn set_default_user(&mut self, user: &str) {
+        self.user.get_or_insert_with(|| user.to_owned());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn form_value_should_use_serialized_names() {
+        assert_eq!(form_value(&ImageSize::Large).unwrap(), "1024x1024");
+        assert_eq!(
+            form_value(&ImageResponseFormat::B64Json).unwrap(),
+            "b64_json"
+        );
+        assert_eq!(
+            form_value(&ImageModel::Custom("dall-e-2".into())).unwrap(),
+            "dall-e-2"
+        );
+        assert!(matches!(form_value(&3), Err(LlmError::InvalidRequest(_))));
+        let req = EditImageRequest::new(vec![0; 10], vec![0; 5], "add a hat");
+        assert!(format!("{:?}", req).contains("image: [<10 bytes>]"));
+    }
+}
@@ -1,7 +1,10 @@
-use crate::IntoRequest;
+#[cfg(feature = "tokenizer")]
+use crate::tokenizer::Tokenizer;
+use crate::{EndpointKind, IntoRequest, LlmError};
+use base64::{engine::general_purpose::STANDARD, Engine};
 use derive_builder::Builder;
 use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
 
 #[derive(Debug, Clone, Serialize, Builder)]
 #[builder(pattern = "mutable")]
@@ -14,11 +17,21 @@ pub struct EmbeddingRequest {
     /// The format to return the embeddings in. Can be either float or base64.
     #[builder(default, setter(strip_option))]
     #[serde(skip_serializing_if = "Option::is_none")]
-    encoding_format: Option<EmbeddingEncodingFormat>,
+    pub(crate) encoding_format: Option<EmbeddingEncodingFormat>,
+    /// The number of dimensions the resulting output embeddings should have. Only supported in text-embedding-3 and later models.
+    #[builder(default, setter(strip_option))]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dimensions: Option<usize>,
     /// A unique identifier representing your end-user, which can help OpenAI to monitor and detect abuse. Learn more.
     #[builder(default, setter(strip_option, into))]
     #[serde(skip_serializing_if = "Option::is_none")]
     user: Option<String>,
+    /// Truncate inputs to the token limit of the model before sending, see
+    /// `EmbeddingRequestBuilder::auto_truncate`.
+    #[builder(default, setter(custom))]
+    #[serde(skip)]
+    #[cfg_attr(not(feature = "tokenizer"), allow(dead_code))]
+    auto_truncate: bool,
 }
 
 // currently we don't support array of integers, or array of array of integers
@@ -29,11 +42,18 @@ pub enum EmbeddingInput {
     StringArray(Vec<String>),
 }
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub enum EmbeddingModel {
     #[default]
     #[serde(rename = "text-embedding-ada-002")]
     TextEmbeddingAda002,
+    #[serde(rename = "text-embedding-3-small")]
+    TextEmbedding3Small,
+    #[serde(rename = "text-embedding-3-large")]
+    TextEmbedding3Large,
+    /// Any other model name, e.g. a newly released or fine-tuned model.
+    #[serde(untagged)]
+    Custom(String),
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
@@ -63,19 +83,145 @@ pub struct EmbeddingData {
     /// The index of the embedding in the list of embeddings.
     pub index: usize,
     /// The embedding vector, which is a list of floats. The length of vector depends on the model as listed in the embedding guide.
+    /// Base64 encoded vectors are decoded, so this works with either encoding format.
+    #[serde(deserialize_with = "float_or_base64")]
     pub embedding: Vec<f32>,
     /// The object type, which is always "embedding".
     pub object: String,
 }
 
+/// An `EmbeddingResponse` with the vectors kept in double precision. The API sends them as
+/// JSON numbers, which `f32` would round.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmbeddingResponseF64 {
+    pub object: String,
+    pub data: Vec<EmbeddingDataF64>,
+    pub model: String,
+    pub usage: EmbeddingUsage,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmbeddingDataF64 {
+    /// The index of the embedding in the list of embeddings.
+    pub index: usize,
+    pub embedding: Vec<f64>,
+    /// The object type, which is always "embedding".
+    pub object: String,
+}
+
+/// The response for `EmbeddingEncodingFormat::Base64`, which is less than half the size of
+/// float JSON.
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmbeddingBase64Response {
+    pub object: String,
+    pub data: Vec<EmbeddingBase64Data>,
+    pub model: String,
+    pub usage: EmbeddingUsage,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmbeddingBase64Data {
+    /// The index of the embedding in the list of embeddings.
+    pub index: usize,
+    /// The embedding as base64 encoded little-endian f32 values, see `decode`.
+    pub embedding: String,
+    /// The object type, which is always "embedding".
+    pub object: String,
+}
+
+impl EmbeddingBase64Data {
+    pub fn decode(&self) -> anyhow::Result<Vec<f32>> {
+        decode_base64(&self.embedding)
+    }
+}
+
+fn decode_base64(embedding: &str) -> anyhow::Result<Vec<f32>> {
+    let bytes = STANDARD.decode(embedding)?;
+    if bytes.len() % 4 != 0 {
+        anyhow::bail!("embedding is {} bytes, not a multiple of 4", bytes.len());
+    }
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect())
+}
+
+/// Deserialize an embedding sent as a list of floats or as base64.
+fn float_or_base64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<f32>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Embedding {
+        Float(Vec<f32>),
+        Base64(String),
+    }
+    match Embedding::deserialize(deserializer)? {
+        Embedding::Float(embedding) => Ok(embedding),
+        Embedding::Base64(embedding) => decode_base64(&embedding).map_err(serde::de::Error::custom),
+    }
+}
+
 impl IntoRequest for EmbeddingRequest {
     fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        #[cfg(feature = "tokenizer")]
+        let body = self.auto_truncated();
+        #[cfg(not(feature = "tokenizer"))]
+        let body = self;
         let url = format!("{}/embeddings", base_url);
-        client.post(url).json(&self)
+        client.post(url).json(&body)
+    }
+
+    fn endpoint_kind(&self) -> Option<EndpointKind> {
+        Some(EndpointKind::Embedding)
+    }
+
+    fn set_default_user(&mut self, user: &str) {
+        self.user.get_or_insert_with(|| user.to_owned());
+    }
+
+    fn set_default_embedding_format(&mut self, format: EmbeddingEncodingFormat) {
+        self.encoding_format.get_or_insert(format);
+    }
+}
+
+#[cfg(feature = "tokenizer")]
+impl EmbeddingRequestBuilder {
+    /// Truncate each input to the token limit of the model when sending. Truncation silently
+    /// drops the end of longer inputs, so their embeddings only represent the beginning.
+    pub fn auto_truncate(&mut self, auto_truncate: bool) -> &mut Self {
+        self.auto_truncate = Some(auto_truncate);
+        self
     }
 }
 
 impl EmbeddingRequest {
+    /// Cut each input to at most `max_tokens` tokens, so one oversized chunk doesn't fail the
+    /// whole batch. The end of longer inputs is silently dropped. Returns the number of inputs
+    /// that were truncated.
+    #[cfg(feature = "tokenizer")]
+    pub fn truncate(&mut self, max_tokens: usize) -> usize {
+        let tokenizer = Tokenizer::for_embeddings();
+        let mut truncated = 0;
+        let inputs = match &mut self.input {
+            EmbeddingInput::String(input) => std::slice::from_mut(input),
+            EmbeddingInput::StringArray(inputs) => inputs.as_mut_slice(),
+        };
+        for input in inputs {
+            if let Some(prefix) = tokenizer.truncate(input, max_tokens) {
+                *input = prefix;
+                truncated += 1;
+            }
+        }
+        truncated
+    }
+
+    #[cfg(feature = "tokenizer")]
+    fn auto_truncated(mut self) -> Self {
+        if let (true, Some(max_tokens)) = (self.auto_truncate, self.model.max_input_tokens()) {
+            self.truncate(max_tokens);
+        }
+        self
+    }
+
     pub fn new(input: impl Into<EmbeddingInput>) -> Self {
         EmbeddingRequestBuilder::default()
             .input(input.into())
@@ -89,6 +235,125 @@ impl EmbeddingRequest {
             .build()
             .unwrap()
     }
+
+    pub fn new_with_model(input: impl Into<EmbeddingInput>, model: EmbeddingModel) -> Self {
+        EmbeddingRequestBuilder::default()
+            .input(input.into())
+            .model(model)
+            .build()
+            .unwrap()
+    }
+
+    pub fn new_array_with_model(input: Vec<String>, model: EmbeddingModel) -> Self {
+        Self::new_with_model(input, model)
+    }
+
+    /// A request using `text-embedding-3-small`.
+    pub fn new_small(input: impl Into<EmbeddingInput>) -> Self {
+        Self::new_with_model(input, EmbeddingModel::TextEmbedding3Small)
+    }
+
+    /// A request using `text-embedding-3-large`.
+    pub fn new_large(input: impl Into<EmbeddingInput>) -> Self {
+        Self::new_with_model(input, EmbeddingModel::TextEmbedding3Large)
+    }
+
+    /// Check `dimensions` against the model, as the API rejects values it can't produce.
+    /// Custom models are not checked.
+    pub fn validate(&self) -> Result<(), LlmError> {
+        let Some(dimensions) = self.dimensions else {
+            return Ok(());
+        };
+        let max = match &self.model {
+            EmbeddingModel::TextEmbeddingAda002 => {
+                return Err(LlmError::InvalidRequest(
+                    "text-embedding-ada-002 does not support dimensions".into(),
+                ))
+            }
+            EmbeddingModel::Custom(_) => return Ok(()),
+            model => model.max_dimensions().unwrap_or(usize::MAX),
+        };
+        if dimensions == 0 || dimensions > max {
+            return Err(LlmError::InvalidRequest(format!(
+                "dimensions must be between 1 and {}, got {}",
+                max, dimensions
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl EmbeddingModel {
+    /// The maximum number of tokens of each input, if known.
+    pub fn max_input_tokens(&self) -> Option<usize> {
+        match self {
+            EmbeddingModel::Custom(_) => None,
+            _ => Some(8191),
+        }
+    }
+
+    /// The native number of dimensions of the model, if known.
+    pub fn max_dimensions(&self) -> Option<usize> {
+        match self {
+            EmbeddingModel::TextEmbeddingAda002 | EmbeddingModel::TextEmbedding3Small => Some(1536),
+            EmbeddingModel::TextEmbedding3Large => Some(3072),
+            EmbeddingModel::Custom(_) => None,
+        }
+    }
+}
+
+impl EmbeddingData {
+    /// The embedding scaled to unit length, so that the dot product of two normalized
+    /// embeddings is their cosine similarity. A zero vector is returned unchanged.
+    pub fn normalized(&self) -> Vec<f32> {
+        let mut embedding = self.embedding.clone();
+        normalize(&mut embedding);
+        embedding
+    }
+}
+
+impl EmbeddingResponse {
+    /// Scale every embedding to unit length, see `EmbeddingData::normalized`.
+    pub fn normalize_all(&mut self) {
+        for data in &mut self.data {
+            normalize(&mut data.embedding);
+        }
+    }
+
+    /// The embedding vectors in input order.
+    pub fn into_vectors(mut self) -> Vec<Vec<f32>> {
+        self.data.sort_by_key(|data| data.index);
+        self.data.into_iter().map(|data| data.embedding).collect()
+    }
+
+    /// The embedding vector of a single input.
+    pub fn into_single(self) -> Option<Vec<f32>> {
+        self.into_vectors().into_iter().next()
+    }
+
+    /// The embeddings as a matrix with one row per input, in input order.
+    #[cfg(feature = "ndarray")]
+    pub fn to_array2(self) -> anyhow::Result<ndarray::Array2<f32>> {
+        let vectors = self.into_vectors();
+        let dim = vectors.first().map_or(0, Vec::len);
+        if let Some(v) = vectors.iter().find(|v| v.len() != dim) {
+            anyhow::bail!(
+                "embeddings have different dimensions: {} and {}",
+                dim,
+                v.len()
+            );
+        }
+        let rows = vectors.len();
+        let data = vectors.into_iter().flatten().collect();
+        Ok(ndarray::Array2::from_shape_vec((rows, dim), data)?)
+    }
+}
+
+fn normalize(v: &mut [f32]) {
+    let norm = v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        v.iter_mut().for_each(|x| *x /= norm);
+    }
 }
 
 impl From<String> for EmbeddingInput {
@@ -117,8 +382,14 @@ impl From<&[String]> for EmbeddingInput {
 
 #[cfg(test)]
 mod test {
-    use crate::{EmbeddingRequest, SDK};
+    use crate::{
+        EmbeddingBase64Data, EmbeddingEncodingFormat, EmbeddingModel, EmbeddingRequest,
+        EmbeddingRequestBuilder, EmbeddingResponse, EmbeddingResponseF64, IntoRequest, LlmError,
+        SDK,
+    };
     use anyhow::Result;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use serde_json::json;
 
     #[tokio::test]
     async fn test() -> Result<()> {
@@ -126,4 +397,192 @@ mod test {
         let _res = SDK.embedding(req).await?;
         Ok(())
     }
+
+    fn request(model: EmbeddingModel, dimensions: usize) -> EmbeddingRequest {
+        EmbeddingRequestBuilder::default()
+            .input("hello".into())
+            .model(model)
+            .dimensions(dimensions)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn validate_should_reject_unsupported_dimensions() {
+        assert!(request(EmbeddingModel::TextEmbedding3Small, 512)
+            .validate()
+            .is_ok());
+        assert!(request(EmbeddingModel::TextEmbedding3Large, 3072)
+            .validate()
+            .is_ok());
+        assert!(matches!(
+            request(EmbeddingModel::TextEmbedding3Small, 3072).validate(),
+            Err(LlmError::InvalidRequest(_))
+        ));
+        assert!(matches!(
+            request(EmbeddingModel::TextEmbeddingAda002, 256).validate(),
+            Err(LlmError::InvalidRequest(_))
+        ));
+        assert!(EmbeddingRequest::new("hello").validate().is_ok());
+    }
+
+    #[cfg(feature = "tokenizer")]
+    #[test]
+    fn auto_truncate_should_cut_long_inputs() {
+        let long = "word ".repeat(10_000);
+        let mut req = EmbeddingRequestBuilder::default()
+            .input(vec![long.clone(), "short".to_string()].into())
+            .auto_truncate(true)
+            .build()
+            .unwrap();
+        assert_eq!(req.clone().auto_truncated().truncate(8191), 0);
+        assert_eq!(req.truncate(8191), 1);
+        let json = serde_json::to_value(&req).unwrap();
+        assert!(json["input"][0].as_str().unwrap().len() < long.len());
+        assert_eq!(json["input"][1], "short");
+        assert!(json.get("auto_truncate").is_none());
+    }
+
+    #[test]
+    fn model_constructors_should_set_model() {
+        let req = EmbeddingRequest::new_small("hello");
+        assert_eq!(
+            serde_json::to_value(&req).unwrap(),
+            json!({"input": "hello", "model": "text-embedding-3-small"})
+        );
+        let req = EmbeddingRequest::new_array_with_model(
+            vec!["a".into(), "b".into()],
+            EmbeddingModel::TextEmbedding3Large,
+        );
+        assert_eq!(
+            serde_json::to_value(&req).unwrap(),
+            json!({"input": ["a", "b"], "model": "text-embedding-3-large"})
+        );
+    }
+
+    #[test]
+    fn into_vectors_should_sort_by_index() {
+        let res: EmbeddingResponse = serde_json::from_value(json!({
+            "object": "list",
+            "model": "text-embedding-ada-002",
+            "usage": {"prompt_tokens": 3, "total_tokens": 3},
+            "data": [
+                {"object": "embedding", "index": 2, "embedding": [2.0]},
+                {"object": "embedding", "index": 0, "embedding": [0.0]},
+                {"object": "embedding", "index": 1, "embedding": [1.0]},
+            ],
+        }))
+        .unwrap();
+        assert_eq!(
+            res.clone().into_vectors(),
+            vec![vec![0.0], vec![1.0], vec![2.0]]
+        );
+        assert_eq!(res.into_single(), Some(vec![0.0]));
+    }
+
+    #[test]
+    fn f64_response_should_keep_precision() {
+        let body = r#"{"object":"list","data":[{"object":"embedding","index":0,"embedding":[0.123456789012345]}],"model":"text-embedding-3-small","usage":{"prompt_tokens":1,"total_tokens":1}}"#;
+        let res: EmbeddingResponseF64 = serde_json::from_str(body).unwrap();
+        assert_eq!(res.data[0].embedding[0], 0.123456789012345);
+        let res: EmbeddingResponse = serde_json::from_str(body).unwrap();
+        assert_ne!(res.data[0].embedding[0] as f64, 0.123456789012345);
+    }
+
+    #[test]
+    fn base64_embedding_should_decode_and_be_smaller() {
+        // a typical text-embedding-3-small vector
+        let embedding: Vec<f32> = (0..1536).map(|i| (i as f32 * 0.37).sin() * 0.05).collect();
+        let bytes: Vec<u8> = embedding.iter().flat_map(|x| x.to_le_bytes()).collect();
+        let data = EmbeddingBase64Data {
+            index: 0,
+            embedding: STANDARD.encode(&bytes),
+            object: "embedding".into(),
+        };
+        assert_eq!(data.decode().unwrap(), embedding);
+
+        let float_json = serde_json::to_string(&embedding).unwrap();
+        let ratio = data.embedding.len() as f32 / float_json.len() as f32;
+        assert!(ratio < 0.5, "base64 is {:.0}% of float JSON", ratio * 100.0);
+
+        let bad = EmbeddingBase64Data {
+            embedding: STANDARD.encode([0u8; 3]),
+            ..data
+        };
+        assert!(bad.decode().is_err());
+
+        let res: EmbeddingResponse = serde_json::from_value(json!({
+            "object": "list",
+            "data": [{"object": "embedding", "index": 0, "embedding": STANDARD.encode(&bytes)}],
+            "model": "text-embedding-3-small",
+            "usage": {"prompt_tokens": 2, "total_tokens": 2}
+        }))
+        .unwrap();
+        assert_eq!(res.into_single().unwrap(), embedding);
+    }
+
+    #[test]
+    fn default_embedding_format_should_apply_when_unset() {
+        let mut req = EmbeddingRequest::new("hello");
+        req.set_default_embedding_format(EmbeddingEncodingFormat::Base64);
+        assert_eq!(
+            serde_json::to_value(&req).unwrap()["encoding_format"],
+            "base64"
+        );
+
+        let mut req = EmbeddingRequestBuilder::default()
+            .input("hello".into())
+            .encoding_format(EmbeddingEncodingFormat::Float)
+            .build()
+            .unwrap();
+        req.set_default_embedding_format(EmbeddingEncodingFormat::Base64);
+        assert_eq!(
+            serde_json::to_value(&req).unwrap()["encoding_format"],
+            "float"
+        );
+    }
+
+    #[test]
+    fn normalize_all_should_scale_to_unit_length() {
+        let mut res: EmbeddingResponse = serde_json::from_value(json!({
+            "object": "list",
+            "data": [
+                {"object": "embedding", "index": 0, "embedding": [3.0, 4.0]},
+                {"object": "embedding", "index": 1, "embedding": [0.0, 0.0]}
+            ],
+            "model": "text-embedding-3-small",
+            "usage": {"prompt_tokens": 2, "total_tokens": 2}
+        }))
+        .unwrap();
+        assert_eq!(res.data[0].normalized(), vec![0.6, 0.8]);
+        res.normalize_all();
+        assert_eq!(res.into_vectors(), vec![vec![0.6, 0.8], vec![0.0, 0.0]]);
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn to_array2_should_stack_embeddings_in_input_order() {
+        let res = |data: serde_json::Value| -> EmbeddingResponse {
+            serde_json::from_value(json!({
+                "object": "list",
+                "data": data,
+                "model": "text-embedding-3-small",
+                "usage": {"prompt_tokens": 2, "total_tokens": 2}
+            }))
+            .unwrap()
+        };
+        let matrix = res(json!([
+            {"object": "embedding", "index": 1, "embedding": [3.0, 4.0]},
+            {"object": "embedding", "index": 0, "embedding": [1.0, 2.0]}
+        ]))
+        .to_array2()
+        .unwrap();
+        assert_eq!(matrix, ndarray::array![[1.0, 2.0], [3.0, 4.0]]);
+
+        let ragged = res(json!([
+            {"object": "embedding", "index": 0, "embedding": [1.0, 2.0]},
+            {"object": "embedding", "index": 1, "embedding": [3.0]}
+        ]));
+        assert!(ragged.to_array2().is_err());
+    }
 }
@@ -0,0 +1,158 @@
+use crate::{EndpointKind, ImageInput, IntoRequest};
+use derive_builder::Builder;
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
+use serde::Serialize;
+
+/// Embed an image with a CLIP-compatible model, see `LlmSDK::embed_image`.
+#[derive(Debug, Clone, Serialize, Builder)]
+#[builder(pattern = "mutable")]
+pub struct ImageEmbeddingRequest {
+    /// The image to embed, sent as a URL or a base64 data URL.
+    #[serde(rename = "input")]
+    image: ImageInput,
+    #[builder(default)]
+    model: ImageEmbeddingModel,
+    /// The path of the embedding endpoint relative to the base url.
+    #[builder(default = r#""embeddings".into()"#, setter(into))]
+    #[serde(skip)]
+    endpoint: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub enum ImageEmbeddingModel {
+    #[default]
+    #[serde(rename = "openai/clip-vit-large-patch14")]
+    ClipVitL14,
+    /// Any other model name, as listed by the provider.
+    #[serde(untagged)]
+    Custom(String),
+}
+
+/// Embeds text with the model and endpoint of an `ImageEmbeddingRequest`, so the text and image
+/// vectors are comparable, see `LlmSDK::multimodal_similarity`.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ClipTextRequest {
+    input: String,
+    model: ImageEmbeddingModel,
+    #[serde(skip)]
+    endpoint: String,
+}
+
+impl ImageEmbeddingRequest {
+    pub fn new(image: ImageInput) -> Self {
+        ImageEmbeddingRequestBuilder::default()
+            .image(image)
+            .build()
+            .unwrap()
+    }
+
+    /// A request for `text` to the same model and endpoint.
+    pub(crate) fn text_request(&self, text: impl Into<String>) -> ClipTextRequest {
+        ClipTextRequest {
+            input: text.into(),
+            model: self.model.clone(),
+            endpoint: self.endpoint.clone(),
+        }
+    }
+}
+
+impl From<ImageInput> for ImageEmbeddingRequest {
+    fn from(image: ImageInput) -> Self {
+        Self::new(image)
+    }
+}
+
+impl ImageEmbeddingModel {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ImageEmbeddingModel::ClipVitL14 => "openai/clip-vit-large-patch14",
+            ImageEmbeddingModel::Custom(name) => name,
+        }
+    }
+}
+
+impl IntoRequest for ImageEmbeddingRequest {
+    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        let url = format!("{}/{}", base_url, self.endpoint.trim_start_matches('/'));
+        client.post(url).json(&self)
+    }
+
+    fn endpoint_kind(&self) -> Option<EndpointKind> {
+        Some(EndpointKind::Embedding)
+    }
+}
+
+impl IntoRequest for ClipTextRequest {
+    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        let url = format!("{}/{}", base_url, self.endpoint.trim_start_matches('/'));
+        client.post(url).json(&self)
+    }
+
+    fn endpoint_kind(&self) -> Option<EndpointKind> {
+        Some(EndpointKind::Embedding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_server, LlmSDK};
+    use std::time::Duration;
+
+    fn embedding(vector: [f32; 2]) -> String {
+        serde_json::json!({
+            "object": "list",
+            "data": [{"index": 0, "embedding": vector, "object": "embedding"}],
+            "model": "openai/clip-vit-large-patch14",
+            "usage": {"prompt_tokens": 1, "total_tokens": 1}
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn embed_image_should_use_the_configured_endpoint() -> anyhow::Result<()> {
+        let url = test_server::serve_routes(vec![(
+            "/v1/clip",
+            Duration::ZERO,
+            200,
+            embedding([0.6, 0.8]),
+        )]);
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let req = ImageEmbeddingRequestBuilder::default()
+            .image(ImageInput::url("https://example.com/cat.png"))
+            .endpoint("/v1/clip")
+            .build()?;
+        assert_eq!(sdk.embed_image(req).await?, vec![0.6, 0.8]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn multimodal_similarity_should_embed_both_with_the_request_endpoint(
+    ) -> anyhow::Result<()> {
+        // the text and image requests are sent one after the other, so both match one route
+        let url = test_server::serve_routes(vec![
+            ("/v1/clip", Duration::ZERO, 200, embedding([0.6, 0.8])),
+            ("/v1/clip", Duration::ZERO, 200, embedding([0.6, 0.8])),
+        ]);
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let req = ImageEmbeddingRequestBuilder::default()
+            .image(ImageInput::url("https://example.com/cat.png"))
+            .endpoint("/v1/clip")
+            .build()?;
+        let similarity = sdk.multimodal_similarity("a cat", req).await?;
+        assert!((similarity - 1.0).abs() < 1e-6);
+        Ok(())
+    }
+
+    #[test]
+    fn request_should_send_image_as_data_url() {
+        let req = ImageEmbeddingRequest::new(ImageInput::bytes(b"png".to_vec(), "image/png"));
+        assert_eq!(
+            serde_json::to_value(&req).unwrap(),
+            serde_json::json!({
+                "input": "data:image/png;base64,cG5n",
+                "model": "openai/clip-vit-large-patch14"
+            })
+        );
+    }
+}
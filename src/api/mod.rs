@@ -1,11 +1,29 @@
 mod chat_completion;
+mod chat_completion_stream;
+mod conversation;
 mod create_image;
+mod edit_image;
 mod embedding;
+#[cfg(feature = "multimodal-embeddings")]
+mod image_embedding;
+mod models;
+mod moderation;
+mod response;
+mod run;
 mod speech;
 mod whisper;
 
 pub use chat_completion::*;
+pub use chat_completion_stream::*;
+pub use conversation::*;
 pub use create_image::*;
+pub use edit_image::*;
 pub use embedding::*;
+#[cfg(feature = "multimodal-embeddings")]
+pub use image_embedding::*;
+pub use models::*;
+pub use moderation::*;
+pub use response::*;
+pub use run::*;
 pub use speech::*;
 pub use whisper::*;
@@ -0,0 +1,41 @@
+use crate::IntoRequest;
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
+use serde::Deserialize;
+
+/// List the models available to the API key.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ListModelsRequest;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModelList {
+    /// The object type, which is always "list".
+    pub object: String,
+    pub data: Vec<Model>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Model {
+    /// The model identifier, which can be referenced in the API endpoints.
+    pub id: String,
+    /// The object type, which is always "model".
+    pub object: String,
+    /// The Unix timestamp (in seconds) when the model was created.
+    #[serde(default)]
+    pub created: u64,
+    /// The organization that owns the model.
+    #[serde(default)]
+    pub owned_by: String,
+}
+
+impl ModelList {
+    pub fn contains(&self, id: &str) -> bool {
+        self.data.iter().any(|model| model.id == id)
+    }
+}
+
+impl IntoRequest for ListModelsRequest {
+    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        let url = format!("{}/models", base_url);
+        client.get(url)
+    }
+}
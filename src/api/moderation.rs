@@ -0,0 +1,126 @@
+use crate::{EndpointKind, IntoRequest};
+use derive_builder::Builder;
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Serialize, Builder)]
+#[builder(pattern = "mutable")]
+pub struct ModerationRequest {
+    /// The text to classify.
+    #[builder(setter(into))]
+    input: String,
+    /// The moderation model to use: omni-moderation-latest or text-moderation-latest.
+    #[builder(default)]
+    model: ModerationModel,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub enum ModerationModel {
+    #[default]
+    #[serde(rename = "omni-moderation-latest")]
+    OmniModerationLatest,
+    #[serde(rename = "text-moderation-latest")]
+    TextModerationLatest,
+    /// Any other model name, e.g. a pinned snapshot.
+    #[serde(untagged)]
+    Custom(String),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModerationResponse {
+    /// The unique identifier for the moderation request.
+    pub id: String,
+    /// The model used to generate the moderation results.
+    pub model: String,
+    /// A list of moderation objects, one per input.
+    pub results: Vec<ModerationResult>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct ModerationResult {
+    /// Whether any of the categories are flagged.
+    pub flagged: bool,
+    /// Whether each category, e.g. `harassment` or `violence/graphic`, is flagged.
+    pub categories: HashMap<String, bool>,
+    /// The score of each category, between 0 and 1.
+    pub category_scores: HashMap<String, f32>,
+}
+
+impl IntoRequest for ModerationRequest {
+    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        let url = format!("{}/moderations", base_url);
+        client.post(url).json(&self)
+    }
+
+    fn endpoint_kind(&self) -> Option<EndpointKind> {
+        Some(EndpointKind::Moderation)
+    }
+}
+
+impl ModerationRequest {
+    pub fn new(input: impl Into<String>) -> Self {
+        ModerationRequestBuilder::default()
+            .input(input)
+            .build()
+            .unwrap()
+    }
+}
+
+impl ModerationResult {
+    /// Combine the results of two moderations: flagged if either is, with the highest score of
+    /// each category.
+    pub fn merge(mut self, other: ModerationResult) -> ModerationResult {
+        self.flagged |= other.flagged;
+        for (category, flagged) in other.categories {
+            *self.categories.entry(category).or_default() |= flagged;
+        }
+        for (category, score) in other.category_scores {
+            let entry = self.category_scores.entry(category).or_default();
+            *entry = entry.max(score);
+        }
+        self
+    }
+
+    /// The flagged categories, sorted by name.
+    pub fn flagged_categories(&self) -> Vec<&str> {
+        let mut categories: Vec<_> = self
+            .categories
+            .iter()
+            .filter(|(_, flagged)| **flagged)
+            .map(|(category, _)| category.as_str())
+            .collect();
+        categories.sort_unstable();
+        categories
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn moderation_results_should_merge_flags_and_scores() {
+        let res: ModerationResponse = serde_json::from_value(json!({
+            "id": "modr-1",
+            "model": "omni-moderation-latest",
+            "results": [{
+                "flagged": true,
+                "categories": {"harassment": true, "violence": false},
+                "category_scores": {"harassment": 0.9, "violence": 0.1}
+            }]
+        }))
+        .unwrap();
+        let input = res.results[0].clone();
+        let output = ModerationResult {
+            flagged: false,
+            categories: HashMap::from([("violence".to_string(), false)]),
+            category_scores: HashMap::from([("violence".to_string(), 0.3)]),
+        };
+        let merged = input.merge(output);
+        assert!(merged.flagged);
+        assert_eq!(merged.flagged_categories(), vec!["harassment"]);
+        assert_eq!(merged.category_scores["violence"], 0.3);
+    }
+}
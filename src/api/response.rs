@@ -0,0 +1,79 @@
+use anyhow::Result;
+use bytes::Bytes;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::{Response, StatusCode};
+use serde::de::DeserializeOwned;
+use std::time::Duration;
+
+/// A deserialized response body together with the status and headers it came with.
+#[derive(Debug, Clone)]
+pub struct ApiResponse<T> {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: T,
+}
+
+impl<T> ApiResponse<T> {
+    /// The `x-request-id` header, useful when reporting issues to OpenAI.
+    pub fn request_id(&self) -> Option<&str> {
+        self.headers
+            .get("x-request-id")
+            .and_then(|v| v.to_str().ok())
+    }
+
+    /// The `Retry-After` header in seconds, which may be sent even on success.
+    pub fn retry_after(&self) -> Option<Duration> {
+        self.headers
+            .get(RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<f64>().ok())
+            .filter(|secs| secs.is_finite() && *secs >= 0.0)
+            .map(Duration::from_secs_f64)
+    }
+
+    pub fn into_parts(self) -> (StatusCode, T) {
+        (self.status, self.body)
+    }
+}
+
+impl ApiResponse<Bytes> {
+    pub(crate) async fn from_response(res: Response) -> Result<Self> {
+        let status = res.status();
+        let headers = res.headers().clone();
+        let body = res.bytes().await?;
+        Ok(Self {
+            status,
+            headers,
+            body,
+        })
+    }
+
+    pub(crate) fn json<T: DeserializeOwned>(self) -> Result<ApiResponse<T>> {
+        Ok(ApiResponse {
+            status: self.status,
+            headers: self.headers,
+            body: serde_json::from_slice(&self.body)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn retry_after_should_parse_seconds() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("1.5"));
+        headers.insert("x-request-id", HeaderValue::from_static("req_123"));
+        let res = ApiResponse {
+            status: StatusCode::CREATED,
+            headers,
+            body: (),
+        };
+        assert_eq!(res.retry_after(), Some(Duration::from_millis(1500)));
+        assert_eq!(res.request_id(), Some("req_123"));
+        assert_eq!(res.into_parts().0, StatusCode::CREATED);
+    }
+}
@@ -0,0 +1,151 @@
+use crate::{BetaFeature, IntoRequest, ToolCall};
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
+use serde::{Deserialize, Serialize};
+
+/// Run an assistant on a thread.
+#[derive(Debug, Clone, Serialize)]
+pub struct CreateRunRequest {
+    #[serde(skip)]
+    thread_id: String,
+    /// The id of the assistant to use for the run.
+    assistant_id: String,
+}
+
+/// Get the current state of a run.
+#[derive(Debug, Clone)]
+pub struct RetrieveRunRequest {
+    thread_id: String,
+    run_id: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Run {
+    pub id: String,
+    pub thread_id: String,
+    pub assistant_id: String,
+    pub status: RunStatus,
+    /// What the run is waiting for when the status is `requires_action`.
+    #[serde(default)]
+    pub required_action: Option<RequiredAction>,
+    /// Why the run failed, if it did.
+    #[serde(default)]
+    pub last_error: Option<RunError>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunStatus {
+    Queued,
+    InProgress,
+    /// The run is waiting for the outputs of the tool calls in `Run::required_action`.
+    RequiresAction,
+    Cancelling,
+    Cancelled,
+    Failed,
+    Completed,
+    Incomplete,
+    Expired,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RequiredAction {
+    pub submit_tool_outputs: SubmitToolOutputs,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SubmitToolOutputs {
+    pub tool_calls: Vec<ToolCall>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RunError {
+    pub code: String,
+    pub message: String,
+}
+
+impl CreateRunRequest {
+    pub fn new(thread_id: impl Into<String>, assistant_id: impl Into<String>) -> Self {
+        Self {
+            thread_id: thread_id.into(),
+            assistant_id: assistant_id.into(),
+        }
+    }
+}
+
+impl RetrieveRunRequest {
+    pub fn new(thread_id: impl Into<String>, run_id: impl Into<String>) -> Self {
+        Self {
+            thread_id: thread_id.into(),
+            run_id: run_id.into(),
+        }
+    }
+}
+
+impl RunStatus {
+    /// Whether the run stopped, either for good or until tool outputs are submitted.
+    pub fn is_terminal(&self) -> bool {
+        !matches!(
+            self,
+            RunStatus::Queued | RunStatus::InProgress | RunStatus::Cancelling
+        )
+    }
+}
+
+impl IntoRequest for CreateRunRequest {
+    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        let url = format!("{}/threads/{}/runs", base_url, self.thread_id);
+        client.post(url).json(&self)
+    }
+
+    fn beta_feature(&self) -> Option<BetaFeature> {
+        Some(BetaFeature::AssistantsV2)
+    }
+}
+
+impl IntoRequest for RetrieveRunRequest {
+    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        let url = format!(
+            "{}/threads/{}/runs/{}",
+            base_url, self.thread_id, self.run_id
+        );
+        client.get(url)
+    }
+
+    fn beta_feature(&self) -> Option<BetaFeature> {
+        Some(BetaFeature::AssistantsV2)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn run_should_deserialize_required_action() {
+        let run: Run = serde_json::from_value(serde_json::json!({
+            "id": "run_1",
+            "object": "thread.run",
+            "thread_id": "thread_1",
+            "assistant_id": "asst_1",
+            "status": "requires_action",
+            "required_action": {
+                "type": "submit_tool_outputs",
+                "submit_tool_outputs": {"tool_calls": [{
+                    "id": "call_1",
+                    "type": "function",
+                    "function": {"name": "get_weather", "arguments": "{}"}
+                }]}
+            },
+            "last_error": null
+        }))
+        .unwrap();
+        assert_eq!(run.status, RunStatus::RequiresAction);
+        assert!(run.status.is_terminal());
+        let action = run.required_action.unwrap();
+        assert_eq!(
+            action.submit_tool_outputs.tool_calls[0].function().name,
+            "get_weather"
+        );
+        assert!(!RunStatus::InProgress.is_terminal());
+    }
+}
@@ -1,7 +1,7 @@
-use crate::IntoRequest;
+use crate::{EndpointKind, IntoRequest};
 use derive_builder::Builder;
 use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Builder)]
 #[builder(pattern = "mutable")]
@@ -13,9 +13,9 @@ pub struct SpeechRequest {
     #[builder(setter(into))]
     input: String,
     /// The voice to use when generating the audio. Supported voices are alloy, echo, fable, onyx, nova, and shimmer. Previews of the voices are available in the Text to speech guide.
-    #[builder(default)]
+    #[builder(default, setter(into))]
     voice: SpeechVoice,
-    /// The format to audio in. Supported formats are mp3, opus, aac, and flac.
+    /// The format to audio in. Supported formats are mp3, opus, aac, flac and pcm.
     #[builder(default)]
     response_format: SpeechResponseFormat,
     /// The speed of the generated audio. Select a value from 0.25 to 4.0. 1.0 is the default.
@@ -24,16 +24,19 @@ pub struct SpeechRequest {
     speed: Option<f32>,
 }
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
 pub enum SpeechModel {
     #[default]
     #[serde(rename = "tts-1")]
     Tts1,
     #[serde(rename = "tts-1-hd")]
     Tts1Hd,
+    /// Any other model name, e.g. a newly released or fine-tuned model.
+    #[serde(untagged)]
+    Custom(String),
 }
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum SpeechVoice {
     Alloy,
@@ -43,8 +46,43 @@ pub enum SpeechVoice {
     #[default]
     Nova,
     Shimmer,
+    /// A custom voice, serialized as its voice id.
+    #[serde(untagged)]
+    Custom(CustomVoice),
+}
+
+/// A custom voice created from an audio sample. This is forward-looking: OpenAI has announced
+/// custom voices but not released them yet, see `LlmSDK::create_voice`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct CustomVoice {
+    pub voice_id: String,
+}
+
+/// A voice that can be used for speech, see `LlmSDK::list_voices`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VoiceInfo {
+    /// The id to pass as `SpeechVoice`.
+    pub id: String,
+    pub name: String,
+    /// The language the voice is tuned for. OpenAI voices speak other languages too, with an
+    /// English accent.
+    pub language: String,
+    pub gender: Option<String>,
+    /// A sample of the voice.
+    pub preview_url: Option<String>,
 }
 
+/// The built-in OpenAI voices as `(id, name, gender)`.
+const OPENAI_VOICES: &[(&str, &str, Option<&str>)] = &[
+    ("alloy", "Alloy", None),
+    ("echo", "Echo", Some("male")),
+    ("fable", "Fable", Some("male")),
+    ("onyx", "Onyx", Some("male")),
+    ("nova", "Nova", Some("female")),
+    ("shimmer", "Shimmer", Some("female")),
+];
+
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
 #[serde(rename_all = "snake_case")]
 pub enum SpeechResponseFormat {
@@ -53,6 +91,8 @@ pub enum SpeechResponseFormat {
     Opus,
     Aac,
     Flac,
+    /// Raw 24kHz 16-bit signed little-endian samples, without a header.
+    Pcm,
 }
 
 impl IntoRequest for SpeechRequest {
@@ -60,6 +100,53 @@ impl IntoRequest for SpeechRequest {
         let url = format!("{}/audio/speech", base_url);
         client.post(url).json(&self)
     }
+
+    fn endpoint_kind(&self) -> Option<EndpointKind> {
+        Some(EndpointKind::Speech)
+    }
+}
+
+impl From<CustomVoice> for SpeechVoice {
+    fn from(voice: CustomVoice) -> Self {
+        Self::Custom(voice)
+    }
+}
+
+impl CustomVoice {
+    pub fn new(voice_id: impl Into<String>) -> Self {
+        Self {
+            voice_id: voice_id.into(),
+        }
+    }
+}
+
+impl VoiceInfo {
+    /// The built-in OpenAI voices. OpenAI has no endpoint to list them.
+    pub(crate) fn openai_voices() -> Vec<VoiceInfo> {
+        OPENAI_VOICES
+            .iter()
+            .map(|(id, name, gender)| VoiceInfo {
+                id: id.to_string(),
+                name: name.to_string(),
+                language: "en".to_string(),
+                gender: gender.map(str::to_string),
+                preview_url: None,
+            })
+            .collect()
+    }
+
+    /// The voice to use in a `SpeechRequest`.
+    pub fn voice(&self) -> SpeechVoice {
+        match self.id.as_str() {
+            "alloy" => SpeechVoice::Alloy,
+            "echo" => SpeechVoice::Echo,
+            "fable" => SpeechVoice::Fable,
+            "onyx" => SpeechVoice::Onyx,
+            "nova" => SpeechVoice::Nova,
+            "shimmer" => SpeechVoice::Shimmer,
+            id => SpeechVoice::Custom(CustomVoice::new(id)),
+        }
+    }
 }
 
 impl SpeechRequest {
@@ -78,6 +165,28 @@ mod test {
     use anyhow::Result;
     use std::fs;
 
+    #[test]
+    fn custom_voice_should_serialize_as_voice_id() {
+        let req = SpeechRequestBuilder::default()
+            .input("hello")
+            .voice(CustomVoice::new("voice_abc123"))
+            .build()
+            .unwrap();
+        let json = serde_json::to_value(req).unwrap();
+        assert_eq!(json["voice"], "voice_abc123");
+    }
+
+    #[test]
+    fn openai_voices_should_map_to_speech_voices() {
+        let voices: Vec<_> = VoiceInfo::openai_voices()
+            .iter()
+            .map(|voice| voice.voice())
+            .collect();
+        assert_eq!(voices.len(), 6);
+        assert_eq!(voices[0], SpeechVoice::Alloy);
+        assert_eq!(voices[5], SpeechVoice::Shimmer);
+    }
+
     #[tokio::test]
     async fn test_speech() -> Result<()> {
         let req = SpeechRequest::new("The quick brown fox jumps over the lazy dog.");
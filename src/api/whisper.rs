@@ -1,11 +1,17 @@
-use crate::IntoRequest;
+use crate::{EndpointKind, IntoRequest, LlmError};
+use bytes::Bytes;
 use derive_builder::Builder;
+use futures::{Stream, StreamExt};
 use reqwest::multipart::{Form, Part};
+use reqwest::Body;
 use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
 use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::fmt;
+use std::sync::{Arc, Mutex};
 use strum::{Display, EnumString};
 
-#[derive(Debug, Clone, Builder, Serialize)]
+#[derive(Clone, Builder, Serialize)]
 #[builder(pattern = "mutable")]
 pub struct WhisperRequest {
     /// The audio file object (not file name) to transcribe/translate, in one of these formats: flac, mp3, mp4, mpeg, mpga, m4a, ogg, wav, or webm.
@@ -25,18 +31,51 @@ pub struct WhisperRequest {
     /// The sampling temperature, between 0 and 1. Higher values like 0.8 will make the output more random, while lower values like 0.2 will make it more focused and deterministic. If set to 0, the model will use log probability to automatically increase the temperature until certain thresholds are hit.
     #[builder(default, setter(strip_option))]
     temperature: Option<f32>,
+    /// The timestamp granularities to populate for this transcription. `response_format` must be set to `verbose_json` to use timestamp granularities.
+    #[builder(default)]
+    timestamp_granularities: Vec<TimestampGranularity>,
 
     request_type: WhisperRequestType,
+    /// The audio streamed from a source instead of `file`, see `WhisperRequest::from_stream`.
+    #[builder(setter(skip))]
+    #[serde(skip)]
+    stream: Option<StreamedFile>,
+    /// Stream the transcript as server-sent events, set by `LlmSDK::whisper_stream`.
+    #[builder(setter(skip))]
+    #[serde(skip)]
+    pub(crate) stream_events: bool,
 }
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, EnumString, Display, Serialize)]
+/// A streamed audio body. Clones share the stream, so only the first request built from it can
+/// upload the audio. Sending another one fails rather than uploading an empty file.
+#[derive(Clone)]
+struct StreamedFile(Arc<Mutex<Option<Body>>>);
+
+const STREAM_ALREADY_SENT: &str = "the streamed audio was already sent, a request built with \
+    `WhisperRequest::from_stream` can only be sent once";
+
+impl StreamedFile {
+    fn new(body: Body) -> Self {
+        Self(Arc::new(Mutex::new(Some(body))))
+    }
+
+    fn take(&self) -> Option<Body> {
+        self.0.lock().ok()?.take()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, EnumString, Display, Serialize)]
 pub enum WhisperModel {
     #[default]
     #[strum(serialize = "whisper-1")]
     Whisper1,
+    /// Any other model name, e.g. a newly released or fine-tuned model.
+    #[strum(default)]
+    #[serde(untagged)]
+    Custom(String),
 }
 
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, EnumString, Display, Serialize)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, EnumString, Display, Serialize)]
 #[strum(serialize_all = "snake_case")]
 pub enum WhisperResponseFormat {
     #[default]
@@ -47,6 +86,14 @@ pub enum WhisperResponseFormat {
     Vtt,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, Display, Serialize)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum TimestampGranularity {
+    Word,
+    Segment,
+}
+
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, EnumString, Display, Serialize)]
 pub enum WhisperRequestType {
     #[default]
@@ -59,6 +106,82 @@ pub struct WhisperResponse {
     pub text: String,
 }
 
+/// The response for `WhisperResponseFormat::VerboseJson`, with timestamped segments.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WhisperVerboseResponse {
+    /// The language of the input audio.
+    pub language: String,
+    /// The duration of the input audio in seconds.
+    pub duration: f32,
+    /// The transcribed text.
+    pub text: String,
+    #[serde(default)]
+    pub segments: Vec<WhisperSegment>,
+    /// Only set if `TimestampGranularity::Word` was requested.
+    #[serde(default)]
+    pub words: Vec<WhisperWord>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WhisperSegment {
+    pub id: usize,
+    /// Start time of the segment in seconds.
+    pub start: f32,
+    /// End time of the segment in seconds.
+    pub end: f32,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WhisperWord {
+    pub word: String,
+    /// Start time of the word in seconds.
+    pub start: f32,
+    /// End time of the word in seconds.
+    pub end: f32,
+}
+
+/// An event of a streamed transcription, see `LlmSDK::whisper_stream`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type")]
+pub enum TranscriptionEvent {
+    /// More text of the transcript, which may still be revised by later segments.
+    #[serde(rename = "transcript.text.delta")]
+    TextDelta { delta: String },
+    /// A finalized segment with its timestamps, sent by models that segment the audio, e.g.
+    /// gpt-4o-transcribe-diarize. Use these to render timed subtitle blocks.
+    #[serde(rename = "transcript.text.segment")]
+    Segment(TranscriptionSegment),
+    /// The complete transcript, always the last event.
+    #[serde(rename = "transcript.text.done")]
+    Done { text: String },
+    /// An event type this SDK doesn't know yet.
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TranscriptionSegment {
+    pub id: String,
+    /// Start time of the segment in seconds.
+    pub start: f32,
+    /// End time of the segment in seconds.
+    pub end: f32,
+    pub text: String,
+    /// The speaker label, for diarizing models.
+    #[serde(default)]
+    pub speaker: Option<String>,
+}
+
+/// The output of `LlmSDK::whisper_multi` for one response format.
+#[derive(Debug, Clone)]
+pub enum WhisperOutput {
+    Json(WhisperResponse),
+    /// The transcript for `Text`, `Srt` and `Vtt`.
+    Text(String),
+    VerboseJson(WhisperVerboseResponse),
+}
+
 impl WhisperRequest {
     pub fn transcription(data: Vec<u8>) -> Self {
         WhisperRequestBuilder::default()
@@ -76,11 +199,47 @@ impl WhisperRequest {
             .unwrap()
     }
 
-    pub fn into_form(self) -> Form {
-        let part = Part::bytes(self.file)
-            .file_name("file.mp3")
-            .mime_str("audio/mp3")
+    /// Build a request that streams the audio from `stream` instead of buffering it in memory,
+    /// which keeps memory flat for very large recordings. A streamed body can't be replayed, so
+    /// the request is never retried and is skipped by `record_to`.
+    pub fn from_stream<S>(stream: S, request_type: WhisperRequestType) -> Self
+    where
+        S: Stream<Item = Bytes> + Send + Sync + 'static,
+    {
+        let body = Body::wrap_stream(stream.map(Ok::<_, Infallible>));
+        let mut req = WhisperRequestBuilder::default()
+            .file(vec![])
+            .request_type(request_type)
+            .build()
             .unwrap();
+        req.stream = Some(StreamedFile::new(body));
+        req
+    }
+
+    /// Move a streamed body out of the storage shared with clones of the request, so that this
+    /// request is the one to send it. Fails if a clone has already taken it.
+    pub(crate) fn claim_stream(&mut self) -> Result<(), LlmError> {
+        if let Some(stream) = &self.stream {
+            let body = stream
+                .take()
+                .ok_or_else(|| LlmError::InvalidRequest(STREAM_ALREADY_SENT.into()))?;
+            self.stream = Some(StreamedFile::new(body));
+        }
+        Ok(())
+    }
+
+    /// The multipart form of the request. The form of a streamed request whose audio was already
+    /// sent fails to upload.
+    pub fn into_form(self) -> Form {
+        let part = match self.stream {
+            Some(stream) => Part::stream(stream.take().unwrap_or_else(|| {
+                Body::wrap_stream(futures::stream::once(async {
+                    Err::<Bytes, _>(LlmError::InvalidRequest(STREAM_ALREADY_SENT.into()))
+                }))
+            })),
+            None => Part::bytes(self.file),
+        };
+        let part = part.file_name("file.mp3").mime_str("audio/mp3").unwrap();
         let mut form = Form::new()
             .part("file", part)
             .text("model", self.model.to_string())
@@ -95,6 +254,12 @@ impl WhisperRequest {
         } else {
             form
         };
+        if self.stream_events {
+            form = form.text("stream", "true");
+        }
+        for granularity in self.timestamp_granularities {
+            form = form.text("timestamp_granularities[]", granularity.to_string());
+        }
         if let Some(temperature) = self.temperature {
             form.text("temperature", temperature.to_string())
         } else {
@@ -103,6 +268,84 @@ impl WhisperRequest {
     }
 }
 
+impl WhisperVerboseResponse {
+    /// Render the segments as SubRip subtitles, as returned for `WhisperResponseFormat::Srt`.
+    pub fn to_srt(&self) -> String {
+        self.segments
+            .iter()
+            .enumerate()
+            .map(|(i, segment)| {
+                format!(
+                    "{}\n{} --> {}\n{}\n\n",
+                    i + 1,
+                    timestamp(segment.start, ','),
+                    timestamp(segment.end, ','),
+                    segment.text.trim()
+                )
+            })
+            .collect()
+    }
+
+    /// Render the segments as WebVTT subtitles, as returned for `WhisperResponseFormat::Vtt`.
+    pub fn to_vtt(&self) -> String {
+        let cues: String = self
+            .segments
+            .iter()
+            .map(|segment| {
+                format!(
+                    "{} --> {}\n{}\n\n",
+                    timestamp(segment.start, '.'),
+                    timestamp(segment.end, '.'),
+                    segment.text.trim()
+                )
+            })
+            .collect();
+        format!("WEBVTT\n\n{}", cues)
+    }
+
+    /// Derive the output of `format` without another request.
+    pub fn to_output(&self, format: WhisperResponseFormat) -> WhisperOutput {
+        match format {
+            WhisperResponseFormat::Json => WhisperOutput::Json(WhisperResponse {
+                text: self.text.clone(),
+            }),
+            WhisperResponseFormat::Text => WhisperOutput::Text(self.text.clone()),
+            WhisperResponseFormat::Srt => WhisperOutput::Text(self.to_srt()),
+            WhisperResponseFormat::Vtt => WhisperOutput::Text(self.to_vtt()),
+            WhisperResponseFormat::VerboseJson => WhisperOutput::VerboseJson(self.clone()),
+        }
+    }
+}
+
+fn timestamp(seconds: f32, separator: char) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        separator,
+        millis % 1000
+    )
+}
+
+impl fmt::Debug for WhisperRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WhisperRequest")
+            .field("file", &format_args!("[<{} bytes>]", self.file.len()))
+            .field("model", &self.model)
+            .field("language", &self.language)
+            .field("prompt", &self.prompt)
+            .field("response_format", &self.response_format)
+            .field("temperature", &self.temperature)
+            .field("timestamp_granularities", &self.timestamp_granularities)
+            .field("request_type", &self.request_type)
+            .field("streamed", &self.stream.is_some())
+            .field("stream_events", &self.stream_events)
+            .finish()
+    }
+}
+
 impl IntoRequest for WhisperRequest {
     fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
         let url = match self.request_type {
@@ -111,14 +354,91 @@ impl IntoRequest for WhisperRequest {
         };
         client.post(url).multipart(self.into_form())
     }
+
+    fn endpoint_kind(&self) -> Option<EndpointKind> {
+        Some(EndpointKind::Whisper)
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::{WhisperRequest, SDK};
+    use crate::{
+        test_server, LlmError, LlmSDK, WhisperModel, WhisperOutput, WhisperRequest,
+        WhisperRequestType, WhisperResponseFormat, WhisperVerboseResponse, SDK,
+    };
     use anyhow::Result;
+    use bytes::Bytes;
     use std::fs;
 
+    #[test]
+    fn verbose_response_should_derive_subtitles() {
+        let res: WhisperVerboseResponse = serde_json::from_value(serde_json::json!({
+            "task": "transcribe",
+            "language": "english",
+            "duration": 5.2,
+            "text": "Hello there. General Kenobi.",
+            "segments": [
+                {"id": 0, "seek": 0, "start": 0.0, "end": 1.5, "text": " Hello there."},
+                {"id": 1, "seek": 0, "start": 1.5, "end": 3661.25, "text": " General Kenobi."}
+            ]
+        }))
+        .unwrap();
+        assert_eq!(
+            res.to_srt(),
+            "1\n00:00:00,000 --> 00:00:01,500\nHello there.\n\n2\n00:00:01,500 --> 01:01:01,250\nGeneral Kenobi.\n\n"
+        );
+        assert!(res
+            .to_vtt()
+            .starts_with("WEBVTT\n\n00:00:00.000 --> 00:00:01.500\nHello there.\n"));
+        match res.to_output(WhisperResponseFormat::Text) {
+            WhisperOutput::Text(text) => assert_eq!(text, "Hello there. General Kenobi."),
+            other => panic!("unexpected output {:?}", other),
+        }
+    }
+
+    #[test]
+    fn from_stream_should_mark_request_as_streamed() {
+        let chunks = futures::stream::iter(vec![Bytes::from_static(b"ID3"), Bytes::new()]);
+        let req = WhisperRequest::from_stream(chunks, WhisperRequestType::Transcription);
+        assert!(format!("{:?}", req).contains("streamed: true"));
+        let _form = req.into_form();
+    }
+
+    #[tokio::test]
+    async fn streamed_request_should_upload_once() -> Result<()> {
+        let (url, received) =
+            test_server::serve_recording(vec![(200, r#"{"text": "hello"}"#.into())]);
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let chunks = futures::stream::iter(vec![
+            Bytes::from_static(b"streamed-"),
+            Bytes::from_static(b"audio"),
+        ]);
+        let req = WhisperRequest::from_stream(chunks, WhisperRequestType::Transcription);
+        let clone = req.clone();
+        assert_eq!(sdk.whisper(req).await?.text, "hello");
+        assert!(received.lock().unwrap()[0].contains("streamed-audio"));
+
+        let err = sdk.whisper(clone).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LlmError>(),
+            Some(LlmError::InvalidRequest(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn debug_should_not_dump_audio_bytes() {
+        let req = WhisperRequest::transcription(vec![1, 2, 3, 4, 5]);
+        assert!(format!("{:?}", req).contains("file: [<5 bytes>]"));
+    }
+
+    #[test]
+    fn custom_model_should_use_its_name() {
+        let model = WhisperModel::Custom("gpt-4o-transcribe".into());
+        assert_eq!(model.to_string(), "gpt-4o-transcribe");
+        assert_eq!(WhisperModel::Whisper1.to_string(), "whisper-1");
+    }
+
     #[tokio::test]
     async fn test() -> Result<()> {
         let data = fs::read("test.mp3")?;
@@ -0,0 +1,83 @@
+use crate::{
+    ChatCompletionChunk, ChatCompletionRequest, ChatCompletionResponse, CreateImageRequest,
+    CreateImageResponse, EmbeddingRequest, LlmSDK, ModerationRequest, ModerationResponse,
+    SpeechRequest, WhisperRequest, WhisperResponse,
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::stream::BoxStream;
+
+/// The core API calls of `LlmSDK` as an object safe trait, so callers can take an
+/// `Arc<dyn LlmClient>` and swap in a mock or another provider.
+#[async_trait]
+pub trait LlmClient: Send + Sync {
+    async fn chat_completion(&self, req: ChatCompletionRequest) -> Result<ChatCompletionResponse>;
+
+    async fn chat_completion_stream(
+        &self,
+        req: ChatCompletionRequest,
+    ) -> Result<BoxStream<'static, Result<ChatCompletionChunk>>>;
+
+    async fn create_image(&self, req: CreateImageRequest) -> Result<CreateImageResponse>;
+
+    async fn embedding(&self, req: EmbeddingRequest) -> Result<Bytes>;
+
+    async fn moderation(&self, req: ModerationRequest) -> Result<ModerationResponse>;
+
+    async fn speech(&self, req: SpeechRequest) -> Result<Bytes>;
+
+    async fn whisper(&self, req: WhisperRequest) -> Result<WhisperResponse>;
+}
+
+#[async_trait]
+impl LlmClient for LlmSDK {
+    async fn chat_completion(&self, req: ChatCompletionRequest) -> Result<ChatCompletionResponse> {
+        LlmSDK::chat_completion(self, req).await
+    }
+
+    async fn chat_completion_stream(
+        &self,
+        req: ChatCompletionRequest,
+    ) -> Result<BoxStream<'static, Result<ChatCompletionChunk>>> {
+        LlmSDK::chat_completion_stream(self, req).await
+    }
+
+    async fn create_image(&self, req: CreateImageRequest) -> Result<CreateImageResponse> {
+        LlmSDK::create_image(self, req).await
+    }
+
+    async fn embedding(&self, req: EmbeddingRequest) -> Result<Bytes> {
+        LlmSDK::embedding(self, req).await
+    }
+
+    async fn moderation(&self, req: ModerationRequest) -> Result<ModerationResponse> {
+        LlmSDK::moderation(self, req).await
+    }
+
+    async fn speech(&self, req: SpeechRequest) -> Result<Bytes> {
+        LlmSDK::speech(self, req).await
+    }
+
+    async fn whisper(&self, req: WhisperRequest) -> Result<WhisperResponse> {
+        LlmSDK::whisper(self, req).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_server, ChatCompleteModel, ChatCompletionMessage};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn sdk_should_be_usable_as_trait_object() -> Result<()> {
+        let url = test_server::serve(vec![(200, test_server::chat_response("hi"))]);
+        let client: Arc<dyn LlmClient> = Arc::new(LlmSDK::new_with_base_url("token", url));
+        let messages = vec![ChatCompletionMessage::new_user("hello", "")];
+        let req = ChatCompletionRequest::new(ChatCompleteModel::Gpt4oMini, messages);
+        let res = client.chat_completion(req).await?;
+        assert_eq!(res.choices[0].message.content.as_deref(), Some("hi"));
+        Ok(())
+    }
+}
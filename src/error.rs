@@ -0,0 +1,183 @@
+use reqwest::StatusCode;
+use serde::Deserialize;
+use thiserror::Error;
+
+/// Errors produced by the SDK. Public methods return `anyhow::Result`, so use
+/// `err.downcast_ref::<LlmError>()` to inspect them.
+#[derive(Debug, Error)]
+pub enum LlmError {
+    /// The API responded with a non-success status code.
+    #[error("API failed ({status}): {message}")]
+    ApiError {
+        status: u16,
+        /// The machine readable error code, e.g. `content_policy_violation`.
+        code: Option<String>,
+        /// The error type, e.g. `invalid_request_error`.
+        r#type: Option<String>,
+        /// The request parameter the error relates to, e.g. `max_tokens`.
+        param: Option<String>,
+        message: String,
+    },
+    /// The prompt was rejected by the content policy and no fallback was available.
+    #[error("prompt was rejected by the content policy")]
+    ContentFiltered,
+    /// The feature is not yet offered by the API.
+    #[error("{0} is not yet available")]
+    NotYetAvailable(&'static str),
+    /// The request was rejected locally because the API would reject it.
+    #[error("invalid request: {0}")]
+    InvalidRequest(String),
+    /// The conversation is estimated to exceed the context window of the model.
+    #[error("estimated {estimated} tokens exceed the context window of {limit} tokens")]
+    ContextWindowExceeded { estimated: usize, limit: usize },
+    /// The model didn't produce valid structured output within the allowed attempts.
+    #[error("no valid structured output after all attempts: {last_error}")]
+    MaxRetryAttemptsExceeded {
+        last_error: String,
+        /// The output of the last attempt.
+        raw_output: String,
+    },
+    /// Structured output was cut off by the token limit (`finish_reason` is `length`), so it
+    /// isn't valid JSON. Raise `max_tokens` or ask for less output.
+    #[error("structured output was truncated after {completion_tokens} tokens, raise max_tokens")]
+    TruncatedJson {
+        completion_tokens: usize,
+        /// The truncated output.
+        raw_output: String,
+    },
+    /// A document could not be read or converted to text.
+    #[error("failed to parse document: {0}")]
+    DocumentParseError(String),
+    /// A streamed response ended before the `[DONE]` sentinel.
+    #[error("stream ended before completion")]
+    StreamInterrupted,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiErrorBody {
+    error: ApiErrorDetail,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiErrorDetail {
+    message: String,
+    #[serde(default)]
+    r#type: Option<String>,
+    #[serde(default)]
+    code: Option<String>,
+    #[serde(default)]
+    param: Option<String>,
+}
+
+impl LlmError {
+    /// Build an `ApiError` from a failed response. Falls back to the raw body as message if it
+    /// is not an OpenAI style error object.
+    pub(crate) fn from_response(status: StatusCode, text: &str) -> Self {
+        match serde_json::from_str::<ApiErrorBody>(text) {
+            Ok(body) => LlmError::ApiError {
+                status: status.as_u16(),
+                code: body.error.code,
+                r#type: body.error.r#type,
+                param: body.error.param,
+                message: body.error.message,
+            },
+            Err(_) => LlmError::ApiError {
+                status: status.as_u16(),
+                code: None,
+                r#type: None,
+                param: None,
+                message: text.to_owned(),
+            },
+        }
+    }
+
+    /// Some OpenAI compatible gateways report errors with a success status. Returns the
+    /// `ApiError` if `body` is an error object.
+    pub(crate) fn from_success_body(status: StatusCode, body: &[u8]) -> Option<Self> {
+        let body = serde_json::from_slice::<ApiErrorBody>(body).ok()?;
+        Some(LlmError::ApiError {
+            status: status.as_u16(),
+            code: body.error.code,
+            r#type: body.error.r#type,
+            param: body.error.param,
+            message: body.error.message,
+        })
+    }
+
+    pub fn is_content_policy_violation(&self) -> bool {
+        matches!(self, LlmError::ApiError { code: Some(code), .. } if code == "content_policy_violation")
+    }
+
+    /// Whether the model rejected `max_tokens` and wants `max_completion_tokens` instead, as
+    /// the reasoning models do.
+    pub fn is_max_tokens_unsupported(&self) -> bool {
+        matches!(
+            self,
+            LlmError::ApiError { code: Some(code), param: Some(param), .. }
+                if code == "unsupported_parameter" && param == "max_tokens"
+        )
+    }
+}
+
+pub(crate) fn is_content_policy_violation(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<LlmError>()
+        .is_some_and(LlmError::is_content_policy_violation)
+}
+
+pub(crate) fn is_max_tokens_unsupported(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<LlmError>()
+        .is_some_and(LlmError::is_max_tokens_unsupported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn api_error_should_parse_openai_error_body() {
+        let body = r#"{"error":{"message":"Your request was rejected","type":"invalid_request_error","param":null,"code":"content_policy_violation"}}"#;
+        let err = LlmError::from_response(StatusCode::BAD_REQUEST, body);
+        assert!(err.is_content_policy_violation());
+        match err {
+            LlmError::ApiError {
+                status,
+                r#type,
+                message,
+                ..
+            } => {
+                assert_eq!(status, 400);
+                assert_eq!(r#type.as_deref(), Some("invalid_request_error"));
+                assert_eq!(message, "Your request was rejected");
+            }
+            _ => panic!("expected ApiError"),
+        }
+    }
+
+    #[test]
+    fn api_error_should_detect_unsupported_max_tokens() {
+        let body = r#"{"error":{"message":"Unsupported parameter: 'max_tokens' is not supported with this model. Use 'max_completion_tokens' instead.","type":"invalid_request_error","param":"max_tokens","code":"unsupported_parameter"}}"#;
+        let err = LlmError::from_response(StatusCode::BAD_REQUEST, body);
+        assert!(err.is_max_tokens_unsupported());
+        let body = r#"{"error":{"message":"Unsupported parameter: 'temperature'","type":"invalid_request_error"}}"#;
+        let err = LlmError::from_response(StatusCode::BAD_REQUEST, body);
+        assert!(!err.is_max_tokens_unsupported());
+    }
+
+    #[test]
+    fn api_error_should_keep_raw_body_when_not_json() {
+        let err = LlmError::from_response(StatusCode::BAD_GATEWAY, "bad gateway");
+        assert!(!err.is_content_policy_violation());
+        assert_eq!(err.to_string(), "API failed (502): bad gateway");
+    }
+
+    #[test]
+    fn success_body_should_only_be_an_error_if_it_has_an_error_object() {
+        let body = br#"{"error":{"message":"The server is overloaded","type":"server_error"}}"#;
+        let err = LlmError::from_success_body(StatusCode::OK, body).unwrap();
+        assert_eq!(
+            err.to_string(),
+            "API failed (200): The server is overloaded"
+        );
+        assert!(LlmError::from_success_body(StatusCode::OK, br#"{"object":"list"}"#).is_none());
+    }
+}
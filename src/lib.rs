@@ -1,38 +1,253 @@
 mod api;
+mod client;
+mod error;
 mod middleware;
+#[cfg(feature = "pipeline")]
+mod pipeline;
+mod record;
+mod sensitive;
+mod sse;
+mod tasks;
+#[cfg(test)]
+mod test_server;
+mod tokenizer;
 
-use crate::middleware::RetryMiddleware;
-use anyhow::Result;
+use crate::error::{is_content_policy_violation, is_max_tokens_unsupported};
+use crate::middleware::{
+    ConcurrencyLimitMiddleware, RetryMiddleware, SigningMiddleware, SingleFlightMiddleware,
+};
+use crate::record::Recorder;
+use anyhow::{anyhow, Result};
 pub use api::*;
+use base64::{engine::general_purpose::STANDARD, Engine};
 use bytes::Bytes;
+pub use client::LlmClient;
 use derive_builder::Builder;
+pub use error::LlmError;
+use futures::stream::{self, BoxStream};
+use futures::{Stream, StreamExt};
+pub use middleware::{Sleeper, TokioSleeper};
+#[cfg(feature = "pipeline")]
+pub use pipeline::*;
+pub use record::{read_recorded, replay, RecordedRequest};
+use reqwest::header::{HeaderMap, HeaderName, CONTENT_TYPE};
 use reqwest::{Client, Response};
 use reqwest_middleware::{ClientBuilder, ClientWithMiddleware, RequestBuilder};
 use reqwest_retry::policies::ExponentialBackoff;
-use reqwest_retry::RetryTransientMiddleware;
+use reqwest_retry::{Jitter, RetryDecision, RetryPolicy};
 use reqwest_tracing::TracingMiddleware;
 use schemars::{schema_for, JsonSchema};
-use std::time::Duration;
-use tracing::{error, info};
+pub use sensitive::Sensitive;
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+pub use tasks::*;
+pub use tokenizer::warm_tokenizer;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
 
+const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
 const TIMEOUT: u64 = 30;
+const CONNECT_TIMEOUT: u64 = 10;
+const IMAGE_TIMEOUT: u64 = 120;
 const MAX_RETRIES: u32 = 3;
+const MAX_REDIRECTS: usize = 10;
+const MAX_RETRY_INTERVAL: u64 = 20;
+const RETRY_BASE_INTERVAL: u64 = 1;
+const RETRY_MULTIPLIER: u32 = 2;
+const EMBEDDING_BATCH_SIZE: usize = 100;
+const RUN_POLL_INTERVAL_MS: u64 = 500;
+const MAX_RUN_POLL_INTERVAL_MS: u64 = 5000;
+const CORRELATION_HEADER: &str = "x-correlation-id";
+const VOICE_PREVIEW_TEXT: &str = "The quick brown fox jumps over the lazy dog.";
 
-#[derive(Debug, Clone, Builder)]
+#[derive(Clone, Builder)]
+#[builder(build_fn(validate = "Self::validate"))]
 pub struct LlmSDK {
-    #[builder(setter(into), default = r#""https://api.openai.com/v1".into()"#)]
+    #[builder(setter(into), default = "DEFAULT_BASE_URL.into()")]
     pub(crate) base_url: String,
     #[builder(setter(into))]
-    pub(crate) token: String,
-    #[allow(dead_code)]
+    pub(crate) token: Sensitive<String>,
     #[builder(default = "3")]
     pub(crate) max_retries: u32,
-    #[builder(setter(skip), default = "self.default_client()")]
+    /// The longest wait before a retry. Exponential backoff without a `Retry-After` would
+    /// otherwise stall a single call for minutes during an outage. Waits are jittered below the
+    /// cap. Defaults to 20 seconds.
+    #[builder(default = "Duration::from_secs(MAX_RETRY_INTERVAL)")]
+    pub(crate) max_retry_interval: Duration,
+    /// The wait before the first retry, multiplied by `retry_multiplier` for each further
+    /// retry up to `max_retry_interval`. Defaults to 1 second.
+    #[builder(default = "Duration::from_secs(RETRY_BASE_INTERVAL)")]
+    pub(crate) retry_base_interval: Duration,
+    /// The factor the wait grows by from one retry to the next, 1 for a constant wait. Defaults
+    /// to 2.
+    #[builder(default = "RETRY_MULTIPLIER")]
+    pub(crate) retry_multiplier: u32,
+    /// Append every JSON request to this file, see `replay` to send them again. Requests are
+    /// written by a background thread, so the file may lag slightly behind the requests sent.
+    #[builder(default, setter(strip_option, into))]
+    pub(crate) record_to: Option<PathBuf>,
+    #[builder(
+        setter(skip),
+        default = "self.record_to.clone().flatten().map(Recorder::new)"
+    )]
+    pub(crate) recorder: Option<Recorder>,
+    /// Send a correlation id in this header with every request and log it together with the
+    /// `x-request-id` of the response. A new id is generated per request unless one is set with
+    /// `LlmSDK::with_correlation_id`.
+    #[builder(default, setter(strip_option, into))]
+    pub(crate) correlation_header: Option<String>,
+    #[builder(setter(skip))]
+    pub(crate) correlation_id: Option<String>,
+    /// Headers added to every request, replacing those set by the SDK. See
+    /// `LlmSDK::with_headers`.
+    #[builder(setter(skip))]
+    pub(crate) headers: HeaderMap,
+    /// The end-user id sent as `user` with every request that doesn't set its own.
+    #[builder(default, setter(strip_option, into))]
+    pub(crate) default_user: Option<String>,
+    /// Sampling parameters for chat requests that don't set their own.
+    #[builder(default)]
+    pub(crate) sampling_defaults: SamplingDefaults,
+    /// The `encoding_format` of embedding requests that don't set their own, e.g. base64 to
+    /// halve the response size. `EmbeddingResponse` decodes either format. Float by default.
+    #[builder(default, setter(strip_option))]
+    pub(crate) default_embedding_format: Option<EmbeddingEncodingFormat>,
+    /// Waits between retries. Replace it to test retries without real delays.
+    #[builder(default = "Arc::new(TokioSleeper)")]
+    pub(crate) sleeper: Arc<dyn Sleeper>,
+    /// Decides whether an error reported in the body of a success response is retried, see
+    /// `LlmSDKBuilder::should_retry`.
+    #[builder(default, setter(custom))]
+    pub(crate) should_retry: Option<RetryPredicate>,
+    /// Beta features sent in the `OpenAI-Beta` header, see `LlmSDKBuilder::enable_beta`.
+    #[builder(default, setter(custom))]
+    pub(crate) beta_features: Vec<BetaFeature>,
+    /// Extra trusted root certificates, e.g. of a corporate PKI. See
+    /// `LlmSDKBuilder::add_root_certificate`.
+    #[builder(default, setter(custom))]
+    pub(crate) root_certificates: Vec<reqwest::Certificate>,
+    /// Client certificate for mTLS.
+    #[builder(default, setter(strip_option))]
+    pub(crate) identity: Option<reqwest::Identity>,
+    /// Don't verify the server certificate. Only use this for development.
+    #[builder(default)]
+    pub(crate) danger_accept_invalid_certs: bool,
+    /// Sign requests with HMAC for gateways that require it, see `LlmSDKBuilder::sign_requests`.
+    #[builder(default, setter(custom))]
+    pub(crate) signing: Option<SigningMiddleware>,
+    /// The number of redirects to follow, 0 to follow none. A redirect that isn't followed is
+    /// returned as `LlmError::ApiError`. Gateways that redirect unauthenticated requests to a
+    /// login page otherwise surface auth failures as an unexpected HTML body, and following a
+    /// redirect sends the request body to a host that wasn't configured. Defaults to 10.
+    #[builder(default = "MAX_REDIRECTS")]
+    pub(crate) max_redirects: usize,
+    /// The maximum number of requests in flight at once, shared by all clones of the client,
+    /// e.g. to protect a small self-hosted inference server. Must be at least 1. Unlimited by
+    /// default.
+    #[builder(default, setter(strip_option))]
+    pub(crate) max_concurrency: Option<usize>,
+    /// Send identical requests that are in flight at the same time only once and give all
+    /// callers the same response, e.g. for a hot prompt in a busy server. Unlike a cache, a
+    /// request is sent again once the previous one has finished. Only JSON responses are
+    /// shared, so streamed completions and audio reach every caller as they are downloaded.
+    /// Off by default.
+    #[builder(default)]
+    pub(crate) single_flight: bool,
+    /// The total timeout of requests to endpoints without their own timeout, from sending the
+    /// request until the whole response was read. Raise it for long generations.
+    #[builder(default = "Duration::from_secs(TIMEOUT)")]
+    pub(crate) timeout: Duration,
+    /// The timeout of establishing a connection, so unreachable endpoints fail fast regardless
+    /// of `timeout`. Defaults to 10 seconds.
+    #[builder(default = "Duration::from_secs(CONNECT_TIMEOUT)")]
+    pub(crate) connect_timeout: Duration,
+    /// Timeouts per endpoint, see `LlmSDKBuilder::endpoint_timeout`. Image generation defaults
+    /// to 120 seconds.
+    #[builder(default = "default_endpoint_timeouts()", setter(custom))]
+    pub(crate) endpoint_timeouts: HashMap<EndpointKind, Duration>,
+    /// Base urls per endpoint for gateways that route endpoints to different hosts, see
+    /// `LlmSDKBuilder::endpoint_base_url`.
+    #[builder(default, setter(custom))]
+    pub(crate) endpoint_base_urls: HashMap<EndpointKind, String>,
+    #[builder(setter(skip), default = "self.default_client()?")]
     pub(crate) client: ClientWithMiddleware,
+    /// Downloads generated images from third-party hosts, without the auth, signing, retries
+    /// and other middleware meant for the API.
+    #[builder(setter(skip), default = "self.download_client()?")]
+    pub(crate) download_client: ClientWithMiddleware,
+}
+
+/// Beta features that must be opted into with the `OpenAI-Beta` header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BetaFeature {
+    AssistantsV2,
+    RealtimeV1,
+}
+
+impl BetaFeature {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BetaFeature::AssistantsV2 => "assistants=v2",
+            BetaFeature::RealtimeV1 => "realtime=v1",
+        }
+    }
+}
+
+/// A predicate on API errors, see `LlmSDKBuilder::should_retry`.
+pub type RetryPredicate = Arc<dyn Fn(&LlmError) -> bool + Send + Sync>;
+
+/// The endpoint categories that can be configured separately, e.g. with
+/// `LlmSDKBuilder::endpoint_timeout`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EndpointKind {
+    Chat,
+    Image,
+    Speech,
+    Whisper,
+    Embedding,
+    Moderation,
 }
 
 pub trait IntoRequest {
     fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder;
+
+    /// The endpoint category of the request, used to pick per-endpoint settings.
+    fn endpoint_kind(&self) -> Option<EndpointKind> {
+        None
+    }
+
+    /// Set the end-user id for abuse monitoring unless the request already has one. Requests
+    /// without a `user` field ignore it.
+    fn set_default_user(&mut self, _user: &str) {}
+
+    /// Apply `defaults` to the sampling parameters the request leaves unset. Requests without
+    /// sampling parameters ignore it.
+    fn set_sampling_defaults(&mut self, _defaults: &SamplingDefaults) {}
+
+    /// Set the embedding encoding format unless the request already has one. Requests other
+    /// than embeddings ignore it.
+    fn set_default_embedding_format(&mut self, _format: EmbeddingEncodingFormat) {}
+
+    /// The beta feature the endpoint requires. It is added to the `OpenAI-Beta` header whether
+    /// or not it was enabled on the client, and can be replaced with `LlmSDK::with_headers`.
+    fn beta_feature(&self) -> Option<BetaFeature> {
+        None
+    }
+}
+
+/// Sampling parameters applied to every chat request that doesn't set its own, see
+/// `LlmSDKBuilder::sampling_defaults`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SamplingDefaults {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<usize>,
 }
 
 /// For tool function. If you have a function that you want ChatGPT to call, you shall put
@@ -42,118 +257,1056 @@ pub trait ToSchema: JsonSchema {
     fn to_schema() -> serde_json::Value;
 }
 
+fn default_endpoint_timeouts() -> HashMap<EndpointKind, Duration> {
+    HashMap::from([(EndpointKind::Image, Duration::from_secs(IMAGE_TIMEOUT))])
+}
+
 impl LlmSDKBuilder {
-    fn default_client(&self) -> ClientWithMiddleware {
-        let retry_policy = ExponentialBackoff::builder()
-            .build_with_max_retries(self.max_retries.unwrap_or(MAX_RETRIES));
+    /// Use `timeout` for requests to `kind` endpoints instead of the global timeout.
+    pub fn endpoint_timeout(&mut self, kind: EndpointKind, timeout: Duration) -> &mut Self {
+        self.endpoint_timeouts
+            .get_or_insert_with(default_endpoint_timeouts)
+            .insert(kind, timeout);
+        self
+    }
+
+    /// Send requests to `kind` endpoints to `base_url` instead of the global base url, e.g. when
+    /// a gateway serves embeddings from another host than chat.
+    pub fn endpoint_base_url(
+        &mut self,
+        kind: EndpointKind,
+        base_url: impl Into<String>,
+    ) -> &mut Self {
+        self.endpoint_base_urls
+            .get_or_insert_with(HashMap::new)
+            .insert(kind, base_url.into());
+        self
+    }
+
+    /// Shorthand for `endpoint_base_url(EndpointKind::Chat, base_url)`.
+    pub fn chat_base_url(&mut self, base_url: impl Into<String>) -> &mut Self {
+        self.endpoint_base_url(EndpointKind::Chat, base_url)
+    }
+
+    /// Shorthand for `endpoint_base_url(EndpointKind::Embedding, base_url)`.
+    pub fn embedding_base_url(&mut self, base_url: impl Into<String>) -> &mut Self {
+        self.endpoint_base_url(EndpointKind::Embedding, base_url)
+    }
+
+    /// Trust `cert` in addition to the built-in root certificates.
+    pub fn add_root_certificate(&mut self, cert: reqwest::Certificate) -> &mut Self {
+        self.root_certificates
+            .get_or_insert_with(Vec::new)
+            .push(cert);
+        self
+    }
+
+    /// Send `feature` in the `OpenAI-Beta` header of every request.
+    pub fn enable_beta(&mut self, feature: BetaFeature) -> &mut Self {
+        let features = self.beta_features.get_or_insert_with(Vec::new);
+        if !features.contains(&feature) {
+            features.push(feature);
+        }
+        self
+    }
+
+    /// Sign every request for an API gateway: `header` is set to the hex encoded HMAC-SHA256,
+    /// keyed with `secret`, of the method, the path with query and the body, separated by
+    /// newlines, e.g. `POST\n/v1/chat/completions\n{...}`. The signature is computed after
+    /// retries are decided, so every attempt is signed. Only requests to the base urls are
+    /// signed, never downloads from other hosts. An upload streamed from
+    /// `WhisperRequest::from_stream` can't be read before it is sent, so sending one fails with
+    /// `LlmError::InvalidRequest`.
+    pub fn sign_requests(
+        &mut self,
+        secret: impl Into<Vec<u8>>,
+        header: impl Into<String>,
+    ) -> &mut Self {
+        self.signing = Some(Some(SigningMiddleware::new(secret.into(), header.into())));
+        self
+    }
+
+    /// Retry errors that are reported in the body of a success response, as some OpenAI
+    /// compatible providers do when they are overloaded, if `predicate` returns true for them.
+    /// Error statuses are retried by the HTTP client regardless of this setting. The
+    /// predicate receives an `LlmError::ApiError`, and the retries are limited by `max_retries`.
+    pub fn should_retry(
+        &mut self,
+        predicate: impl Fn(&LlmError) -> bool + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.should_retry = Some(Some(Arc::new(predicate)));
+        self
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.max_concurrency == Some(Some(0)) {
+            return Err("max_concurrency must be at least 1".into());
+        }
+        Ok(())
+    }
+
+    fn download_client(&self) -> Result<ClientWithMiddleware, String> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(
+                self.connect_timeout
+                    .unwrap_or(Duration::from_secs(CONNECT_TIMEOUT)),
+            )
+            .build()
+            .map_err(|e| format!("can't build the download client: {e}"))?;
+        Ok(ClientBuilder::new(client).build())
+    }
+
+    /// Fails if the TLS settings are invalid, e.g. a malformed root certificate or identity.
+    fn default_client(&self) -> Result<ClientWithMiddleware, String> {
+        let retry_policy = retry_policy(
+            self.max_retries.unwrap_or(MAX_RETRIES),
+            self.retry_base_interval
+                .unwrap_or(Duration::from_secs(RETRY_BASE_INTERVAL)),
+            self.retry_multiplier.unwrap_or(RETRY_MULTIPLIER),
+            self.max_retry_interval
+                .unwrap_or(Duration::from_secs(MAX_RETRY_INTERVAL)),
+        );
         info!("init client");
-        let m = RetryTransientMiddleware::new_with_policy(retry_policy);
-        ClientBuilder::new(
-            reqwest::Client::builder()
-                .build()
-                .unwrap(),
-        )
-        // Trace HTTP requests. See the tracing crate to make use of these traces.
-        .with(TracingMiddleware::default())
+        let sleeper = self
+            .sleeper
+            .clone()
+            .unwrap_or_else(|| Arc::new(TokioSleeper));
+        let m = RetryMiddleware::new(retry_policy, sleeper);
+        let mut builder = reqwest::Client::builder().connect_timeout(
+            self.connect_timeout
+                .unwrap_or(Duration::from_secs(CONNECT_TIMEOUT)),
+        );
+        for cert in self.root_certificates.iter().flatten() {
+            builder = builder.add_root_certificate(cert.clone());
+        }
+        if let Some(Some(identity)) = &self.identity {
+            builder = builder.identity(identity.clone());
+        }
+        if self.danger_accept_invalid_certs == Some(true) {
+            if !cfg!(test) {
+                warn!("TLS certificate verification is disabled, never use this in production");
+            }
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        builder = match self.max_redirects.unwrap_or(MAX_REDIRECTS) {
+            0 => builder.redirect(reqwest::redirect::Policy::none()),
+            max => builder.redirect(reqwest::redirect::Policy::limited(max)),
+        };
+        let client = builder
+            .build()
+            .map_err(|e| format!("can't build the client: {e}"))?;
+        let client = ClientBuilder::new(client)
+            // Trace HTTP requests. See the tracing crate to make use of these traces.
+            .with(TracingMiddleware::default());
+        // Share the outcome of the request including its retries.
+        let client = match self.single_flight {
+            Some(true) => {
+                let correlation_header = self.correlation_header.clone().flatten();
+                let mut volatile_headers = vec![correlation_header
+                    .as_deref()
+                    .unwrap_or(CORRELATION_HEADER)
+                    .to_owned()];
+                if let Some(Some(signing)) = &self.signing {
+                    volatile_headers.push(signing.header().to_owned());
+                }
+                let volatile_headers = volatile_headers
+                    .iter()
+                    .filter_map(|h| HeaderName::from_bytes(h.as_bytes()).ok())
+                    .collect();
+                client.with(SingleFlightMiddleware::new(volatile_headers))
+            }
+            _ => client,
+        };
         // Retry failed requests.
-        .with(RetryMiddleware::from(m))
-        .build()
+        let client = client.with(m);
+        // Limit each attempt rather than the whole retry loop, so backoff doesn't hold a slot.
+        let client = match self.max_concurrency.flatten() {
+            Some(max) => client.with(ConcurrencyLimitMiddleware::new(max)),
+            None => client,
+        };
+        match self.signing.clone().flatten() {
+            // Sign each attempt of a request.
+            Some(signing) => {
+                let base_url = self.base_url.as_deref().unwrap_or(DEFAULT_BASE_URL);
+                let base_urls = self
+                    .endpoint_base_urls
+                    .iter()
+                    .flat_map(|urls| urls.values());
+                let signing = signing
+                    .for_base_urls(std::iter::once(base_url).chain(base_urls.map(String::as_str)));
+                Ok(client.with(signing).build())
+            }
+            None => Ok(client.build()),
+        }
     }
 }
 
 impl LlmSDK {
     pub fn new(token: impl Into<String>) -> Self {
-        LlmSDKBuilder::default().token(token).build().unwrap()
+        LlmSDKBuilder::default()
+            .token(token.into())
+            .build()
+            .unwrap()
     }
 
     // fixme Method new1 can run to retry, but new can't
     pub fn new1(base_url: impl Into<String>, token: impl Into<String>, max_retries: u32) -> Self {
-        let retry_policy = ExponentialBackoff::builder().build_with_max_retries(max_retries);
-        let client = ClientBuilder::new(Client::new())
+        let retry_policy = retry_policy(
+            max_retries,
+            Duration::from_secs(RETRY_BASE_INTERVAL),
+            RETRY_MULTIPLIER,
+            Duration::from_secs(MAX_RETRY_INTERVAL),
+        );
+        let sleeper: Arc<dyn Sleeper> = Arc::new(TokioSleeper);
+        let inner = Client::builder()
+            .connect_timeout(Duration::from_secs(CONNECT_TIMEOUT))
+            .build()
+            .unwrap();
+        let client = ClientBuilder::new(inner)
             .with(TracingMiddleware::default())
-            .with(RetryTransientMiddleware::new_with_policy(retry_policy))
+            .with(RetryMiddleware::new(retry_policy, sleeper.clone()))
             .build();
 
         Self {
             base_url: base_url.into(),
-            token: token.into(),
+            token: Sensitive::new(token.into()),
             max_retries: 3,
+            max_retry_interval: Duration::from_secs(MAX_RETRY_INTERVAL),
+            retry_base_interval: Duration::from_secs(RETRY_BASE_INTERVAL),
+            retry_multiplier: RETRY_MULTIPLIER,
+            record_to: None,
+            recorder: None,
+            correlation_header: None,
+            correlation_id: None,
+            headers: HeaderMap::new(),
+            default_user: None,
+            sampling_defaults: SamplingDefaults::default(),
+            default_embedding_format: None,
+            sleeper,
+            should_retry: None,
+            signing: None,
+            beta_features: vec![],
+            root_certificates: vec![],
+            identity: None,
+            danger_accept_invalid_certs: false,
+            max_redirects: MAX_REDIRECTS,
+            max_concurrency: None,
+            single_flight: false,
+            timeout: Duration::from_secs(TIMEOUT),
+            connect_timeout: Duration::from_secs(CONNECT_TIMEOUT),
+            endpoint_timeouts: default_endpoint_timeouts(),
+            endpoint_base_urls: HashMap::new(),
             client,
+            download_client: LlmSDKBuilder::default().download_client().unwrap(),
         }
     }
 
     pub fn new_with_base_url(token: impl Into<String>, base_url: impl Into<String>) -> Self {
         LlmSDKBuilder::default()
-            .token(token)
+            .token(token.into())
             .base_url(base_url)
             .build()
             .unwrap()
     }
 
+    /// Return a client that sends `correlation_id` with all its requests, e.g. the trace id of
+    /// the incoming request being served. Uses `x-correlation-id` unless another header was
+    /// configured with `correlation_header`.
+    pub fn with_correlation_id(&self, correlation_id: impl Into<String>) -> Self {
+        Self {
+            correlation_id: Some(correlation_id.into()),
+            ..self.clone()
+        }
+    }
+
+    /// Return a client that adds `headers` to all its requests, replacing headers of the same
+    /// name set by the SDK, e.g. `OpenAI-Beta: assistants=v2` for a single call. Headers from
+    /// earlier calls are kept unless overridden.
+    pub fn with_headers(&self, headers: HeaderMap) -> Self {
+        let mut sdk = self.clone();
+        sdk.headers.extend(headers);
+        sdk
+    }
+
+    /// Send a chat request. If the model rejects `max_tokens`, as newer models do, the request
+    /// is sent once more with `max_completion_tokens` instead.
     pub async fn chat_completion(
         &self,
-        req: ChatCompletionRequest,
+        mut req: ChatCompletionRequest,
     ) -> Result<ChatCompletionResponse> {
+        // the limit may come from the sampling defaults
+        req.set_sampling_defaults(&self.sampling_defaults);
+        // only a request with `max_tokens` can be corrected, so only those are kept for a retry
+        let retry = req.uses_max_tokens().then(|| req.clone());
+        match self.send_json(self.prepare_request(req)).await {
+            Err(e) if is_max_tokens_unsupported(&e) => {
+                let Some(mut retry) = retry else {
+                    return Err(e);
+                };
+                retry.use_max_completion_tokens();
+                warn!("the model rejected max_tokens, retrying with max_completion_tokens; set max_completion_tokens to avoid the extra request");
+                self.send_json(self.prepare_request(retry)).await
+            }
+            res => res,
+        }
+    }
+
+    /// Stream the completion as it is generated. Feed the chunks into a `StreamAccumulator` to
+    /// reassemble the messages, or use `StreamAccumulator::collect_lossy` to keep the partial
+    /// output if the connection drops before the model finishes.
+    pub async fn chat_completion_stream(
+        &self,
+        mut req: ChatCompletionRequest,
+    ) -> Result<BoxStream<'static, Result<ChatCompletionChunk>>> {
+        req.stream = Some(true);
         let req = self.prepare_request(req);
         let res = req.send_and_log().await?;
-        Ok(res.json::<ChatCompletionResponse>().await?)
+        let chunks = sse::sse_data(res.bytes_stream())
+            .map(|data| Ok(serde_json::from_str::<ChatCompletionChunk>(&data?)?));
+        Ok(chunks.boxed())
+    }
+
+    /// Stream the reply text of the first choice into `writer` as it is generated, e.g. to proxy
+    /// a completion to a client. Returns the number of bytes written. If the connection drops,
+    /// the partial reply has been written and `LlmError::StreamInterrupted` is returned.
+    pub async fn chat_stream_to_writer(
+        &self,
+        req: ChatCompletionRequest,
+        mut writer: impl AsyncWrite + Unpin,
+    ) -> Result<u64> {
+        let mut chunks = self.chat_completion_stream(req).await?;
+        let mut written = 0;
+        while let Some(chunk) = chunks.next().await {
+            for choice in chunk?.choices.into_iter().filter(|c| c.index == 0) {
+                if let Some(content) = choice.delta.content {
+                    writer.write_all(content.as_bytes()).await?;
+                    written += content.len() as u64;
+                }
+            }
+        }
+        writer.flush().await?;
+        Ok(written)
+    }
+
+    /// Send a system and a user message with the default model and return the reply text.
+    pub async fn complete(&self, system: &str, user: &str) -> Result<String> {
+        let messages = vec![
+            ChatCompletionMessage::new_system(system, ""),
+            ChatCompletionMessage::new_user(user, ""),
+        ];
+        let req = ChatCompletionRequest::new(ChatCompleteModel::default(), messages);
+        first_content(self.chat_completion(req).await?)
+    }
+
+    /// Like `complete`, but sends the prompt to `preferred_model` only if it fits in its context
+    /// window with room for the reply, and otherwise to the first model of `fallback_chain` that
+    /// fits. Returns the response with the model that was used, or
+    /// `LlmError::ContextWindowExceeded` if no model fits.
+    pub async fn complete_auto_model(
+        &self,
+        system: &str,
+        user: &str,
+        preferred_model: ChatCompleteModel,
+        fallback_chain: Vec<ChatCompleteModel>,
+    ) -> Result<(ChatCompletionResponse, ChatCompleteModel)> {
+        let messages = vec![
+            ChatCompletionMessage::new_system(system, ""),
+            ChatCompletionMessage::new_user(user, ""),
+        ];
+        let models = std::iter::once(preferred_model).chain(fallback_chain);
+        let (req, model) = ChatCompletionRequest::for_first_fitting_model(messages, models)?;
+        debug!("completing with {:?}", model);
+        Ok((self.chat_completion(req).await?, model))
+    }
+
+    /// Extract a `T` from `text` using JSON mode. The JSON schema of `T` is appended to
+    /// `instructions` in the system prompt.
+    pub async fn extract<T: DeserializeOwned + JsonSchema>(
+        &self,
+        instructions: &str,
+        text: &str,
+    ) -> Result<T> {
+        let system = format!("{}\n{}", instructions, schema_prompt::<T>());
+        let messages = vec![
+            ChatCompletionMessage::new_system(system, ""),
+            ChatCompletionMessage::new_user(text, ""),
+        ];
+        let req = ChatCompletionRequestBuilder::default()
+            .messages(messages)
+            .response_format(ChatResponseFormatObject::new(ChatResponseFormat::Json))
+            .build()?;
+        let content = json_content(self.chat_completion(req).await?)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    /// Send `req` and parse the reply as a `T`. The JSON schema of `T` is added to the messages
+    /// as a system message. If the reply is not valid JSON for `T`, the error is sent back to the
+    /// model to correct it, up to `max_attempts` calls in total.
+    pub async fn chat_completion_typed_with_retry<T: DeserializeOwned + JsonSchema>(
+        &self,
+        mut req: ChatCompletionRequest,
+        max_attempts: u32,
+    ) -> Result<T> {
+        if max_attempts == 0 {
+            return Err(LlmError::InvalidRequest("max_attempts must be at least 1".into()).into());
+        }
+        req.push_message(ChatCompletionMessage::new_system(schema_prompt::<T>(), ""));
+        let mut last_error = String::new();
+        let mut raw_output = String::new();
+        for attempt in 1..=max_attempts {
+            // truncated output would be truncated again, so it isn't sent back to the model
+            let content = json_content(self.chat_completion(req.clone()).await?)?;
+            match serde_json::from_str::<T>(&content) {
+                Ok(value) => return Ok(value),
+                Err(e) => {
+                    debug!("structured output attempt {} failed: {}", attempt, e);
+                    req.push_message(ChatCompletionMessage::new_assistant(content.clone(), ""));
+                    req.push_message(ChatCompletionMessage::new_user(
+                        format!(
+                            "Your previous response was invalid JSON. Error: {}. Please try again.",
+                            e
+                        ),
+                        "",
+                    ));
+                    last_error = e.to_string();
+                    raw_output = content;
+                }
+            }
+        }
+        Err(LlmError::MaxRetryAttemptsExceeded {
+            last_error,
+            raw_output,
+        }
+        .into())
+    }
+
+    /// Embed `texts` in batches and return the vectors in input order.
+    pub(crate) async fn embed_texts(&self, texts: Vec<String>) -> Result<Vec<Vec<f32>>> {
+        let mut embeddings = Vec::with_capacity(texts.len());
+        for batch in texts.chunks(EMBEDDING_BATCH_SIZE) {
+            let res = self
+                .embedding(EmbeddingRequest::new_array(batch.to_vec()))
+                .await?;
+            let res: EmbeddingResponse = serde_json::from_slice(&res)?;
+            embeddings.extend(res.into_vectors());
+        }
+        Ok(embeddings)
     }
 
     pub async fn create_image(&self, req: CreateImageRequest) -> Result<CreateImageResponse> {
+        req.validate()?;
         let req = self.prepare_request(req);
-        let res = req.send().await?;
-        Ok(res.json::<CreateImageResponse>().await?)
+        self.send_json(req).await
     }
 
-    pub async fn speech(&self, req: SpeechRequest) -> Result<Bytes> {
+    /// Edit the areas of an image that are transparent in the mask.
+    pub async fn edit_image(&self, req: EditImageRequest) -> Result<CreateImageResponse> {
+        req.validate()?;
         let req = self.prepare_request(req);
-        let res = req.send_and_log().await?;
+        self.send_json(req).await
+    }
+
+    /// Create an image, retrying once with `fallback_prompt` if the prompt is rejected by the
+    /// content policy. Returns `LlmError::ContentFiltered` if there is no fallback prompt.
+    pub async fn create_image_safe(
+        &self,
+        req: CreateImageRequest,
+        fallback_prompt: Option<&str>,
+    ) -> Result<CreateImageResponse> {
+        match self.create_image(req.clone()).await {
+            Err(e) if is_content_policy_violation(&e) => match fallback_prompt {
+                Some(prompt) => {
+                    info!("image prompt rejected, retrying with fallback prompt");
+                    let mut req = req;
+                    req.prompt = prompt.into();
+                    self.create_image(req).await
+                }
+                None => Err(LlmError::ContentFiltered.into()),
+            },
+            res => res,
+        }
+    }
+
+    /// Create an image from `prompt`. If the prompt is rejected by the content policy, ask GPT
+    /// to rewrite it into a safer version and retry once.
+    pub async fn create_image_with_auto_fallback(
+        &self,
+        prompt: &str,
+    ) -> Result<CreateImageResponse> {
+        match self.create_image(CreateImageRequest::new(prompt)).await {
+            Err(e) if is_content_policy_violation(&e) => {
+                let safer = self.rewrite_rejected_image_prompt(prompt).await?;
+                info!("image prompt rejected, retrying with: {}", safer);
+                self.create_image_safe(CreateImageRequest::new(safer), None)
+                    .await
+            }
+            res => res,
+        }
+    }
+
+    /// Fetch the bytes of a generated image, decoding `b64_json` or downloading `url`. The
+    /// download is sent without the API token, signing or retries, as the url points to a CDN.
+    pub async fn download_image(&self, image: &ImageObject) -> Result<Bytes> {
+        if let Some(data) = &image.b64_json {
+            return Ok(STANDARD.decode(data)?.into());
+        }
+        let url = image
+            .url
+            .as_deref()
+            .ok_or_else(|| anyhow!("image has neither url nor b64_json"))?;
+        let res = self
+            .download_client
+            .get(url)
+            .timeout(self.timeout_for(Some(EndpointKind::Image)))
+            .send_and_log()
+            .await?;
         Ok(res.bytes().await?)
     }
 
-    pub async fn whisper(&self, req: WhisperRequest) -> Result<WhisperResponse> {
+    /// Generate `reqs` with at most `concurrency` requests in flight and download each image as
+    /// soon as it is ready. Yields `(request_index, image_bytes)` in completion order, once per
+    /// image of a request.
+    pub fn create_image_download_stream(
+        &self,
+        reqs: Vec<CreateImageRequest>,
+        concurrency: usize,
+    ) -> impl Stream<Item = Result<(usize, Bytes)>> + '_ {
+        stream::iter(reqs.into_iter().enumerate())
+            .map(move |(index, req)| {
+                stream::once(self.create_image(req))
+                    .flat_map(move |res| match res {
+                        Ok(res) => stream::iter(res.data)
+                            .then(move |image| async move {
+                                Ok((index, self.download_image(&image).await?))
+                            })
+                            .left_stream(),
+                        Err(e) => stream::once(async move { Err(e) }).right_stream(),
+                    })
+                    .boxed()
+            })
+            .flatten_unordered(concurrency.max(1))
+    }
+
+    async fn rewrite_rejected_image_prompt(&self, prompt: &str) -> Result<String> {
+        self.complete("You rewrite image generation prompts that were rejected by a content policy. Keep the subject and intent, remove anything that may violate the policy, and reply with the rewritten prompt only.", prompt).await
+    }
+
+    /// Embed an image with a CLIP-compatible model.
+    ///
+    /// OpenAI doesn't expose image embeddings, but providers like Together AI and Replicate host
+    /// CLIP models. Point the SDK to such a provider with `LlmSDK::new_with_base_url`, and set the
+    /// endpoint path on the request if it isn't `embeddings`.
+    #[cfg(feature = "multimodal-embeddings")]
+    pub async fn embed_image(&self, req: ImageEmbeddingRequest) -> Result<Vec<f32>> {
+        let req = self.prepare_request(req);
+        self.send_json::<EmbeddingResponse>(req)
+            .await?
+            .into_single()
+            .ok_or_else(|| anyhow!("no embedding returned"))
+    }
+
+    /// The cosine similarity of `text` and `image`, both embedded with the model and endpoint of
+    /// the image request, for text-image matching. Pass an `ImageInput` to use the default CLIP
+    /// model at `embeddings`.
+    #[cfg(feature = "multimodal-embeddings")]
+    pub async fn multimodal_similarity(
+        &self,
+        text: &str,
+        image: impl Into<ImageEmbeddingRequest>,
+    ) -> Result<f32> {
+        let image = image.into();
+        let req = self.prepare_request(image.text_request(text));
+        let text = self
+            .send_json::<EmbeddingResponse>(req)
+            .await?
+            .into_single()
+            .ok_or_else(|| anyhow!("no embedding returned"))?;
+        let image = self.embed_image(image).await?;
+        Ok(cosine_similarity(&text, &image))
+    }
+
+    pub async fn list_models(&self) -> Result<ModelList> {
+        let req = self.prepare_request(ListModelsRequest);
+        self.send_json(req).await
+    }
+
+    /// Generate audio for the request. Errors come back as `LlmError::ApiError`, including those
+    /// sent as a JSON body with a success status, so they are never returned as audio.
+    pub async fn speech(&self, req: SpeechRequest) -> Result<Bytes> {
+        let req = self.prepare_request(req);
+        Ok(self.send_checked(req).await?.body)
+    }
+
+    /// Like `speech`, but yields the audio as it is downloaded. Dropping the stream closes the
+    /// connection and stops the download, e.g. when the listener navigates away.
+    ///
+    /// The timeout of `EndpointKind::Speech` covers the whole download, not just the wait for
+    /// the first byte, so raise it with `LlmSDKBuilder::endpoint_timeout` for long narrations.
+    pub async fn speech_stream(
+        &self,
+        req: SpeechRequest,
+    ) -> Result<BoxStream<'static, Result<Bytes>>> {
+        let res = self.prepare_request(req).send_and_log().await?;
+        let status = res.status();
+        let json = res
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("application/json"));
+        if json {
+            // audio is never JSON, so this is an error sent with a success status
+            let body = res.bytes().await?;
+            return Err(LlmError::from_success_body(status, &body)
+                .unwrap_or_else(|| LlmError::from_response(status, &String::from_utf8_lossy(&body)))
+                .into());
+        }
+        Ok(res.bytes_stream().map(|chunk| Ok(chunk?)).boxed())
+    }
+
+    /// Like `speech`, but writes the audio to `writer` as it arrives, e.g. a `tokio::fs::File` or
+    /// a socket, instead of holding it in memory. Returns the number of bytes written. The
+    /// timeout covers the whole download, see `speech_stream`.
+    pub async fn speech_to_writer(
+        &self,
+        req: SpeechRequest,
+        mut writer: impl AsyncWrite + Unpin,
+    ) -> Result<u64> {
+        let mut written = 0;
+        let mut body = self.speech_stream(req).await?;
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk?;
+            writer.write_all(&chunk).await?;
+            written += chunk.len() as u64;
+        }
+        writer.flush().await?;
+        Ok(written)
+    }
+
+    /// Create a custom voice from an audio sample, to be used with `SpeechVoice::Custom`.
+    ///
+    /// This is forward-looking: OpenAI has not released a custom voice endpoint yet, so this
+    /// always returns `LlmError::NotYetAvailable`. The endpoint URL (assumed to be
+    /// `POST /audio/voices`) will need updating once it is released.
+    pub async fn create_voice(
+        &self,
+        _name: &str,
+        _description: &str,
+        _audio_sample: Vec<u8>,
+    ) -> Result<CustomVoice> {
+        Err(LlmError::NotYetAvailable("custom voice creation (POST /audio/voices)").into())
+    }
+
+    /// The voices available for speech, e.g. for a voice picker. OpenAI has no endpoint for
+    /// this, so the built-in voices are returned without a request.
+    pub async fn list_voices(&self) -> Result<Vec<VoiceInfo>> {
+        Ok(VoiceInfo::openai_voices())
+    }
+
+    /// Speak a standard test sentence with `voice`, for previewing voices.
+    pub async fn preview_voice(&self, voice: SpeechVoice) -> Result<Bytes> {
+        let req = SpeechRequestBuilder::default()
+            .input(VOICE_PREVIEW_TEXT)
+            .voice(voice)
+            .build()?;
+        self.speech(req).await
+    }
+
+    pub async fn whisper(&self, mut req: WhisperRequest) -> Result<WhisperResponse> {
+        req.claim_stream()?;
         let is_json = req.response_format == WhisperResponseFormat::Json;
         let req = self.prepare_request(req);
-        let res = req.send_and_log().await?;
+        let body = self.send_checked(req).await?.body;
         let ret = if is_json {
-            res.json::<WhisperResponse>().await?
+            serde_json::from_slice(&body)?
         } else {
-            let text = res.text().await?;
-            WhisperResponse { text }
+            WhisperResponse {
+                text: String::from_utf8_lossy(&body).into_owned(),
+            }
         };
         Ok(ret)
     }
 
-    pub async fn embedding(&self, req: EmbeddingRequest) -> Result<Bytes> {
+    /// Stream the transcript as it is generated, with finalized segments for models that send
+    /// them. Whisper-1 doesn't support streaming, use a model like gpt-4o-transcribe. The stream
+    /// ends after `TranscriptionEvent::Done`.
+    pub async fn whisper_stream(
+        &self,
+        mut req: WhisperRequest,
+    ) -> Result<BoxStream<'static, Result<TranscriptionEvent>>> {
+        req.claim_stream()?;
+        req.stream_events = true;
         let req = self.prepare_request(req);
         let res = req.send_and_log().await?;
-        Ok(res.bytes().await?)
+        let events = sse::sse_data(res.bytes_stream())
+            .map(|data| Ok(serde_json::from_str::<TranscriptionEvent>(&data?)?))
+            .scan(false, |done, event| {
+                let event = (!*done).then_some(event);
+                *done = matches!(event, Some(Ok(TranscriptionEvent::Done { .. })));
+                futures::future::ready(event)
+            });
+        Ok(events.boxed())
+    }
+
+    /// Transcribe or translate once and return the output in each of `formats`.
+    ///
+    /// A single format is requested as is. For several formats the audio is sent once with
+    /// `verbose_json`, and `json`, `text`, `srt` and `vtt` are derived locally from its text and
+    /// timestamped segments, so this never costs more than one call.
+    pub async fn whisper_multi(
+        &self,
+        mut req: WhisperRequest,
+        formats: &[WhisperResponseFormat],
+    ) -> Result<HashMap<WhisperResponseFormat, WhisperOutput>> {
+        req.claim_stream()?;
+        let mut outputs = HashMap::new();
+        match formats {
+            [] => {}
+            [format] if *format != WhisperResponseFormat::VerboseJson => {
+                req.response_format = *format;
+                let res = self.whisper(req).await?;
+                let output = match format {
+                    WhisperResponseFormat::Json => WhisperOutput::Json(res),
+                    _ => WhisperOutput::Text(res.text),
+                };
+                outputs.insert(*format, output);
+            }
+            _ => {
+                req.response_format = WhisperResponseFormat::VerboseJson;
+                let req = self.prepare_request(req);
+                let verbose: WhisperVerboseResponse = self.send_json(req).await?;
+                for format in formats {
+                    outputs.insert(*format, verbose.to_output(*format));
+                }
+            }
+        }
+        Ok(outputs)
+    }
+
+    /// Classify text as potentially harmful. Moderation is free of charge.
+    pub async fn moderation(&self, req: ModerationRequest) -> Result<ModerationResponse> {
+        let req = self.prepare_request(req);
+        self.send_json(req).await
+    }
+
+    /// Run an assistant on a thread. Uses the Assistants beta.
+    pub async fn create_run(&self, req: CreateRunRequest) -> Result<Run> {
+        let req = self.prepare_request(req);
+        self.send_json(req).await
+    }
+
+    pub async fn retrieve_run(&self, req: RetrieveRunRequest) -> Result<Run> {
+        let req = self.prepare_request(req);
+        self.send_json(req).await
+    }
+
+    /// Run an assistant on a thread and poll the run, backing off from 0.5 up to 5 seconds,
+    /// until it stops. Check `Run::status`: a run that is `RequiresAction` waits for the
+    /// outputs of the tool calls in `Run::required_action`.
+    pub async fn create_and_poll_run(&self, thread_id: &str, assistant_id: &str) -> Result<Run> {
+        let mut run = self
+            .create_run(CreateRunRequest::new(thread_id, assistant_id))
+            .await?;
+        let mut interval = Duration::from_millis(RUN_POLL_INTERVAL_MS);
+        while !run.status.is_terminal() {
+            self.sleeper.sleep(interval).await;
+            interval = (interval * 2).min(Duration::from_millis(MAX_RUN_POLL_INTERVAL_MS));
+            run = self
+                .retrieve_run(RetrieveRunRequest::new(&run.thread_id, &run.id))
+                .await?;
+        }
+        Ok(run)
+    }
+
+    pub async fn embedding(&self, req: EmbeddingRequest) -> Result<Bytes> {
+        req.validate()?;
+        let req = self.prepare_request(req);
+        Ok(self.send_checked(req).await?.body)
+    }
+
+    /// Like `embedding`, but deserializes the vectors as `f64` to keep their full precision.
+    /// The encoding format is always set to float.
+    pub async fn embedding_f64(&self, mut req: EmbeddingRequest) -> Result<EmbeddingResponseF64> {
+        req.encoding_format = Some(EmbeddingEncodingFormat::Float);
+        Ok(serde_json::from_slice(&self.embedding(req).await?)?)
+    }
+
+    /// Like `embedding`, but requests the base64 encoding, which saves bandwidth for large
+    /// batches. Decode the vectors with `EmbeddingBase64Data::decode`.
+    pub async fn embedding_base64(
+        &self,
+        mut req: EmbeddingRequest,
+    ) -> Result<EmbeddingBase64Response> {
+        req.encoding_format = Some(EmbeddingEncodingFormat::Base64);
+        Ok(serde_json::from_slice(&self.embedding(req).await?)?)
+    }
+
+    /// Send any request and return the deserialized body together with the HTTP status and
+    /// headers, for callers that need more than the body.
+    pub async fn send_with_status<T: DeserializeOwned>(
+        &self,
+        req: impl IntoRequest,
+    ) -> Result<ApiResponse<T>> {
+        let req = self.prepare_request(req);
+        self.send_checked(req).await?.json()
+    }
+
+    async fn send_json<T: DeserializeOwned>(&self, req: RequestBuilder) -> Result<T> {
+        Ok(serde_json::from_slice(&self.send_checked(req).await?.body)?)
+    }
+
+    /// Send `req` and return the response if its body isn't an error object. Such errors are
+    /// retried with exponential backoff while `should_retry` accepts them.
+    async fn send_checked(&self, req: RequestBuilder) -> Result<ApiResponse<Bytes>> {
+        let policy = retry_policy(
+            self.max_retries,
+            self.retry_base_interval,
+            self.retry_multiplier,
+            self.max_retry_interval,
+        );
+        let start_time = SystemTime::now();
+        let mut n_past_retries = 0;
+        let mut req = req;
+        loop {
+            let retry = req.try_clone();
+            let res = ApiResponse::from_response(req.send_and_log().await?).await?;
+            let Some(err) = LlmError::from_success_body(res.status, &res.body) else {
+                return Ok(res);
+            };
+            let retryable = self.should_retry.as_ref().is_some_and(|f| f(&err));
+            let (Some(retry), true) = (retry, retryable) else {
+                return Err(err.into());
+            };
+            match policy.should_retry(start_time, n_past_retries) {
+                RetryDecision::Retry { execute_after } => {
+                    let duration = execute_after
+                        .duration_since(SystemTime::now())
+                        .unwrap_or_default();
+                    warn!(
+                        "API error in response body: {}, retrying in {:?}",
+                        err, duration
+                    );
+                    self.sleeper.sleep(duration).await;
+                    n_past_retries += 1;
+                    req = retry;
+                }
+                RetryDecision::DoNotRetry => return Err(err.into()),
+            }
+        }
     }
 
-    fn prepare_request(&self, req: impl IntoRequest) -> RequestBuilder {
-        let req = req.into_request(&self.base_url, self.client.clone());
-        let req = if self.token.is_empty() {
+    fn prepare_request(&self, mut req: impl IntoRequest) -> RequestBuilder {
+        if let Some(user) = &self.default_user {
+            req.set_default_user(user);
+        }
+        req.set_sampling_defaults(&self.sampling_defaults);
+        if let Some(format) = self.default_embedding_format {
+            req.set_default_embedding_format(format);
+        }
+        let kind = req.endpoint_kind();
+        let timeout = self.timeout_for(kind);
+        let base_url = self.base_url_for(kind);
+        let beta = self.beta_header(req.beta_feature());
+        let req = req.into_request(base_url, self.client.clone());
+        if let Some(recorder) = &self.recorder {
+            recorder.record(base_url, kind, &req);
+        }
+        let req = if self.token.expose().is_empty() {
             req
         } else {
-            req.bearer_auth(&self.token)
+            req.bearer_auth(self.token.expose())
                 .header("user-agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/129.0.0.0 Safari/537.36")
         };
-        req.timeout(Duration::from_secs(TIMEOUT))
+        let req = match beta {
+            Some(beta) => req.header("OpenAI-Beta", beta),
+            None => req,
+        };
+        let req = match self.correlation() {
+            Some((header, id)) => req.header(header, &id).with_extension(CorrelationId(id)),
+            None => req,
+        };
+        let req = if self.headers.is_empty() {
+            req
+        } else {
+            req.headers(self.headers.clone())
+        };
+        req.timeout(timeout)
+    }
+
+    fn timeout_for(&self, kind: Option<EndpointKind>) -> Duration {
+        kind.and_then(|kind| self.endpoint_timeouts.get(&kind))
+            .copied()
+            .unwrap_or(self.timeout)
+    }
+
+    fn base_url_for(&self, kind: Option<EndpointKind>) -> &str {
+        kind.and_then(|kind| self.endpoint_base_urls.get(&kind))
+            .unwrap_or(&self.base_url)
+    }
+
+    fn beta_header(&self, required: Option<BetaFeature>) -> Option<String> {
+        let mut features: Vec<_> = self.beta_features.iter().map(|f| f.as_str()).collect();
+        if let Some(required) = required {
+            if !self.beta_features.contains(&required) {
+                features.push(required.as_str());
+            }
+        }
+        if features.is_empty() {
+            return None;
+        }
+        Some(features.join(","))
+    }
+
+    fn correlation(&self) -> Option<(&str, String)> {
+        if self.correlation_header.is_none() && self.correlation_id.is_none() {
+            return None;
+        }
+        let header = self
+            .correlation_header
+            .as_deref()
+            .unwrap_or(CORRELATION_HEADER);
+        let id = self
+            .correlation_id
+            .clone()
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        Some((header, id))
+    }
+}
+
+/// Exponential backoff with bounded jitter, starting at `base_interval` and waiting at most
+/// `max_interval` between attempts.
+fn retry_policy(
+    max_retries: u32,
+    base_interval: Duration,
+    multiplier: u32,
+    max_interval: Duration,
+) -> ExponentialBackoff {
+    ExponentialBackoff::builder()
+        .retry_bounds(base_interval.min(max_interval), max_interval)
+        .base(multiplier)
+        .jitter(Jitter::Bounded)
+        .build_with_max_retries(max_retries)
+}
+
+/// The instruction to reply with JSON matching the schema of `T`, for JSON mode, which doesn't
+/// take a schema itself.
+pub(crate) fn schema_prompt<T: JsonSchema>() -> String {
+    format!(
+        "Respond with a JSON object that matches this JSON schema:\n{}",
+        T::to_schema()
+    )
+}
+
+pub(crate) fn first_content(res: ChatCompletionResponse) -> Result<String> {
+    res.choices
+        .into_iter()
+        .next()
+        .and_then(|choice| choice.message.content)
+        .ok_or_else(|| anyhow!("no content returned in chat completion"))
+}
+
+/// Like `first_content`, but fails with `LlmError::TruncatedJson` if the output was cut off by
+/// the token limit, rather than with a confusing parse error later.
+fn json_content(res: ChatCompletionResponse) -> Result<String> {
+    let completion_tokens = res.usage.completion_tokens;
+    let truncated = res
+        .choices
+        .first()
+        .is_some_and(|choice| choice.finish_reason == FinishReason::Length);
+    let content = first_content(res)?;
+    if truncated {
+        return Err(LlmError::TruncatedJson {
+            completion_tokens,
+            raw_output: content,
+        }
+        .into());
+    }
+    Ok(content)
+}
+
+impl fmt::Debug for LlmSDK {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LlmSDK")
+            .field("base_url", &self.base_url)
+            .field("token", &self.token)
+            .field("max_retries", &self.max_retries)
+            .field("max_retry_interval", &self.max_retry_interval)
+            .field("retry_base_interval", &self.retry_base_interval)
+            .field("retry_multiplier", &self.retry_multiplier)
+            .field("record_to", &self.record_to)
+            .field("correlation_header", &self.correlation_header)
+            .field("correlation_id", &self.correlation_id)
+            .field("headers", &self.headers.keys().collect::<Vec<_>>())
+            .field("default_user", &self.default_user)
+            .field("sampling_defaults", &self.sampling_defaults)
+            .field("default_embedding_format", &self.default_embedding_format)
+            .field("sleeper", &format_args!("[...]"))
+            .field("should_retry", &self.should_retry.is_some())
+            .field("beta_features", &self.beta_features)
+            .field("root_certificates", &self.root_certificates.len())
+            .field("identity", &self.identity.is_some())
+            .field(
+                "danger_accept_invalid_certs",
+                &self.danger_accept_invalid_certs,
+            )
+            .field(
+                "signing",
+                &self.signing.as_ref().map(SigningMiddleware::header),
+            )
+            .field("max_redirects", &self.max_redirects)
+            .field("max_concurrency", &self.max_concurrency)
+            .field("single_flight", &self.single_flight)
+            .field("timeout", &self.timeout)
+            .field("connect_timeout", &self.connect_timeout)
+            .field("endpoint_timeouts", &self.endpoint_timeouts)
+            .field("endpoint_base_urls", &self.endpoint_base_urls)
+            .field("client", &format_args!("[...]"))
+            .finish()
     }
 }
 
+#[derive(Debug, Clone)]
+pub(crate) struct CorrelationId(String);
+
 trait SendAndLog {
     async fn send_and_log(self) -> Result<Response>;
 }
 
 impl SendAndLog for RequestBuilder {
-    async fn send_and_log(self) -> Result<Response> {
+    async fn send_and_log(mut self) -> Result<Response> {
+        let correlation_id = self.extensions().get::<CorrelationId>().cloned();
         let res = self.send().await?;
         let status = res.status();
+        if let Some(CorrelationId(correlation_id)) = &correlation_id {
+            let request_id = res
+                .headers()
+                .get("x-request-id")
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("-");
+            info!(
+                "correlation id: {}, request id: {}, status: {}",
+                correlation_id, request_id, status
+            );
+        }
+        if status.is_redirection() {
+            let location = res
+                .headers()
+                .get("location")
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("-");
+            error!("API redirected to {}", location);
+            return Err(LlmError::ApiError {
+                status: status.as_u16(),
+                code: None,
+                r#type: None,
+                param: None,
+                message: format!("redirected to {}, see `max_redirects`", location),
+            }
+            .into());
+        }
         if status.is_client_error() || status.is_server_error() {
             let text = res.text().await?;
             error!("API failed: {}", text);
-            return Err(anyhow::anyhow!("API failed: {}", text));
+            return Err(LlmError::from_response(status, &text).into());
         }
         Ok(res)
     }
@@ -165,8 +1318,574 @@ impl<T: JsonSchema> ToSchema for T {
     }
 }
 
+#[cfg(test)]
+static SDK: once_cell::sync::Lazy<LlmSDK> =
+    once_cell::sync::Lazy::new(|| LlmSDK::new(std::env::var("OPENAI_API_KEY").unwrap_or_default()));
+
 #[cfg(test)]
 #[ctor::ctor]
 fn init() {
     tracing_subscriber::fmt::init();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[test]
+    fn correlation_should_use_supplied_or_generated_id() {
+        let sdk = LlmSDK::new("token");
+        assert!(sdk.correlation().is_none());
+
+        let traced = sdk.with_correlation_id("trace-1");
+        let (header, id) = traced.correlation().unwrap();
+        assert_eq!((header, id.as_str()), ("x-correlation-id", "trace-1"));
+
+        let sdk = LlmSDKBuilder::default()
+            .token("token")
+            .correlation_header("x-trace-id")
+            .build()
+            .unwrap();
+        let (header, id) = sdk.correlation().unwrap();
+        assert_eq!(header, "x-trace-id");
+        assert_ne!(id, sdk.correlation().unwrap().1);
+    }
+
+    #[test]
+    fn with_headers_should_override_sdk_headers() -> Result<()> {
+        let sdk = LlmSDKBuilder::default()
+            .token("token")
+            .enable_beta(BetaFeature::RealtimeV1)
+            .build()?;
+        let mut headers = HeaderMap::new();
+        headers.insert("OpenAI-Beta", "assistants=v2".parse()?);
+        headers.insert("x-tenant", "acme".parse()?);
+        let req = sdk
+            .with_headers(headers)
+            .prepare_request(ChatCompletionRequest::new(
+                ChatCompleteModel::Gpt4oMini,
+                vec![ChatCompletionMessage::new_user("hi", "")],
+            ))
+            .build()?;
+        let beta: Vec<_> = req.headers().get_all("OpenAI-Beta").iter().collect();
+        assert_eq!(beta, vec!["assistants=v2"]);
+        assert_eq!(req.headers()["x-tenant"], "acme");
+        assert!(sdk
+            .prepare_request(ModerationRequest::new("hi"))
+            .build()?
+            .headers()
+            .get("x-tenant")
+            .is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn retry_policy_should_cap_each_wait() {
+        let policy = retry_policy(20, Duration::from_secs(1), 2, Duration::from_secs(5));
+        let start = SystemTime::now();
+        for n_past_retries in [0, 5, 15] {
+            let RetryDecision::Retry { execute_after } = policy.should_retry(start, n_past_retries)
+            else {
+                panic!("expected a retry");
+            };
+            let wait = execute_after
+                .duration_since(SystemTime::now())
+                .unwrap_or_default();
+            assert!(wait <= Duration::from_secs(5), "waited {:?}", wait);
+        }
+    }
+
+    #[test]
+    fn retry_policy_should_use_base_interval_and_multiplier() {
+        let policy = retry_policy(20, Duration::from_millis(10), 1, Duration::from_secs(5));
+        let start = SystemTime::now();
+        for n_past_retries in [0, 5, 15] {
+            let RetryDecision::Retry { execute_after } = policy.should_retry(start, n_past_retries)
+            else {
+                panic!("expected a retry");
+            };
+            let wait = execute_after
+                .duration_since(SystemTime::now())
+                .unwrap_or_default();
+            assert!(wait <= Duration::from_millis(10), "waited {:?}", wait);
+        }
+    }
+
+    #[test]
+    fn beta_header_should_join_enabled_features() {
+        assert!(LlmSDK::new("token").beta_header(None).is_none());
+        let sdk = LlmSDKBuilder::default()
+            .token("token")
+            .enable_beta(BetaFeature::AssistantsV2)
+            .enable_beta(BetaFeature::RealtimeV1)
+            .enable_beta(BetaFeature::AssistantsV2)
+            .build()
+            .unwrap();
+        assert_eq!(
+            sdk.beta_header(None).as_deref(),
+            Some("assistants=v2,realtime=v1")
+        );
+        assert_eq!(
+            sdk.beta_header(Some(BetaFeature::AssistantsV2)).as_deref(),
+            Some("assistants=v2,realtime=v1")
+        );
+        assert_eq!(
+            LlmSDK::new("token")
+                .beta_header(Some(BetaFeature::AssistantsV2))
+                .as_deref(),
+            Some("assistants=v2")
+        );
+    }
+
+    #[test]
+    fn builder_should_reject_zero_max_concurrency() {
+        let err = LlmSDKBuilder::default()
+            .token("token")
+            .max_concurrency(0)
+            .build()
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("max_concurrency"));
+    }
+
+    #[test]
+    fn builder_should_apply_tls_settings() {
+        let sdk = LlmSDKBuilder::default()
+            .token("token")
+            .danger_accept_invalid_certs(true)
+            .build()
+            .unwrap();
+        let debug = format!("{:?}", sdk);
+        assert!(debug.contains("root_certificates: 0"));
+        assert!(debug.contains("danger_accept_invalid_certs: true"));
+    }
+
+    #[test]
+    fn builder_should_fail_on_invalid_root_certificate() {
+        let err = LlmSDKBuilder::default()
+            .token("token")
+            .add_root_certificate(reqwest::Certificate::from_der(b"not a certificate").unwrap())
+            .build()
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("can't build the client"));
+    }
+
+    #[tokio::test]
+    async fn endpoint_base_url_should_override_base_url() -> Result<()> {
+        let url = test_server::serve(vec![(200, test_server::chat_response("hi"))]);
+        let sdk = LlmSDKBuilder::default()
+            .token("token")
+            .base_url("http://127.0.0.1:1")
+            .chat_base_url(url)
+            .build()?;
+        assert_eq!(sdk.complete("system", "user").await?, "hi");
+        assert_eq!(sdk.base_url_for(None), "http://127.0.0.1:1");
+        assert_eq!(
+            sdk.base_url_for(Some(EndpointKind::Embedding)),
+            "http://127.0.0.1:1"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn timeout_should_fall_back_to_global_timeout() {
+        let sdk = LlmSDKBuilder::default()
+            .token("token")
+            .timeout(Duration::from_secs(10))
+            .endpoint_timeout(EndpointKind::Speech, Duration::from_secs(90))
+            .build()
+            .unwrap();
+        let timeout = |kind| sdk.timeout_for(Some(kind)).as_secs();
+        assert_eq!(timeout(EndpointKind::Speech), 90);
+        assert_eq!(timeout(EndpointKind::Image), IMAGE_TIMEOUT);
+        assert_eq!(timeout(EndpointKind::Embedding), 10);
+        assert_eq!(sdk.timeout_for(None).as_secs(), 10);
+    }
+
+    #[derive(Debug, Deserialize, JsonSchema)]
+    struct Answer {
+        answer: u32,
+    }
+
+    fn question() -> ChatCompletionRequest {
+        let messages = vec![ChatCompletionMessage::new_user("What is 6 * 7?", "")];
+        ChatCompletionRequest::new(ChatCompleteModel::default(), messages)
+    }
+
+    #[tokio::test]
+    async fn typed_retry_should_feed_back_parse_errors() -> Result<()> {
+        let (url, received) = test_server::serve_recording(vec![
+            (200, test_server::chat_response("{\"result\": 42}")),
+            (200, test_server::chat_response("{\"answer\": 42}")),
+        ]);
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let res: Answer = sdk.chat_completion_typed_with_retry(question(), 3).await?;
+        assert_eq!(res.answer, 42);
+        assert!(received.lock().unwrap()[0].contains("JSON schema"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn typed_retry_should_reject_zero_attempts() {
+        let sdk = LlmSDK::new_with_base_url("token", "http://localhost:1");
+        let err = sdk
+            .chat_completion_typed_with_retry::<Answer>(question(), 0)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LlmError>(),
+            Some(LlmError::InvalidRequest(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn typed_retry_should_give_up_after_max_attempts() {
+        let url = test_server::serve(vec![
+            (200, test_server::chat_response("not json")),
+            (200, test_server::chat_response("{}")),
+        ]);
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let err = sdk
+            .chat_completion_typed_with_retry::<Answer>(question(), 2)
+            .await
+            .unwrap_err();
+        match err.downcast_ref::<LlmError>() {
+            Some(LlmError::MaxRetryAttemptsExceeded { raw_output, .. }) => {
+                assert_eq!(raw_output, "{}")
+            }
+            _ => panic!("unexpected error {:?}", err),
+        }
+    }
+
+    #[tokio::test]
+    async fn chat_completion_should_retry_with_max_completion_tokens() -> Result<()> {
+        let rejected = r#"{"error":{"message":"Unsupported parameter: 'max_tokens' is not supported with this model. Use 'max_completion_tokens' instead.","type":"invalid_request_error","param":"max_tokens","code":"unsupported_parameter"}}"#;
+        let (url, received) = test_server::serve_recording(vec![
+            (400, rejected.to_owned()),
+            (200, test_server::chat_response("hi")),
+        ]);
+        let sdk = LlmSDKBuilder::default()
+            .token("token")
+            .base_url(url)
+            .sampling_defaults(SamplingDefaults {
+                max_tokens: Some(100),
+                ..Default::default()
+            })
+            .build()?;
+        let res = sdk.chat_completion(question()).await?;
+        assert_eq!(first_content(res)?, "hi");
+        let retried: serde_json::Value = serde_json::from_str(&received.lock().unwrap()[1])?;
+        assert_eq!(retried["max_completion_tokens"], 100);
+        assert!(retried.get("max_tokens").is_none());
+
+        // without a token limit there is nothing to correct
+        let url = test_server::serve(vec![(400, rejected.to_owned())]);
+        let err = LlmSDK::new_with_base_url("token", url)
+            .chat_completion(question())
+            .await
+            .unwrap_err();
+        assert!(is_max_tokens_unsupported(&err));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn typed_retry_should_report_truncated_output() {
+        let truncated =
+            test_server::chat_response("{\"answer\": ").replace("\"stop\"", "\"length\"");
+        let url = test_server::serve(vec![(200, truncated)]);
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let err = sdk
+            .chat_completion_typed_with_retry::<Answer>(question(), 3)
+            .await
+            .unwrap_err();
+        match err.downcast_ref::<LlmError>() {
+            Some(LlmError::TruncatedJson { raw_output, .. }) => {
+                assert_eq!(raw_output, "{\"answer\": ")
+            }
+            _ => panic!("unexpected error {:?}", err),
+        }
+    }
+
+    struct NoSleep;
+
+    #[async_trait::async_trait]
+    impl Sleeper for NoSleep {
+        async fn sleep(&self, _duration: Duration) {}
+    }
+
+    #[tokio::test]
+    async fn create_and_poll_run_should_poll_until_terminal() -> Result<()> {
+        let run = |status: &str| {
+            serde_json::json!({
+                "id": "run_1",
+                "thread_id": "thread_1",
+                "assistant_id": "asst_1",
+                "status": status
+            })
+            .to_string()
+        };
+        let url = test_server::serve(vec![
+            (200, run("queued")),
+            (200, run("in_progress")),
+            (200, run("completed")),
+        ]);
+        let sdk = LlmSDKBuilder::default()
+            .token("token")
+            .base_url(url)
+            .sleeper(Arc::new(NoSleep))
+            .build()?;
+        let run = sdk.create_and_poll_run("thread_1", "asst_1").await?;
+        assert_eq!(run.status, RunStatus::Completed);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn should_retry_should_retry_errors_in_success_body() -> Result<()> {
+        let overloaded = r#"{"error":{"message":"overloaded","type":"server_error"}}"#;
+        let url = test_server::serve(vec![
+            (200, overloaded.to_owned()),
+            (200, test_server::chat_response("hi")),
+        ]);
+        let sdk = LlmSDKBuilder::default()
+            .token("token")
+            .base_url(url)
+            .sleeper(Arc::new(NoSleep))
+            .should_retry(
+                |e| matches!(e, LlmError::ApiError { message, .. } if message == "overloaded"),
+            )
+            .build()?;
+        assert_eq!(sdk.complete("system", "user").await?, "hi");
+
+        let url = test_server::serve(vec![(200, overloaded.to_owned())]);
+        let err = LlmSDK::new_with_base_url("token", url)
+            .complete("system", "user")
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LlmError>(),
+            Some(LlmError::ApiError { status: 200, .. })
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn image_download_stream_should_yield_decoded_images() -> Result<()> {
+        let image = json!({"created": 1700000000, "data": [{"b64_json": "aGVsbG8="}]});
+        let url = test_server::serve(vec![(200, image.to_string())]);
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let images: Vec<_> = sdk
+            .create_image_download_stream(vec![CreateImageRequest::new("a cat")], 2)
+            .collect()
+            .await;
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].as_ref().unwrap(), &(0, Bytes::from("hello")));
+
+        let url = test_server::serve(vec![(200, "png".into())]);
+        let image = ImageObject {
+            b64_json: None,
+            url: Some(format!("{}/image.png", url)),
+            revised_prompt: String::new(),
+        };
+        assert_eq!(sdk.download_image(&image).await?, "png");
+
+        // the API retries don't apply to downloads
+        let url = test_server::serve(vec![(503, String::new()), (200, "png".into())]);
+        let image = ImageObject {
+            url: Some(format!("{}/image.png", url)),
+            ..image
+        };
+        assert!(sdk.download_image(&image).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn redirect_should_be_an_error_when_not_followed() {
+        let url = test_server::serve(vec![(302, String::new())]);
+        let sdk = LlmSDKBuilder::default()
+            .token("token")
+            .base_url(url)
+            .max_redirects(0)
+            .build()
+            .unwrap();
+        let err = sdk.list_models().await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LlmError>(),
+            Some(LlmError::ApiError { status: 302, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn speech_should_return_typed_errors() {
+        let error = json!({"error": {
+            "message": "Input should have at most 4096 characters",
+            "type": "invalid_request_error",
+            "code": "string_above_max_length"
+        }})
+        .to_string();
+        let url = test_server::serve(vec![(400, error.clone()), (200, error)]);
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        for status in [400, 200] {
+            let err = sdk.speech(SpeechRequest::new("hello")).await.unwrap_err();
+            match err.downcast_ref::<LlmError>() {
+                Some(LlmError::ApiError {
+                    status: s, code, ..
+                }) => {
+                    assert_eq!(*s, status);
+                    assert_eq!(code.as_deref(), Some("string_above_max_length"));
+                }
+                _ => panic!("unexpected error {:?}", err),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn chat_stream_to_writer_should_write_the_deltas() -> Result<()> {
+        let chunk = |content: &str| {
+            json!({
+                "id": "chatcmpl-1",
+                "object": "chat.completion.chunk",
+                "created": 1700000000,
+                "model": "gpt-4o-mini",
+                "choices": [{"index": 0, "delta": {"content": content}}]
+            })
+        };
+        let body = format!(
+            "data: {}\n\ndata: {}\n\ndata: [DONE]\n\n",
+            chunk("Hello"),
+            chunk(" there")
+        );
+        let url = test_server::serve(vec![(200, body)]);
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let mut output = Vec::new();
+        let written = sdk.chat_stream_to_writer(question(), &mut output).await?;
+        assert_eq!(output, b"Hello there");
+        assert_eq!(written, 11);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn speech_to_writer_should_write_the_audio() -> Result<()> {
+        let audio = vec![0xff, 0xfb, 0x90, 0x00, 1, 2, 3];
+        let url = test_server::serve_audio(audio.clone());
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let req = SpeechRequestBuilder::default().input("hello").build()?;
+        let mut output = Vec::new();
+        let written = sdk.speech_to_writer(req, &mut output).await?;
+        assert_eq!(output, audio);
+        assert_eq!(written, audio.len() as u64);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn speech_to_writer_should_not_write_errors() {
+        let body = r#"{"error":{"message":"The server is overloaded","type":"server_error"}}"#;
+        let url = test_server::serve(vec![(200, body.to_owned())]);
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let req = SpeechRequestBuilder::default()
+            .input("hello")
+            .build()
+            .unwrap();
+        let mut output = Vec::new();
+        let err = sdk.speech_to_writer(req, &mut output).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LlmError>(),
+            Some(LlmError::ApiError { status: 200, .. })
+        ));
+        assert!(output.is_empty());
+    }
+
+    #[tokio::test]
+    async fn dropping_speech_stream_should_close_the_connection() -> Result<()> {
+        use std::io::{BufRead, BufReader, Read, Write};
+
+        // streams audio chunks until the client hangs up
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let (closed_tx, closed_rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                match line.trim_end().split_once(':') {
+                    Some((name, value)) if name.eq_ignore_ascii_case("content-length") => {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                    None if line.trim_end().is_empty() => break,
+                    _ => {}
+                }
+            }
+            reader.read_exact(&mut vec![0; content_length]).unwrap();
+            let mut stream = reader.into_inner();
+            let head =
+                "HTTP/1.1 200 OK\r\ncontent-type: audio/mpeg\r\ntransfer-encoding: chunked\r\n\r\n";
+            stream.write_all(head.as_bytes()).unwrap();
+            let chunk = format!("400\r\n{}\r\n", "a".repeat(0x400));
+            for _ in 0..10_000 {
+                if stream.write_all(chunk.as_bytes()).is_err() {
+                    closed_tx.send(()).unwrap();
+                    return;
+                }
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        });
+
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let req = SpeechRequestBuilder::default()
+            .input("hello")
+            .build()
+            .unwrap();
+        let mut audio = sdk.speech_stream(req).await?;
+        assert!(!audio.next().await.unwrap()?.is_empty());
+        drop(audio);
+        // keep the runtime free, the connection is closed by a background task
+        let closed = tokio::task::spawn_blocking(move || {
+            closed_rx.recv_timeout(Duration::from_secs(5)).is_ok()
+        });
+        assert!(closed.await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn whisper_stream_should_yield_deltas_and_segments() -> Result<()> {
+        let body = [
+            json!({"type": "transcript.text.delta", "delta": "Hello"}),
+            json!({"type": "transcript.text.segment", "id": "seg_0", "start": 0.0, "end": 1.2, "text": "Hello there.", "speaker": "A"}),
+            json!({"type": "transcript.text.done", "text": "Hello there."}),
+        ]
+        .iter()
+        .map(|event| format!("data: {}\n\n", event))
+        .collect::<String>();
+        let url = test_server::serve(vec![(200, body)]);
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let events: Vec<_> = sdk
+            .whisper_stream(WhisperRequest::transcription(b"audio".to_vec()))
+            .await?
+            .collect()
+            .await;
+        assert_eq!(events.len(), 3);
+        assert_eq!(
+            events[0].as_ref().unwrap(),
+            &TranscriptionEvent::TextDelta {
+                delta: "Hello".into()
+            }
+        );
+        match events[1].as_ref().unwrap() {
+            TranscriptionEvent::Segment(segment) => {
+                assert_eq!((segment.start, segment.end), (0.0, 1.2));
+                assert_eq!(segment.speaker.as_deref(), Some("A"));
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        assert!(matches!(
+            events[2].as_ref().unwrap(),
+            TranscriptionEvent::Done { .. }
+        ));
+        Ok(())
+    }
+}
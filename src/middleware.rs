@@ -1,10 +1,365 @@
+use crate::{LlmError, Sensitive};
+use anyhow::anyhow;
+use bytes::Bytes;
+use hmac::{Hmac, Mac};
 use http::Extensions;
-use reqwest::{header, Request, Response};
-use reqwest_middleware::{Middleware, Next, Result};
-use reqwest_retry::{policies::ExponentialBackoff, RetryTransientMiddleware};
+use http_body_util::BodyExt;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{header, Request, Response, StatusCode};
+use reqwest_middleware::{Error, Middleware, Next, Result};
+use reqwest_retry::{
+    default_on_request_failure, default_on_request_success, policies::ExponentialBackoff,
+    RetryDecision, RetryPolicy, Retryable,
+};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use tokio::sync::{watch, Semaphore};
+use tracing::warn;
+
+/// The time source used to wait between retries. Inject your own with
+/// `LlmSDKBuilder::sleeper` to test retry behavior without real delays.
+#[async_trait::async_trait]
+pub trait Sleeper: Send + Sync {
+    async fn sleep(&self, duration: Duration);
+}
+
+/// Sleeps with `tokio::time::sleep`, so it respects `tokio::time::pause` in tests.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TokioSleeper;
+
+#[async_trait::async_trait]
+impl Sleeper for TokioSleeper {
+    async fn sleep(&self, duration: Duration) {
+        tokio::time::sleep(duration).await
+    }
+}
+
+/// Signs every request to the configured API hosts with HMAC-SHA256 for gateways that require
+/// it, see `LlmSDKBuilder::sign_requests`. Requests to other hosts, e.g. image downloads from a
+/// CDN, are sent unsigned.
+#[derive(Clone)]
+pub(crate) struct SigningMiddleware {
+    secret: Sensitive<Vec<u8>>,
+    header: String,
+    /// The host and port of every base url, see `for_base_urls`.
+    hosts: Vec<(String, Option<u16>)>,
+}
+
+impl SigningMiddleware {
+    pub(crate) fn new(secret: Vec<u8>, header: String) -> Self {
+        Self {
+            secret: Sensitive::new(secret),
+            header,
+            hosts: Vec::new(),
+        }
+    }
+
+    /// Only sign requests to the hosts of `base_urls`.
+    pub(crate) fn for_base_urls<'a>(
+        mut self,
+        base_urls: impl IntoIterator<Item = &'a str>,
+    ) -> Self {
+        self.hosts = base_urls
+            .into_iter()
+            .filter_map(|url| reqwest::Url::parse(url).ok())
+            .filter_map(|url| Some((url.host_str()?.to_owned(), url.port_or_known_default())))
+            .collect();
+        self
+    }
+
+    pub(crate) fn header(&self) -> &str {
+        &self.header
+    }
+
+    fn signs(&self, url: &reqwest::Url) -> bool {
+        self.hosts.iter().any(|(host, port)| {
+            url.host_str() == Some(host.as_str()) && url.port_or_known_default() == *port
+        })
+    }
+
+    /// Read a body of known length into memory, so it can be signed. reqwest sends every
+    /// multipart form as a stream, but only a form with a streamed part, e.g. of
+    /// `WhisperRequest::from_stream`, has no content length.
+    async fn buffer_sized_body(req: &mut Request) -> Result<()> {
+        let streamed = req.body().is_some_and(|body| body.as_bytes().is_none());
+        if !streamed || !req.headers().contains_key(header::CONTENT_LENGTH) {
+            return Ok(());
+        }
+        if let Some(body) = req.body_mut().take() {
+            let bytes = body.collect().await.map_err(Error::Reqwest)?.to_bytes();
+            *req.body_mut() = Some(bytes.into());
+        }
+        Ok(())
+    }
+
+    /// The hex encoded HMAC-SHA256 of `{method}\n{path and query}\n{body}`, or `None` if the
+    /// body is streamed and can't be read without sending it.
+    fn signature(&self, req: &Request) -> Option<String> {
+        let url = req.url();
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_owned(),
+        };
+        let body = match req.body() {
+            Some(body) => body.as_bytes()?,
+            None => &[],
+        };
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.expose())
+            .expect("HMAC accepts keys of any length");
+        mac.update(req.method().as_str().as_bytes());
+        mac.update(b"\n");
+        mac.update(path.as_bytes());
+        mac.update(b"\n");
+        mac.update(body);
+        Some(hex::encode(mac.finalize().into_bytes()))
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for SigningMiddleware {
+    async fn handle(
+        &self,
+        mut req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        if !self.signs(req.url()) {
+            return next.run(req, extensions).await;
+        }
+        Self::buffer_sized_body(&mut req).await?;
+        let signature = self.signature(&req).ok_or_else(|| {
+            Error::Middleware(
+                LlmError::InvalidRequest(
+                    "can't sign a streamed request body, e.g. of `WhisperRequest::from_stream`"
+                        .into(),
+                )
+                .into(),
+            )
+        })?;
+        let signature =
+            HeaderValue::from_str(&signature).map_err(|e| Error::Middleware(anyhow!(e)))?;
+        let name = HeaderName::from_bytes(self.header.as_bytes())
+            .map_err(|e| Error::Middleware(anyhow!(e)))?;
+        req.headers_mut().insert(name, signature);
+        next.run(req, extensions).await
+    }
+}
+
+/// Caps the number of requests in flight across all clones of a client, see
+/// `LlmSDKBuilder::max_concurrency`. A permit is held from sending until the response headers
+/// arrive, so a streamed body doesn't occupy a slot while it is read.
+#[derive(Clone)]
+pub(crate) struct ConcurrencyLimitMiddleware {
+    semaphore: Arc<Semaphore>,
+}
+
+impl ConcurrencyLimitMiddleware {
+    pub(crate) fn new(max_concurrency: usize) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(max_concurrency)),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for ConcurrencyLimitMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        let _permit = self
+            .semaphore
+            .acquire()
+            .await
+            .map_err(|e| Error::Middleware(anyhow!(e)))?;
+        next.run(req, extensions).await
+    }
+}
+
+/// Coalesces identical requests in flight at the same time, see
+/// `LlmSDKBuilder::single_flight`. The first request is sent and the others wait for its
+/// response, which is buffered and handed to all of them. Requests are identical if their
+/// method, url, headers and body match, apart from the headers in `volatile_headers` that differ
+/// per request, e.g. the correlation id. Only JSON responses are shared: streamed request
+/// bodies are never coalesced, and other responses such as event streams or audio are handed
+/// back unbuffered, so the waiters send their own request.
+#[derive(Clone, Default)]
+pub(crate) struct SingleFlightMiddleware {
+    in_flight: Arc<Mutex<HashMap<[u8; 32], FlightReceiver>>>,
+    volatile_headers: Arc<Vec<HeaderName>>,
+}
+
+type FlightReceiver = watch::Receiver<Option<Flight>>;
+
+/// The outcome of the request the waiters are waiting for.
+#[derive(Clone)]
+enum Flight {
+    Shared(std::result::Result<BufferedResponse, String>),
+    /// The response can't be shared, every waiter sends its own request.
+    NotShared,
+}
+
+#[derive(Clone)]
+struct BufferedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+/// Removes the in-flight entry when the first request finishes or is cancelled. Waiters of a
+/// cancelled request see the channel close and send their own.
+struct FlightGuard<'a> {
+    middleware: &'a SingleFlightMiddleware,
+    key: [u8; 32],
+}
+
+impl Drop for FlightGuard<'_> {
+    fn drop(&mut self) {
+        self.middleware.in_flight.lock().unwrap().remove(&self.key);
+    }
+}
+
+impl SingleFlightMiddleware {
+    pub(crate) fn new(volatile_headers: Vec<HeaderName>) -> Self {
+        Self {
+            in_flight: Default::default(),
+            volatile_headers: Arc::new(volatile_headers),
+        }
+    }
+
+    /// The SHA-256 of the method, url, headers except the volatile ones and body, or `None` if
+    /// the body is streamed.
+    fn key(&self, req: &Request) -> Option<[u8; 32]> {
+        let body = match req.body() {
+            Some(body) => body.as_bytes()?,
+            None => &[],
+        };
+        let mut hasher = Sha256::new();
+        hasher.update(req.method().as_str().as_bytes());
+        hasher.update(b"\n");
+        hasher.update(req.url().as_str().as_bytes());
+        hasher.update(b"\n");
+        let mut headers: Vec<_> = req
+            .headers()
+            .iter()
+            .filter(|(name, _)| !self.volatile_headers.contains(name))
+            .collect();
+        headers.sort_by_key(|(name, value)| (name.as_str(), value.as_bytes()));
+        for (name, value) in headers {
+            hasher.update(name.as_str().as_bytes());
+            hasher.update(b":");
+            hasher.update(value.as_bytes());
+            hasher.update(b"\n");
+        }
+        hasher.update(b"\n");
+        hasher.update(body);
+        Some(hasher.finalize().into())
+    }
+
+    async fn lead(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+        tx: watch::Sender<Option<Flight>>,
+        _guard: FlightGuard<'_>,
+    ) -> Result<Response> {
+        let res = match next.run(req, extensions).await {
+            Ok(res) => res,
+            Err(e) => {
+                tx.send_replace(Some(Flight::Shared(Err(e.to_string()))));
+                return Err(e);
+            }
+        };
+        let json = res
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("application/json"));
+        if !json {
+            tx.send_replace(Some(Flight::NotShared));
+            return Ok(res);
+        }
+        let status = res.status();
+        let headers = res.headers().clone();
+        let body = match res.bytes().await {
+            Ok(body) => body,
+            Err(e) => {
+                tx.send_replace(Some(Flight::Shared(Err(e.to_string()))));
+                return Err(e.into());
+            }
+        };
+        let res = BufferedResponse {
+            status,
+            headers,
+            body,
+        };
+        tx.send_replace(Some(Flight::Shared(Ok(res.clone()))));
+        Ok(res.into_response())
+    }
+}
+
+impl BufferedResponse {
+    fn into_response(self) -> Response {
+        let mut res = http::Response::new(self.body);
+        *res.status_mut() = self.status;
+        *res.headers_mut() = self.headers;
+        res.into()
+    }
+}
+
+#[async_trait::async_trait]
+impl Middleware for SingleFlightMiddleware {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        let Some(key) = self.key(&req) else {
+            return next.run(req, extensions).await;
+        };
+        let waiting = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.get(&key) {
+                Some(rx) => Err(rx.clone()),
+                None => {
+                    let (tx, rx) = watch::channel(None);
+                    in_flight.insert(key, rx);
+                    Ok(tx)
+                }
+            }
+        };
+        let mut rx = match waiting {
+            Ok(tx) => {
+                let guard = FlightGuard {
+                    middleware: self,
+                    key,
+                };
+                return self.lead(req, extensions, next, tx, guard).await;
+            }
+            Err(rx) => rx,
+        };
+        let flight = rx
+            .wait_for(Option::is_some)
+            .await
+            .ok()
+            .and_then(|f| f.clone());
+        match flight {
+            Some(Flight::Shared(Ok(res))) => Ok(res.into_response()),
+            Some(Flight::Shared(Err(e))) => Err(Error::Middleware(anyhow!(e))),
+            Some(Flight::NotShared) | None => next.run(req, extensions).await,
+        }
+    }
+}
 
 pub(crate) struct RetryMiddleware {
-    inner: RetryTransientMiddleware<ExponentialBackoff> ,
+    policy: ExponentialBackoff,
+    sleeper: Arc<dyn Sleeper>,
 }
 
 #[async_trait::async_trait]
@@ -25,19 +380,347 @@ impl Middleware for RetryMiddleware {
                     next.run(req, extensions).await
                 } else {
                     // what about other content types? But at least for OpenAI APIs, we only see multipart/form-data as non-retryable
-                    self.inner.handle(req, extensions, next).await
+                    self.execute_with_retry(req, extensions, next).await
                 }
             }
             _ => {
                 // does this mean, no body?
-                self.inner.handle(req, extensions, next).await
+                self.execute_with_retry(req, extensions, next).await
             }
         }
     }
 }
 
-impl From<RetryTransientMiddleware<ExponentialBackoff>> for RetryMiddleware {
-    fn from(inner: RetryTransientMiddleware<ExponentialBackoff>) -> Self {
-        Self { inner }
+impl RetryMiddleware {
+    pub(crate) fn new(policy: ExponentialBackoff, sleeper: Arc<dyn Sleeper>) -> Self {
+        Self { policy, sleeper }
+    }
+
+    async fn execute_with_retry(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> Result<Response> {
+        let start_time = SystemTime::now();
+        let mut n_past_retries = 0;
+        loop {
+            let duplicate = req.try_clone().ok_or_else(|| {
+                Error::Middleware(anyhow!("request is not cloneable, is the body streamed?"))
+            })?;
+            let result = next.clone().run(duplicate, extensions).await;
+            let retryable = match &result {
+                Ok(res) => default_on_request_success(res),
+                Err(e) => default_on_request_failure(e),
+            };
+            if retryable != Some(Retryable::Transient) {
+                return result;
+            }
+            match self.policy.should_retry(start_time, n_past_retries) {
+                RetryDecision::Retry { execute_after } => {
+                    let duration = execute_after
+                        .duration_since(SystemTime::now())
+                        .unwrap_or_default();
+                    warn!(
+                        "Retry attempt #{}. Sleeping {:?} before the next attempt",
+                        n_past_retries, duration
+                    );
+                    self.sleeper.sleep(duration).await;
+                    n_past_retries += 1;
+                }
+                RetryDecision::DoNotRetry => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::serve;
+    use reqwest_middleware::ClientBuilder;
+    use std::sync::atomic::{AtomicUsize, Ordering::SeqCst};
+
+    #[derive(Default)]
+    struct RecordingSleeper {
+        sleeps: Mutex<Vec<Duration>>,
+    }
+
+    #[async_trait::async_trait]
+    impl Sleeper for RecordingSleeper {
+        async fn sleep(&self, duration: Duration) {
+            self.sleeps.lock().unwrap().push(duration);
+        }
+    }
+
+    async fn send(statuses: Vec<u16>, max_retries: u32) -> (u16, Vec<Duration>) {
+        let url = serve(statuses.into_iter().map(|s| (s, String::new())).collect());
+        let sleeper = Arc::new(RecordingSleeper::default());
+        let policy = ExponentialBackoff::builder().build_with_max_retries(max_retries);
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(RetryMiddleware::new(policy, sleeper.clone()))
+            .build();
+        let res = client.get(url).send().await.unwrap();
+        let sleeps = sleeper.sleeps.lock().unwrap().clone();
+        (res.status().as_u16(), sleeps)
+    }
+
+    #[tokio::test]
+    async fn transient_error_should_retry_once() {
+        let (status, sleeps) = send(vec![503, 200], 3).await;
+        assert_eq!(status, 200);
+        assert_eq!(sleeps.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn client_error_should_not_retry() {
+        let (status, sleeps) = send(vec![400], 3).await;
+        assert_eq!(status, 400);
+        assert!(sleeps.is_empty());
+    }
+
+    #[tokio::test]
+    async fn retries_should_stop_at_max_retries() {
+        let (status, sleeps) = send(vec![500, 500, 500], 2).await;
+        assert_eq!(status, 500);
+        assert_eq!(sleeps.len(), 2);
+    }
+
+    /// Answers every request itself after a short delay, recording the peak number in flight
+    /// and the number of requests.
+    #[derive(Default)]
+    struct Counts {
+        current: AtomicUsize,
+        peak: AtomicUsize,
+        total: AtomicUsize,
+    }
+
+    struct InFlight(Arc<Counts>);
+
+    #[async_trait::async_trait]
+    impl Middleware for InFlight {
+        async fn handle(
+            &self,
+            _req: Request,
+            _extensions: &mut Extensions,
+            _next: Next<'_>,
+        ) -> Result<Response> {
+            self.0.total.fetch_add(1, SeqCst);
+            let current = self.0.current.fetch_add(1, SeqCst) + 1;
+            self.0.peak.fetch_max(current, SeqCst);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            self.0.current.fetch_sub(1, SeqCst);
+            let mut res = http::Response::new("");
+            res.headers_mut().insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            );
+            Ok(res.into())
+        }
+    }
+
+    /// Replaces the content type of the response.
+    struct ContentType(&'static str);
+
+    #[async_trait::async_trait]
+    impl Middleware for ContentType {
+        async fn handle(
+            &self,
+            req: Request,
+            extensions: &mut Extensions,
+            next: Next<'_>,
+        ) -> Result<Response> {
+            let mut res = next.run(req, extensions).await?;
+            res.headers_mut()
+                .insert(header::CONTENT_TYPE, HeaderValue::from_static(self.0));
+            Ok(res)
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrency_limit_should_bound_requests_in_flight() {
+        let counts = Arc::new(Counts::default());
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(ConcurrencyLimitMiddleware::new(2))
+            .with(InFlight(counts.clone()))
+            .build();
+        let requests = (0..6).map(|_| client.get("http://localhost/").send());
+        for res in futures::future::join_all(requests).await {
+            res.unwrap();
+        }
+        assert_eq!(counts.peak.load(SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn single_flight_should_coalesce_identical_requests() {
+        let counts = Arc::new(Counts::default());
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(SingleFlightMiddleware::default())
+            .with(InFlight(counts.clone()))
+            .build();
+        let send = |body: &'static str| client.post("http://localhost/").body(body).send();
+        let requests = vec![send("a"), send("a"), send("a"), send("b")];
+        for res in futures::future::join_all(requests).await {
+            assert_eq!(res.unwrap().status(), 200);
+        }
+        assert_eq!(counts.peak.load(SeqCst), 2);
+
+        // requests that don't overlap are sent again
+        client
+            .post("http://localhost/")
+            .body("a")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(counts.total.load(SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn single_flight_should_not_share_audio() {
+        let counts = Arc::new(Counts::default());
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(SingleFlightMiddleware::default())
+            .with(ContentType("audio/mpeg"))
+            .with(InFlight(counts.clone()))
+            .build();
+        let send = || client.post("http://localhost/").body("a").send();
+        for res in futures::future::join_all(vec![send(), send()]).await {
+            assert_eq!(res.unwrap().status(), 200);
+        }
+        assert_eq!(counts.total.load(SeqCst), 2);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn single_flight_should_only_ignore_volatile_headers() {
+        let counts = Arc::new(Counts::default());
+        let volatile = HeaderName::from_static("x-correlation-id");
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(SingleFlightMiddleware::new(vec![volatile]))
+            .with(InFlight(counts.clone()))
+            .build();
+        let send = |tenant: &'static str, correlation_id: &'static str| {
+            client
+                .post("http://localhost/")
+                .header("x-tenant", tenant)
+                .header("x-correlation-id", correlation_id)
+                .body("a")
+                .send()
+        };
+        let requests = vec![send("t1", "1"), send("t1", "2"), send("t2", "3")];
+        for res in futures::future::join_all(requests).await {
+            assert_eq!(res.unwrap().status(), 200);
+        }
+        assert_eq!(counts.total.load(SeqCst), 2);
+    }
+
+    #[test]
+    fn signature_should_cover_method_path_and_body() {
+        let signing = SigningMiddleware::new(b"secret".to_vec(), "x-signature".into());
+        let req = reqwest::Client::new()
+            .post("https://gateway.internal/v1/chat/completions")
+            .body(r#"{"a":1}"#)
+            .build()
+            .unwrap();
+        assert_eq!(
+            signing.signature(&req).unwrap(),
+            "19081117c3c4abcaef128e289125ed7f403332d1c0b3aa281e0bd1525d24522c"
+        );
+    }
+
+    /// Answers every request itself, recording its headers.
+    struct Headers(Arc<Mutex<Vec<HeaderMap>>>);
+
+    #[async_trait::async_trait]
+    impl Middleware for Headers {
+        async fn handle(
+            &self,
+            req: Request,
+            _extensions: &mut Extensions,
+            _next: Next<'_>,
+        ) -> Result<Response> {
+            self.0.lock().unwrap().push(req.headers().clone());
+            Ok(http::Response::new("").into())
+        }
+    }
+
+    /// Answers every request itself, keeping the request.
+    struct Received(Arc<Mutex<Vec<Request>>>);
+
+    #[async_trait::async_trait]
+    impl Middleware for Received {
+        async fn handle(
+            &self,
+            req: Request,
+            _extensions: &mut Extensions,
+            _next: Next<'_>,
+        ) -> Result<Response> {
+            self.0.lock().unwrap().push(req);
+            Ok(http::Response::new("").into())
+        }
+    }
+
+    #[tokio::test]
+    async fn signing_should_sign_multipart_forms() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let signing = SigningMiddleware::new(b"secret".to_vec(), "x-signature".into())
+            .for_base_urls(["https://gateway.internal/v1"]);
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(signing.clone())
+            .with(Received(received.clone()))
+            .build();
+        let form = reqwest::multipart::Form::new()
+            .text("model", "whisper-1")
+            .part("file", reqwest::multipart::Part::bytes(b"audio".to_vec()));
+        client
+            .post("https://gateway.internal/v1/audio/transcriptions")
+            .multipart(form)
+            .send()
+            .await
+            .unwrap();
+        let received = received.lock().unwrap();
+        let body = received[0].body().and_then(|body| body.as_bytes()).unwrap();
+        assert!(String::from_utf8_lossy(body).contains("whisper-1"));
+        assert_eq!(
+            received[0].headers()["x-signature"],
+            signing.signature(&received[0]).unwrap().as_str()
+        );
+    }
+
+    #[tokio::test]
+    async fn signing_should_only_sign_api_hosts() {
+        let headers = Arc::new(Mutex::new(Vec::new()));
+        let signing = SigningMiddleware::new(b"secret".to_vec(), "x-signature".into())
+            .for_base_urls(["https://gateway.internal/v1"]);
+        let client = ClientBuilder::new(reqwest::Client::new())
+            .with(signing)
+            .with(Headers(headers.clone()))
+            .build();
+        client
+            .post("https://gateway.internal/v1/chat/completions")
+            .body("{}")
+            .send()
+            .await
+            .unwrap();
+        client
+            .get("https://cdn.example.com/image.png")
+            .send()
+            .await
+            .unwrap();
+        {
+            let headers = headers.lock().unwrap();
+            assert!(headers[0].contains_key("x-signature"));
+            assert!(!headers[1].contains_key("x-signature"));
+        }
+
+        let stream = futures::stream::once(async { Ok::<_, std::io::Error>("audio") });
+        let err = client
+            .post("https://gateway.internal/v1/audio/transcriptions")
+            .body(reqwest::Body::wrap_stream(stream))
+            .send()
+            .await
+            .unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("can't sign a streamed request body"));
+    }
+}
@@ -0,0 +1,230 @@
+//! Declarative multi-step pipelines, where each step transforms the output of the previous one.
+//!
+//! ```ignore
+//! let pipeline = Pipeline::new(ticket_text)
+//!     .step("Extract entities", ExtractStep::<Entities>::new("Extract the entities."))
+//!     .step("Classify intent", ClassifyStep::new(vec![Intent::Refund, Intent::Question]))
+//!     .step("Generate response", CompleteStep::new("Reply to the customer."))
+//!     .build();
+//! let ctx = pipeline.run(&sdk).await?;
+//! ```
+
+use crate::LlmSDK;
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+use std::marker::PhantomData;
+
+/// A step of a `Pipeline`. It reads `ctx.value` and replaces it with its output.
+#[async_trait]
+pub trait PipelineStep: Send + Sync {
+    async fn execute<'a>(&self, ctx: PipelineContext<'a>) -> Result<PipelineContext<'a>>;
+}
+
+/// The state passed from step to step.
+#[derive(Debug, Clone)]
+pub struct PipelineContext<'a> {
+    pub sdk: &'a LlmSDK,
+    /// The output of the last step, or the initial input.
+    pub value: Value,
+    /// The output of every step that has run, in order.
+    pub results: Vec<StepResult>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StepResult {
+    pub name: String,
+    pub value: Value,
+}
+
+pub struct Pipeline {
+    input: String,
+    steps: Vec<(String, Box<dyn PipelineStep>)>,
+}
+
+pub struct PipelineBuilder {
+    input: String,
+    steps: Vec<(String, Box<dyn PipelineStep>)>,
+}
+
+/// Extract a `T` from the current value, see `LlmSDK::extract`.
+pub struct ExtractStep<T> {
+    instructions: String,
+    _output: PhantomData<fn() -> T>,
+}
+
+/// Classify the current value into one of `labels` and output the best label, see
+/// `LlmSDK::classify`.
+pub struct ClassifyStep<L> {
+    labels: Vec<L>,
+}
+
+/// Reply to the current value with the chat model, see `LlmSDK::complete`.
+pub struct CompleteStep {
+    system: String,
+}
+
+impl PipelineContext<'_> {
+    /// The current value as text: strings as is, anything else as JSON.
+    pub fn text(&self) -> String {
+        match &self.value {
+            Value::String(s) => s.clone(),
+            value => value.to_string(),
+        }
+    }
+}
+
+impl Pipeline {
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(initial_input: impl Into<String>) -> PipelineBuilder {
+        PipelineBuilder {
+            input: initial_input.into(),
+            steps: vec![],
+        }
+    }
+
+    /// Run the steps in order. Fails with the name of the step that failed.
+    pub async fn run<'a>(&self, sdk: &'a LlmSDK) -> Result<PipelineContext<'a>> {
+        let mut ctx = PipelineContext {
+            sdk,
+            value: Value::String(self.input.clone()),
+            results: vec![],
+        };
+        for (name, step) in &self.steps {
+            ctx = step
+                .execute(ctx)
+                .await
+                .map_err(|e| e.context(format!("pipeline step \"{}\" failed", name)))?;
+            ctx.results.push(StepResult {
+                name: name.clone(),
+                value: ctx.value.clone(),
+            });
+        }
+        Ok(ctx)
+    }
+}
+
+impl PipelineBuilder {
+    pub fn step(mut self, name: impl Into<String>, step: impl PipelineStep + 'static) -> Self {
+        self.steps.push((name.into(), Box::new(step)));
+        self
+    }
+
+    pub fn build(self) -> Pipeline {
+        Pipeline {
+            input: self.input,
+            steps: self.steps,
+        }
+    }
+}
+
+impl<T> ExtractStep<T> {
+    pub fn new(instructions: impl Into<String>) -> Self {
+        Self {
+            instructions: instructions.into(),
+            _output: PhantomData,
+        }
+    }
+}
+
+#[async_trait]
+impl<T: DeserializeOwned + JsonSchema + Serialize> PipelineStep for ExtractStep<T> {
+    async fn execute<'a>(&self, mut ctx: PipelineContext<'a>) -> Result<PipelineContext<'a>> {
+        let output: T = ctx.sdk.extract(&self.instructions, &ctx.text()).await?;
+        ctx.value = serde_json::to_value(output)?;
+        Ok(ctx)
+    }
+}
+
+impl<L> ClassifyStep<L> {
+    pub fn new(labels: Vec<L>) -> Self {
+        Self { labels }
+    }
+}
+
+#[async_trait]
+impl<L> PipelineStep for ClassifyStep<L>
+where
+    L: ToString + DeserializeOwned + JsonSchema + Serialize + Send + Sync,
+{
+    async fn execute<'a>(&self, mut ctx: PipelineContext<'a>) -> Result<PipelineContext<'a>> {
+        let result = ctx
+            .sdk
+            .classify(&ctx.text(), &self.labels, false)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("no classification returned"))?;
+        ctx.value = serde_json::to_value(result.label)?;
+        Ok(ctx)
+    }
+}
+
+impl CompleteStep {
+    pub fn new(system: impl Into<String>) -> Self {
+        Self {
+            system: system.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl PipelineStep for CompleteStep {
+    async fn execute<'a>(&self, mut ctx: PipelineContext<'a>) -> Result<PipelineContext<'a>> {
+        let reply = ctx.sdk.complete(&self.system, &ctx.text()).await?;
+        ctx.value = Value::String(reply);
+        Ok(ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    struct WordCount;
+
+    #[async_trait]
+    impl PipelineStep for WordCount {
+        async fn execute<'a>(&self, mut ctx: PipelineContext<'a>) -> Result<PipelineContext<'a>> {
+            ctx.value = json!({"words": ctx.text().split_whitespace().count()});
+            Ok(ctx)
+        }
+    }
+
+    struct Fail;
+
+    #[async_trait]
+    impl PipelineStep for Fail {
+        async fn execute<'a>(&self, _ctx: PipelineContext<'a>) -> Result<PipelineContext<'a>> {
+            Err(anyhow!("boom"))
+        }
+    }
+
+    #[tokio::test]
+    async fn run_should_feed_each_step_the_previous_output() -> Result<()> {
+        let sdk = LlmSDK::new("token");
+        let pipeline = Pipeline::new("one two three")
+            .step("count", WordCount)
+            .step("count again", WordCount)
+            .build();
+        let ctx = pipeline.run(&sdk).await?;
+        assert_eq!(ctx.value, json!({"words": 1}));
+        assert_eq!(ctx.results.len(), 2);
+        assert_eq!(ctx.results[0].name, "count");
+        assert_eq!(ctx.results[0].value, json!({"words": 3}));
+
+        let err = Pipeline::new("text")
+            .step("count", WordCount)
+            .step("explode", Fail)
+            .build()
+            .run(&sdk)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "pipeline step \"explode\" failed");
+        Ok(())
+    }
+}
@@ -0,0 +1,227 @@
+use crate::{EndpointKind, IntoRequest, LlmSDK, SendAndLog};
+use anyhow::Result;
+use bytes::Bytes;
+use reqwest::Method;
+use reqwest_middleware::{ClientWithMiddleware, RequestBuilder};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use tracing::{debug, warn};
+
+/// A request captured by `LlmSDKBuilder::record_to`, stored as one JSON object per line.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub method: String,
+    /// The url relative to the base url, e.g. `/chat/completions`.
+    pub endpoint: String,
+    /// The endpoint category, so the request is replayed to the base url configured for it,
+    /// see `LlmSDKBuilder::endpoint_base_url`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<EndpointKind>,
+    pub body: serde_json::Value,
+}
+
+impl IntoRequest for RecordedRequest {
+    fn into_request(self, base_url: &str, client: ClientWithMiddleware) -> RequestBuilder {
+        let method = Method::from_bytes(self.method.as_bytes()).unwrap_or(Method::POST);
+        let url = format!("{}{}", base_url, self.endpoint);
+        client.request(method, url).json(&self.body)
+    }
+
+    fn endpoint_kind(&self) -> Option<EndpointKind> {
+        self.kind
+    }
+}
+
+/// Appends requests to the record file on a background thread, so recording never blocks the
+/// async runtime. Requests are written in the order they were prepared.
+#[derive(Clone)]
+pub(crate) struct Recorder {
+    tx: mpsc::Sender<String>,
+}
+
+impl Recorder {
+    pub(crate) fn new(path: PathBuf) -> Self {
+        Self::spawn(path).0
+    }
+
+    /// Start the writer thread, which ends once all clones of the recorder are dropped.
+    fn spawn(path: PathBuf) -> (Self, JoinHandle<()>) {
+        let (tx, rx) = mpsc::channel::<String>();
+        let writer = std::thread::spawn(move || {
+            for line in rx {
+                let ret = OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&path)
+                    .and_then(|mut file| writeln!(file, "{}", line));
+                if let Err(e) = ret {
+                    warn!("failed to record request to {}: {}", path.display(), e);
+                }
+            }
+        });
+        (Self { tx }, writer)
+    }
+
+    /// Record the request. Only requests with a JSON body are recorded, failures are logged
+    /// rather than failing the request.
+    pub(crate) fn record(&self, base_url: &str, kind: Option<EndpointKind>, req: &RequestBuilder) {
+        let Some(req) = req.try_clone().and_then(|req| req.build().ok()) else {
+            debug!("skip recording request with a streaming body");
+            return;
+        };
+        let Some(body) = req
+            .body()
+            .and_then(|body| body.as_bytes())
+            .and_then(|body| serde_json::from_slice(body).ok())
+        else {
+            debug!("skip recording request without a JSON body");
+            return;
+        };
+        let url = req.url().as_str();
+        let recorded = RecordedRequest {
+            method: req.method().to_string(),
+            endpoint: url.strip_prefix(base_url).unwrap_or(url).to_owned(),
+            kind,
+            body,
+        };
+        match serde_json::to_string(&recorded) {
+            Ok(line) => {
+                let _ = self.tx.send(line);
+            }
+            Err(e) => warn!("failed to record request: {}", e),
+        }
+    }
+}
+
+/// Read all requests recorded to `path`.
+pub fn read_recorded(path: impl AsRef<Path>) -> Result<Vec<RecordedRequest>> {
+    fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
+}
+
+/// Replay the requests recorded to `path` against `sdk` (e.g. one pointing at a mock server),
+/// returning the raw response body of each request in order.
+pub async fn replay(path: impl AsRef<Path>, sdk: &LlmSDK) -> Result<Vec<Result<Bytes>>> {
+    // don't record the replayed requests again
+    let sdk = LlmSDK {
+        recorder: None,
+        ..sdk.clone()
+    };
+    let mut responses = Vec::new();
+    for recorded in read_recorded(path)? {
+        let req = sdk.prepare_request(recorded);
+        let res = match req.send_and_log().await {
+            Ok(res) => res.bytes().await.map_err(anyhow::Error::from),
+            Err(e) => Err(e),
+        };
+        responses.push(res);
+    }
+    Ok(responses)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_server, LlmSDKBuilder};
+    use reqwest_middleware::ClientBuilder;
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("llm-sdk-record-{}.jsonl", uuid::Uuid::new_v4()))
+    }
+
+    #[test]
+    fn record_should_append_json_requests() -> Result<()> {
+        let path = temp_path();
+        let (recorder, writer) = Recorder::spawn(path.clone());
+        let client = ClientBuilder::new(reqwest::Client::new()).build();
+        let base_url = "http://localhost/v1";
+        let body = serde_json::json!({"model": "gpt-3.5-turbo-1106"});
+        recorder.record(
+            base_url,
+            Some(EndpointKind::Chat),
+            &client
+                .post(format!("{}/chat/completions", base_url))
+                .json(&body),
+        );
+        recorder.record(base_url, None, &client.get(format!("{}/models", base_url)));
+        drop(recorder);
+        writer.join().unwrap();
+        let recorded = read_recorded(&path)?;
+        fs::remove_file(&path)?;
+        assert_eq!(
+            recorded,
+            vec![RecordedRequest {
+                method: "POST".into(),
+                endpoint: "/chat/completions".into(),
+                kind: Some(EndpointKind::Chat),
+                body,
+            }]
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replay_should_not_record_again() -> Result<()> {
+        let path = temp_path();
+        let recorded = RecordedRequest {
+            method: "POST".into(),
+            endpoint: "/chat/completions".into(),
+            kind: None,
+            body: serde_json::json!({"model": "gpt-4o-mini"}),
+        };
+        fs::write(&path, format!("{}\n", serde_json::to_string(&recorded)?))?;
+        let url = test_server::serve(vec![(200, "{}".into())]);
+        let sdk = LlmSDKBuilder::default()
+            .token("token")
+            .base_url(url)
+            .record_to(path.clone())
+            .build()?;
+        let responses = replay(&path, &sdk).await?;
+        drop(sdk);
+        let recorded = read_recorded(&path)?;
+        fs::remove_file(&path)?;
+        assert_eq!(&responses[0].as_ref().unwrap()[..], b"{}");
+        assert_eq!(recorded.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replay_should_use_the_endpoint_base_url() -> Result<()> {
+        let path = temp_path();
+        let url = test_server::serve(vec![
+            (200, test_server::chat_response("hi")),
+            (200, test_server::chat_response("again")),
+        ]);
+        let (recorder, writer) = Recorder::spawn(path.clone());
+        let sdk = LlmSDKBuilder::default()
+            .token("token")
+            .base_url("http://127.0.0.1:1")
+            .chat_base_url(url)
+            .build()?;
+        let recording = LlmSDK {
+            recorder: Some(recorder),
+            ..sdk.clone()
+        };
+        recording
+            .chat_completion(crate::ChatCompletionRequest::new(
+                Default::default(),
+                vec![crate::ChatCompletionMessage::new_user("hi", "")],
+            ))
+            .await?;
+        drop(recording);
+        writer.join().unwrap();
+        let recorded = read_recorded(&path)?;
+        assert_eq!(recorded[0].kind, Some(EndpointKind::Chat));
+        let responses = replay(&path, &sdk).await?;
+        fs::remove_file(&path)?;
+        assert!(String::from_utf8_lossy(responses[0].as_ref().unwrap()).contains("again"));
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use std::fmt;
+
+/// A value that must not show up in logs. It always `Debug`s as `[REDACTED]`, use `expose` to
+/// get the inner value.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct Sensitive<T>(T);
+
+impl<T> Sensitive<T> {
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T> fmt::Debug for Sensitive<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[REDACTED]")
+    }
+}
+
+impl From<String> for Sensitive<String> {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for Sensitive<String> {
+    fn from(value: &str) -> Self {
+        Self(value.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::LlmSDK;
+
+    #[test]
+    fn sdk_debug_should_redact_token() {
+        let sdk = LlmSDK::new("sk-very-secret");
+        let output = format!("{:?}", sdk);
+        assert!(!output.contains("sk-very-secret"));
+        assert!(output.contains("token: [REDACTED]"));
+    }
+}
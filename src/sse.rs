@@ -0,0 +1,93 @@
+use crate::LlmError;
+use anyhow::Result;
+use bytes::Bytes;
+use futures::stream::{self, BoxStream};
+use futures::{Stream, StreamExt};
+
+const DONE: &str = "[DONE]";
+
+/// Split a server-sent events body into the `data` payload of each event, stopping at the
+/// `[DONE]` sentinel. A body that ends without `[DONE]` yields `LlmError::StreamInterrupted`,
+/// dropping an unterminated last event as it may have been cut off mid-payload.
+pub(crate) fn sse_data<S>(body: S) -> BoxStream<'static, Result<String>>
+where
+    S: Stream<Item = reqwest::Result<Bytes>> + Send + 'static,
+{
+    let state = (body.boxed(), Vec::new(), false);
+    stream::unfold(state, |(mut body, mut buf, done)| async move {
+        if done {
+            return None;
+        }
+        loop {
+            if let Some(pos) = buf.windows(2).position(|w| w == b"\n\n") {
+                let event: Vec<u8> = buf.drain(..pos + 2).collect();
+                let data = event_data(&event);
+                match data.as_deref() {
+                    None => continue,
+                    Some(DONE) => return None,
+                    Some(_) => return Some((Ok(data.unwrap()), (body, buf, false))),
+                }
+            }
+            match body.next().await {
+                Some(Ok(bytes)) => buf.extend(bytes.iter().filter(|b| **b != b'\r')),
+                Some(Err(e)) => return Some((Err(e.into()), (body, buf, true))),
+                None => {
+                    // `[DONE]` may not be terminated by a blank line
+                    if event_data(&buf).as_deref() == Some(DONE) {
+                        return None;
+                    }
+                    return Some((Err(LlmError::StreamInterrupted.into()), (body, buf, true)));
+                }
+            }
+        }
+    })
+    .boxed()
+}
+
+fn event_data(event: &[u8]) -> Option<String> {
+    let event = String::from_utf8_lossy(event);
+    let lines: Vec<_> = event
+        .lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .map(|line| line.strip_prefix(' ').unwrap_or(line))
+        .collect();
+    if lines.is_empty() {
+        None
+    } else {
+        Some(lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn sse_data_should_join_split_events() {
+        let chunks = vec![
+            Ok(Bytes::from_static(b"data: {\"a\":")),
+            Ok(Bytes::from_static(
+                b"1}\n\n: keep-alive\n\ndata: {\"b\":2}\r\n\r\n",
+            )),
+            Ok(Bytes::from_static(b"data: [DONE]\n\ndata: ignored\n\n")),
+        ];
+        let data: Vec<_> = sse_data(stream::iter(chunks))
+            .map(|data| data.unwrap())
+            .collect()
+            .await;
+        assert_eq!(data, vec![r#"{"a":1}"#, r#"{"b":2}"#]);
+    }
+
+    #[tokio::test]
+    async fn sse_data_should_report_interrupted_stream() {
+        let chunks = vec![Ok(Bytes::from_static(b"data: {\"a\":1}\n\ndata: {\"b\""))];
+        let data: Vec<_> = sse_data(stream::iter(chunks)).collect().await;
+        assert_eq!(data.len(), 2);
+        assert_eq!(data[0].as_ref().unwrap(), r#"{"a":1}"#);
+        let err = data[1].as_ref().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LlmError>(),
+            Some(LlmError::StreamInterrupted)
+        ));
+    }
+}
@@ -0,0 +1,102 @@
+use crate::LlmSDK;
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use std::collections::HashSet;
+
+const SOUNDS_PROMPT: &str = "Based on this transcript, what non-speech sounds might be present? List likely ambient sounds, e.g. applause, traffic or laughter, say whether music is playing, and summarize the audio in one or two sentences for a listener who can't hear it.";
+
+/// A text description of audio for accessibility, e.g. as an alternative to a podcast or video
+/// soundtrack.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioDescription {
+    pub summary: String,
+    /// The number of distinct speakers, inferred from the transcript.
+    pub speakers_detected: u8,
+    /// The language detected by Whisper.
+    pub language: String,
+    pub ambient_sounds: Vec<String>,
+    pub music_detected: bool,
+    /// The transcribed speech, one entry per Whisper segment.
+    pub speech_segments: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct SoundAnalysis {
+    summary: String,
+    ambient_sounds: Vec<String>,
+    music_detected: bool,
+}
+
+impl LlmSDK {
+    /// Describe the content of `audio_data` in text. The audio is transcribed once, then the
+    /// speakers and the non-speech sounds are inferred from the transcript, so sounds that
+    /// Whisper didn't annotate and that the speech doesn't hint at are missed.
+    pub async fn describe_audio(&self, audio_data: Vec<u8>) -> Result<AudioDescription> {
+        let transcript = self.transcribe_verbose(audio_data).await?;
+        let (annotated_sounds, annotated_music) = sound_annotations(&transcript.text);
+        let speech_segments: Vec<String> = transcript
+            .segments
+            .iter()
+            .map(|segment| segment.text.trim().to_string())
+            .collect();
+        let diarized = self.label_speakers(transcript.segments, None).await?;
+        let speakers: HashSet<_> = diarized.iter().map(|s| s.speaker_id).collect();
+        let analysis: SoundAnalysis = self.extract(SOUNDS_PROMPT, &transcript.text).await?;
+
+        let mut ambient_sounds = annotated_sounds;
+        for sound in analysis.ambient_sounds {
+            if !ambient_sounds
+                .iter()
+                .any(|s| s.eq_ignore_ascii_case(&sound))
+            {
+                ambient_sounds.push(sound);
+            }
+        }
+        Ok(AudioDescription {
+            summary: analysis.summary,
+            speakers_detected: speakers.len().min(u8::MAX as usize) as u8,
+            language: transcript.language,
+            ambient_sounds,
+            music_detected: annotated_music || analysis.music_detected,
+            speech_segments,
+        })
+    }
+}
+
+/// The sounds Whisper annotated in `text`, like `[applause]` or `(door slams)`, and whether it
+/// marked music, which it writes as `[Music]` or `♪`.
+fn sound_annotations(text: &str) -> (Vec<String>, bool) {
+    let mut sounds: Vec<String> = Vec::new();
+    let mut music = text.contains('♪');
+    for (open, close) in [('[', ']'), ('(', ')')] {
+        let mut rest = text;
+        while let Some(start) = rest.find(open) {
+            let Some(len) = rest[start + 1..].find(close) else {
+                break;
+            };
+            let sound = rest[start + 1..start + 1 + len].trim().to_lowercase();
+            rest = &rest[start + 1 + len + 1..];
+            if sound == "music" {
+                music = true;
+            } else if !sound.is_empty() && !sounds.contains(&sound) {
+                sounds.push(sound);
+            }
+        }
+    }
+    (sounds, music)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sound_annotations_should_find_bracketed_sounds() {
+        let (sounds, music) =
+            sound_annotations("[Music] Welcome back. (Applause) Thanks! [applause] (laughs)");
+        assert_eq!(sounds, vec!["applause", "laughs"]);
+        assert!(music);
+        assert_eq!(sound_annotations("♪ la la ♪"), (vec![], true));
+    }
+}
@@ -0,0 +1,266 @@
+use crate::{
+    ChatCompleteModel, ChatCompletionMessage, ChatCompletionRequestBuilder, ChatResponseFormat,
+    ChatResponseFormatObject, ContentPart, ImageInput, LlmSDK,
+};
+use anyhow::{anyhow, Result};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use strum::Display;
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ClassificationResult<L> {
+    pub label: L,
+    /// How confident the model is in the label, from 0.0 to 1.0.
+    pub confidence: f32,
+    pub reasoning: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize, JsonSchema)]
+pub enum Sentiment3 {
+    Positive,
+    Negative,
+    Neutral,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize, JsonSchema)]
+pub enum Toxicity {
+    Safe,
+    Offensive,
+    Harmful,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ImageClassificationResult<L> {
+    pub label: L,
+    /// How confident the model is in the label, from 0.0 to 1.0.
+    pub confidence: f32,
+    /// What in the image supports the label.
+    pub description: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize, JsonSchema)]
+pub enum ImageContentType {
+    Photo,
+    Illustration,
+    Screenshot,
+    Diagram,
+    Chart,
+    MemeOrComic,
+    Text,
+    Other,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Display, Serialize, Deserialize, JsonSchema)]
+pub enum ImageSafety {
+    Safe,
+    PossiblyOffensive,
+    Explicit,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct ClassificationResponse<L> {
+    results: Vec<ClassificationResult<L>>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct ImageClassificationResponse<L> {
+    results: Vec<ImageClassificationResult<L>>,
+}
+
+impl LlmSDK {
+    /// Classify `text` into one of `labels`, or into any number of them if `multi_label` is set.
+    /// Results are ordered by confidence, highest first; without `multi_label` there is exactly
+    /// one.
+    pub async fn classify<L: ToString + DeserializeOwned + JsonSchema>(
+        &self,
+        text: &str,
+        labels: &[L],
+        multi_label: bool,
+    ) -> Result<Vec<ClassificationResult<L>>> {
+        let mut instructions = format!(
+            "You are a precise text classifier. Classify the user's text using only these labels: {}.",
+            label_list(labels.iter())
+        );
+        if multi_label {
+            instructions.push_str(
+                " Return every label that applies, each with its own confidence and reasoning.",
+            );
+        } else {
+            instructions.push_str(" Return exactly one result with the single best label.");
+        }
+        let allowed: Vec<_> = labels.iter().map(ToString::to_string).collect();
+        let mut results = self
+            .extract_classification(&instructions, text, &allowed)
+            .await?;
+        if !multi_label {
+            results.truncate(1);
+        }
+        Ok(results)
+    }
+
+    /// Few-shot classification: each label comes with example texts that belong to it.
+    pub async fn classify_with_examples<L: ToString + DeserializeOwned + JsonSchema>(
+        &self,
+        text: &str,
+        labels: &[(L, Vec<&str>)],
+    ) -> Result<ClassificationResult<L>> {
+        let mut instructions = format!(
+            "You are a precise text classifier. Classify the user's text using only these labels: {}. Return exactly one result with the single best label.\nExamples:",
+            label_list(labels.iter().map(|(label, _)| label))
+        );
+        for (label, examples) in labels {
+            for example in examples {
+                instructions.push_str(&format!("\n{:?} => {}", example, label.to_string()));
+            }
+        }
+        let allowed: Vec<_> = labels.iter().map(|(label, _)| label.to_string()).collect();
+        let mut results = self
+            .extract_classification(&instructions, text, &allowed)
+            .await?;
+        Ok(results.remove(0))
+    }
+
+    /// Classify an image with a vision model into one of `labels`, or into any number of them if
+    /// `multi_label` is set. Results are ordered by confidence, highest first.
+    pub async fn classify_image<L: ToString + DeserializeOwned + JsonSchema>(
+        &self,
+        image: ImageInput,
+        labels: &[L],
+        multi_label: bool,
+    ) -> Result<Vec<ImageClassificationResult<L>>> {
+        let mut instructions = format!(
+            "You are a precise image classifier. Classify the user's image using only these labels: {}.",
+            label_list(labels.iter())
+        );
+        if multi_label {
+            instructions.push_str(" Return every label that applies, each with its own confidence and a description of what in the image supports it.");
+        } else {
+            instructions.push_str(" Return exactly one result with the single best label.");
+        }
+        let messages = vec![
+            ChatCompletionMessage::new_system(instructions, ""),
+            ChatCompletionMessage::new_user_with_parts(vec![ContentPart::image(&image)], ""),
+        ];
+        let req = ChatCompletionRequestBuilder::default()
+            .model(ChatCompleteModel::Gpt4oMini)
+            .messages(messages)
+            .response_format(ChatResponseFormatObject::new(ChatResponseFormat::Json))
+            .build()?;
+        let res: ImageClassificationResponse<L> =
+            self.chat_completion_typed_with_retry(req, 2).await?;
+
+        let allowed: Vec<_> = labels.iter().map(ToString::to_string).collect();
+        let mut results = filter_results(res.results, &allowed)?;
+        if !multi_label {
+            results.truncate(1);
+        }
+        Ok(results)
+    }
+
+    /// Check an image before uploading it, e.g. to an image edit or a public gallery.
+    pub async fn check_image_safety(&self, image: ImageInput) -> Result<ImageSafety> {
+        let labels = [
+            ImageSafety::Safe,
+            ImageSafety::PossiblyOffensive,
+            ImageSafety::Explicit,
+        ];
+        let mut results = self.classify_image(image, &labels, false).await?;
+        Ok(results.remove(0).label)
+    }
+
+    /// Extract the results, keeping only the allowed labels, ordered by confidence.
+    async fn extract_classification<L: ToString + DeserializeOwned + JsonSchema>(
+        &self,
+        instructions: &str,
+        text: &str,
+        allowed: &[String],
+    ) -> Result<Vec<ClassificationResult<L>>> {
+        let res: ClassificationResponse<L> = self.extract(instructions, text).await?;
+        filter_results(res.results, allowed)
+    }
+}
+
+fn label_list<'a, L: ToString + 'a>(labels: impl Iterator<Item = &'a L>) -> String {
+    labels
+        .map(|label| label.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// A labeled result of the text or image classifier, see `filter_results`.
+trait Labeled {
+    fn label(&self) -> String;
+    fn confidence(&self) -> f32;
+}
+
+impl<L: ToString> Labeled for ClassificationResult<L> {
+    fn label(&self) -> String {
+        self.label.to_string()
+    }
+
+    fn confidence(&self) -> f32 {
+        self.confidence
+    }
+}
+
+impl<L: ToString> Labeled for ImageClassificationResult<L> {
+    fn label(&self) -> String {
+        self.label.to_string()
+    }
+
+    fn confidence(&self) -> f32 {
+        self.confidence
+    }
+}
+
+/// Keep only the allowed labels, ordered by confidence. Fails if none is left.
+fn filter_results<R: Labeled>(results: Vec<R>, allowed: &[String]) -> Result<Vec<R>> {
+    let mut results: Vec<_> = results
+        .into_iter()
+        .filter(|res| allowed.contains(&res.label()))
+        .collect();
+    if results.is_empty() {
+        return Err(anyhow!("no valid label returned by the classifier"));
+    }
+    results.sort_by(|a, b| b.confidence().total_cmp(&a.confidence()));
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+
+    #[tokio::test]
+    async fn check_image_safety_should_return_best_label() -> Result<()> {
+        let reply = serde_json::json!({"results": [
+            {"label": "Safe", "confidence": 0.2, "description": "a beach"},
+            {"label": "Explicit", "confidence": 0.9, "description": "nudity"}
+        ]});
+        let url = test_server::serve(vec![(200, test_server::chat_response(&reply.to_string()))]);
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let image = ImageInput::bytes(b"png".to_vec(), "image/png");
+        assert_eq!(sdk.check_image_safety(image).await?, ImageSafety::Explicit);
+        Ok(())
+    }
+
+    #[test]
+    fn filter_results_should_drop_unrequested_labels_and_sort() {
+        let res: ClassificationResponse<Toxicity> = serde_json::from_value(serde_json::json!({
+            "results": [
+                {"label": "Offensive", "confidence": 0.4, "reasoning": "insult"},
+                {"label": "Harmful", "confidence": 0.9, "reasoning": "threat"},
+                {"label": "Safe", "confidence": 0.1, "reasoning": "mostly calm"}
+            ]
+        }))
+        .unwrap();
+        let allowed = vec![
+            Toxicity::Offensive.to_string(),
+            Toxicity::Harmful.to_string(),
+        ];
+        let results = filter_results(res.results, &allowed).unwrap();
+        let labels: Vec<_> = results.iter().map(|res| res.label).collect();
+        assert_eq!(labels, vec![Toxicity::Harmful, Toxicity::Offensive]);
+    }
+}
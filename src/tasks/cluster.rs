@@ -0,0 +1,202 @@
+use crate::LlmSDK;
+use anyhow::{bail, Result};
+use futures::{stream, StreamExt, TryStreamExt};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+const KMEANS_ITERATIONS: usize = 50;
+/// The maximum number of texts per cluster sent to the model to name it.
+const REPRESENTATIVE_TEXTS: usize = 10;
+const LABEL_CONCURRENCY: usize = 4;
+const LABEL_PROMPT: &str = "What is the common theme of these texts? Respond with a short name and one-sentence description.";
+
+#[derive(Debug, Clone)]
+pub struct ClusterLabel {
+    pub cluster_id: usize,
+    pub name: String,
+    pub description: String,
+    pub representative_texts: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClusteringReport {
+    /// The cluster of each input text.
+    pub assignments: Vec<usize>,
+    /// The labels of the non-empty clusters, ordered by cluster id.
+    pub clusters: Vec<ClusterLabel>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct ClusterTheme {
+    name: String,
+    description: String,
+}
+
+impl LlmSDK {
+    /// Embed `texts` and group them into `k` clusters with k-means. Returns the cluster of each
+    /// text.
+    pub async fn embed_and_cluster(&self, texts: Vec<String>, k: usize) -> Result<Vec<usize>> {
+        if k == 0 {
+            bail!("k must be at least 1");
+        }
+        let embeddings = self.embed_texts(texts).await?;
+        Ok(kmeans(&embeddings, k, KMEANS_ITERATIONS))
+    }
+
+    /// Name each of the `k` clusters of `texts` after the common theme of its texts.
+    pub async fn label_clusters(
+        &self,
+        texts: Vec<String>,
+        assignments: Vec<usize>,
+        k: usize,
+    ) -> Result<Vec<ClusterLabel>> {
+        if texts.len() != assignments.len() {
+            bail!("there must be one assignment per text");
+        }
+        let mut clusters = vec![Vec::new(); k];
+        for (text, cluster) in texts.into_iter().zip(assignments) {
+            if let Some(texts) = clusters.get_mut(cluster) {
+                texts.push(text);
+            }
+        }
+        let mut labels: Vec<ClusterLabel> = stream::iter(clusters.into_iter().enumerate())
+            .filter(|(_, texts)| futures::future::ready(!texts.is_empty()))
+            .map(|(cluster_id, texts)| self.label_cluster(cluster_id, texts))
+            .buffer_unordered(LABEL_CONCURRENCY)
+            .try_collect()
+            .await?;
+        labels.sort_by_key(|label| label.cluster_id);
+        Ok(labels)
+    }
+
+    /// Cluster `texts` with `embed_and_cluster` and name the clusters with `label_clusters`.
+    pub async fn embed_cluster_and_label(
+        &self,
+        texts: Vec<String>,
+        k: usize,
+    ) -> Result<ClusteringReport> {
+        let assignments = self.embed_and_cluster(texts.clone(), k).await?;
+        let clusters = self.label_clusters(texts, assignments.clone(), k).await?;
+        Ok(ClusteringReport {
+            assignments,
+            clusters,
+        })
+    }
+
+    async fn label_cluster(&self, cluster_id: usize, texts: Vec<String>) -> Result<ClusterLabel> {
+        let representative_texts = sample(texts, REPRESENTATIVE_TEXTS);
+        let content = representative_texts
+            .iter()
+            .map(|text| format!("- {}", text))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let theme: ClusterTheme = self.extract(LABEL_PROMPT, &content).await?;
+        Ok(ClusterLabel {
+            cluster_id,
+            name: theme.name,
+            description: theme.description,
+            representative_texts,
+        })
+    }
+}
+
+/// Pick up to `n` texts spread evenly over the cluster.
+fn sample(texts: Vec<String>, n: usize) -> Vec<String> {
+    if texts.len() <= n {
+        return texts;
+    }
+    let step = texts.len() as f32 / n as f32;
+    (0..n)
+        .map(|i| texts[(i as f32 * step) as usize].clone())
+        .collect()
+}
+
+/// Cluster `vectors` with k-means, seeded deterministically with the farthest point heuristic.
+fn kmeans(vectors: &[Vec<f32>], k: usize, iterations: usize) -> Vec<usize> {
+    if vectors.is_empty() {
+        return vec![];
+    }
+    let k = k.min(vectors.len());
+    let mut centroids = vec![vectors[0].clone()];
+    while centroids.len() < k {
+        let farthest = vectors
+            .iter()
+            .max_by(|a, b| {
+                let da = nearest(&centroids, a).1;
+                let db = nearest(&centroids, b).1;
+                da.total_cmp(&db)
+            })
+            .unwrap();
+        centroids.push(farthest.clone());
+    }
+
+    let mut assignments = vec![0; vectors.len()];
+    for _ in 0..iterations {
+        let next: Vec<_> = vectors.iter().map(|v| nearest(&centroids, v).0).collect();
+        let changed = next != assignments;
+        assignments = next;
+        for (cluster, centroid) in centroids.iter_mut().enumerate() {
+            let members: Vec<_> = vectors
+                .iter()
+                .zip(&assignments)
+                .filter(|(_, a)| **a == cluster)
+                .map(|(v, _)| v)
+                .collect();
+            if members.is_empty() {
+                continue;
+            }
+            for (i, value) in centroid.iter_mut().enumerate() {
+                *value = members.iter().map(|m| m[i]).sum::<f32>() / members.len() as f32;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    assignments
+}
+
+/// The index of and squared distance to the centroid nearest to `vector`.
+fn nearest(centroids: &[Vec<f32>], vector: &[f32]) -> (usize, f32) {
+    centroids
+        .iter()
+        .map(|c| {
+            c.iter()
+                .zip(vector)
+                .map(|(a, b)| (a - b) * (a - b))
+                .sum::<f32>()
+        })
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kmeans_should_separate_distinct_groups() {
+        let vectors = vec![
+            vec![1.0, 0.0],
+            vec![0.0, 1.0],
+            vec![0.9, 0.1],
+            vec![0.1, 0.9],
+            vec![0.95, 0.0],
+        ];
+        let assignments = kmeans(&vectors, 2, 10);
+        assert_eq!(assignments[0], assignments[2]);
+        assert_eq!(assignments[0], assignments[4]);
+        assert_eq!(assignments[1], assignments[3]);
+        assert_ne!(assignments[0], assignments[1]);
+    }
+
+    #[test]
+    fn sample_should_limit_representative_texts() {
+        let texts: Vec<_> = (0..25).map(|i| i.to_string()).collect();
+        let sampled = sample(texts, 10);
+        assert_eq!(sampled.len(), 10);
+        assert_eq!(sampled[0], "0");
+        assert_eq!(sampled[1], "2");
+    }
+}
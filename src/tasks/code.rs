@@ -0,0 +1,128 @@
+use crate::{
+    first_content, ChatCompleteModel, ChatCompletionMessage, ChatCompletionRequest, LlmSDK,
+};
+use anyhow::Result;
+
+#[derive(Debug, Clone, Default)]
+pub struct CodeCompletionRequest {
+    /// The programming language to write the code in. Inferred from the code if not given.
+    pub language: Option<String>,
+    /// Additional context, e.g. related types or the project's conventions.
+    pub context: Option<String>,
+    /// What the code should do.
+    pub instruction: String,
+    /// Code to complete or modify.
+    pub existing_code: Option<String>,
+    pub max_lines: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CodeCompletionResult {
+    pub code: String,
+    pub language: String,
+    /// Any text the model returned outside of the code block.
+    pub explanation: Option<String>,
+}
+
+impl CodeCompletionRequest {
+    pub fn new(instruction: impl Into<String>) -> Self {
+        Self {
+            instruction: instruction.into(),
+            ..Default::default()
+        }
+    }
+
+    fn system_prompt(&self) -> String {
+        let mut prompt = match &self.language {
+            Some(language) => format!("You are an expert {} programmer.", language),
+            None => "You are an expert programmer.".to_string(),
+        };
+        prompt.push_str(" Write idiomatic, correct code for the user's instruction and return it in a single fenced markdown code block tagged with the language. Keep any explanation short and outside the code block.");
+        if let Some(max_lines) = self.max_lines {
+            prompt.push_str(&format!(" The code must not exceed {} lines.", max_lines));
+        }
+        prompt
+    }
+
+    fn user_prompt(&self) -> String {
+        let mut prompt = String::new();
+        if let Some(context) = &self.context {
+            prompt.push_str(&format!("Context:\n{}\n\n", context));
+        }
+        if let Some(code) = &self.existing_code {
+            prompt.push_str(&format!("Existing code:\n```\n{}\n```\n\n", code));
+        }
+        prompt.push_str(&self.instruction);
+        prompt
+    }
+}
+
+impl LlmSDK {
+    /// Generate code for the instruction in `req` with gpt-4o-mini.
+    pub async fn complete_code(&self, req: CodeCompletionRequest) -> Result<CodeCompletionResult> {
+        let messages = vec![
+            ChatCompletionMessage::new_system(req.system_prompt(), ""),
+            ChatCompletionMessage::new_user(req.user_prompt(), ""),
+        ];
+        let chat = ChatCompletionRequest::new(ChatCompleteModel::Gpt4oMini, messages);
+        let content = first_content(self.chat_completion(chat).await?)?;
+        let mut res = parse_code_response(&content);
+        if res.language.is_empty() {
+            res.language = req.language.unwrap_or_default();
+        }
+        Ok(res)
+    }
+
+    /// Explain what `code` does in plain language.
+    pub async fn explain_code(&self, code: &str, language: &str) -> Result<String> {
+        let system = format!(
+            "You are an expert {} programmer. Explain what the user's code does, step by step, for a developer who is new to the code base.",
+            language
+        );
+        self.complete(&system, code).await
+    }
+}
+
+/// Split a markdown response into the first fenced code block and the text around it. If there
+/// is no fence, the whole response is taken as code.
+fn parse_code_response(content: &str) -> CodeCompletionResult {
+    let Some(start) = content.find("```") else {
+        return CodeCompletionResult {
+            code: content.trim().to_owned(),
+            language: String::new(),
+            explanation: None,
+        };
+    };
+    let after = &content[start + 3..];
+    let (language, body) = after.split_once('\n').unwrap_or(("", after));
+    let (code, rest) = body.split_once("```").unwrap_or((body, ""));
+    let explanation = format!("{}\n{}", &content[..start].trim(), rest.trim());
+    let explanation = explanation.trim();
+    CodeCompletionResult {
+        code: code.trim_end().to_owned(),
+        language: language.trim().to_owned(),
+        explanation: (!explanation.is_empty()).then(|| explanation.to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_code_response_should_strip_fences() {
+        let res = parse_code_response(
+            "Here you go:\n```rust\nfn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n```\nIt adds two numbers.",
+        );
+        assert_eq!(res.code, "fn add(a: i32, b: i32) -> i32 {\n    a + b\n}");
+        assert_eq!(res.language, "rust");
+        assert_eq!(
+            res.explanation.as_deref(),
+            Some("Here you go:\nIt adds two numbers.")
+        );
+
+        let res = parse_code_response("print('hi')");
+        assert_eq!(res.code, "print('hi')");
+        assert_eq!(res.explanation, None);
+    }
+}
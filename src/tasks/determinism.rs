@@ -0,0 +1,97 @@
+use crate::{ChatCompletionRequest, LlmSDK};
+use anyhow::{bail, Result};
+use std::collections::HashSet;
+
+/// The seed of requests checked by `LlmSDK::check_determinism` that don't set their own.
+const DETERMINISM_SEED: usize = 42;
+
+/// The variation between repeated runs of a request, see `LlmSDK::check_determinism`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeterminismReport {
+    pub runs: usize,
+    /// The distinct reply texts, in the order they first occurred.
+    pub outputs: Vec<String>,
+    /// The number of distinct `system_fingerprint`s. A change of fingerprint means the backend
+    /// changed between runs, which explains differing outputs despite a fixed seed.
+    pub distinct_fingerprints: usize,
+}
+
+impl DeterminismReport {
+    pub fn distinct_outputs(&self) -> usize {
+        self.outputs.len()
+    }
+
+    /// Whether every run produced the same output on the same backend.
+    pub fn is_deterministic(&self) -> bool {
+        self.outputs.len() == 1 && self.distinct_fingerprints == 1
+    }
+}
+
+impl LlmSDK {
+    /// Send `req` `n` times, one after the other, and compare the reply texts of the first
+    /// choice and the system fingerprints. The requests are sent with a temperature of 0 and a
+    /// fixed seed, unless `req` has its own, so the report shows the nondeterminism of the
+    /// backend rather than of sampling. All `n` requests are billed.
+    pub async fn check_determinism(
+        &self,
+        mut req: ChatCompletionRequest,
+        n: usize,
+    ) -> Result<DeterminismReport> {
+        if n == 0 {
+            bail!("n must be at least 1");
+        }
+        req.pin_sampling(DETERMINISM_SEED);
+        let mut outputs = Vec::new();
+        let mut fingerprints = HashSet::new();
+        for _ in 0..n {
+            let res = self.chat_completion(req.clone()).await?;
+            fingerprints.insert(res.system_fingerprint.clone());
+            let output = res
+                .choices
+                .into_iter()
+                .next()
+                .and_then(|choice| choice.message.content)
+                .unwrap_or_default();
+            if !outputs.contains(&output) {
+                outputs.push(output);
+            }
+        }
+        Ok(DeterminismReport {
+            runs: n,
+            outputs,
+            distinct_fingerprints: fingerprints.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_server, ChatCompleteModel, ChatCompletionMessage};
+
+    #[tokio::test]
+    async fn check_determinism_should_count_distinct_outputs() -> Result<()> {
+        let (url, received) = test_server::serve_recording(vec![
+            (200, test_server::chat_response("4")),
+            (200, test_server::chat_response("4")),
+            (200, test_server::chat_response("four")),
+        ]);
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let messages = vec![ChatCompletionMessage::new_user("What is 2 + 2?", "")];
+        let req = ChatCompletionRequest::new(ChatCompleteModel::Gpt4oMini, messages);
+        let report = sdk.check_determinism(req, 3).await?;
+        assert_eq!(report.runs, 3);
+        assert_eq!(report.outputs, vec!["4", "four"]);
+        assert_eq!(report.distinct_outputs(), 2);
+        assert_eq!(report.distinct_fingerprints, 1);
+        assert!(!report.is_deterministic());
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 3);
+        for body in received.iter() {
+            let body: serde_json::Value = serde_json::from_str(body)?;
+            assert_eq!(body["seed"], DETERMINISM_SEED);
+            assert_eq!(body["temperature"], 0.0);
+        }
+        Ok(())
+    }
+}
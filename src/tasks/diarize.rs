@@ -0,0 +1,166 @@
+use crate::{
+    LlmSDK, WhisperRequestBuilder, WhisperRequestType, WhisperResponseFormat, WhisperSegment,
+    WhisperVerboseResponse,
+};
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+/// The number of segments labelled per call.
+const WINDOW_SIZE: usize = 40;
+/// The number of already labelled segments repeated at the start of the next window, so the model
+/// keeps the speaker ids consistent across windows.
+const WINDOW_OVERLAP: usize = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiarizedSegment {
+    /// The speaker of the segment, numbered from 0 in order of appearance.
+    pub speaker_id: u8,
+    pub start_secs: f32,
+    pub end_secs: f32,
+    pub text: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct SpeakerLabels {
+    labels: Vec<SpeakerLabel>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct SpeakerLabel {
+    segment_index: usize,
+    speaker_id: u8,
+}
+
+impl LlmSDK {
+    /// Transcribe `audio_data` and attribute each segment to a speaker. Whisper doesn't diarize,
+    /// so the speakers are inferred by the model from the transcript text alone. This works for
+    /// interviews and dialogues with clear turns, but can't tell apart speakers who sound alike
+    /// in writing. Pass `num_speakers` if you know it, otherwise the model guesses.
+    pub async fn whisper_with_speaker_detection(
+        &self,
+        audio_data: Vec<u8>,
+        num_speakers: Option<u8>,
+    ) -> Result<Vec<DiarizedSegment>> {
+        let transcript = self.transcribe_verbose(audio_data).await?;
+        self.label_speakers(transcript.segments, num_speakers).await
+    }
+
+    pub(crate) async fn transcribe_verbose(
+        &self,
+        audio_data: Vec<u8>,
+    ) -> Result<WhisperVerboseResponse> {
+        let req = WhisperRequestBuilder::default()
+            .file(audio_data)
+            .request_type(WhisperRequestType::Transcription)
+            .response_format(WhisperResponseFormat::VerboseJson)
+            .build()?;
+        let req = self.prepare_request(req);
+        self.send_json(req).await
+    }
+
+    /// Attribute each of `segments` to a speaker, a window of segments per call.
+    pub(crate) async fn label_speakers(
+        &self,
+        segments: Vec<WhisperSegment>,
+        num_speakers: Option<u8>,
+    ) -> Result<Vec<DiarizedSegment>> {
+        let instructions = speaker_prompt(num_speakers);
+        let mut speakers: Vec<Option<u8>> = vec![None; segments.len()];
+        let mut start = 0;
+        while start < segments.len() {
+            let context = start.saturating_sub(WINDOW_OVERLAP);
+            let end = (start + WINDOW_SIZE).min(segments.len());
+            let content = window_content(&segments, &speakers, context..end);
+            let labels: SpeakerLabels = self.extract(&instructions, &content).await?;
+            for label in labels.labels {
+                if (start..end).contains(&label.segment_index) {
+                    speakers[label.segment_index] = Some(label.speaker_id);
+                }
+            }
+            start = end;
+        }
+        Ok(merge_speakers(segments, &speakers))
+    }
+}
+
+fn speaker_prompt(num_speakers: Option<u8>) -> String {
+    let speakers = match num_speakers {
+        Some(n) => format!("{} speakers", n),
+        None => "an unknown number of speakers".to_string(),
+    };
+    format!(
+        "Mark speaker changes in this transcript given {}. Each line is a segment prefixed with its index. \
+Number the speakers from 0 in order of appearance. Segments that already name a speaker were labelled before, keep \
+their speaker ids and label only the other segments.",
+        speakers
+    )
+}
+
+fn window_content(
+    segments: &[WhisperSegment],
+    speakers: &[Option<u8>],
+    range: std::ops::Range<usize>,
+) -> String {
+    range
+        .map(|i| match speakers[i] {
+            Some(speaker) => format!("[{}] (speaker {}) {}", i, speaker, segments[i].text.trim()),
+            None => format!("[{}] {}", i, segments[i].text.trim()),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Attach the speaker labels to `segments`. Segments the model skipped keep the speaker of the
+/// previous segment.
+fn merge_speakers(segments: Vec<WhisperSegment>, speakers: &[Option<u8>]) -> Vec<DiarizedSegment> {
+    let mut last = 0;
+    segments
+        .into_iter()
+        .zip(speakers)
+        .map(|(segment, speaker)| {
+            last = speaker.unwrap_or(last);
+            DiarizedSegment {
+                speaker_id: last,
+                start_secs: segment.start,
+                end_secs: segment.end,
+                text: segment.text.trim().to_string(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(id: usize, text: &str) -> WhisperSegment {
+        WhisperSegment {
+            id,
+            start: id as f32,
+            end: id as f32 + 1.0,
+            text: format!(" {}", text),
+        }
+    }
+
+    #[test]
+    fn merge_speakers_should_fill_skipped_segments() {
+        let segments = vec![
+            segment(0, "Hi."),
+            segment(1, "Hello."),
+            segment(2, "How are you?"),
+        ];
+        let diarized = merge_speakers(segments, &[Some(0), Some(1), None]);
+        let speakers: Vec<_> = diarized.iter().map(|s| s.speaker_id).collect();
+        assert_eq!(speakers, vec![0, 1, 1]);
+        assert_eq!(diarized[2].text, "How are you?");
+        assert_eq!((diarized[2].start_secs, diarized[2].end_secs), (2.0, 3.0));
+    }
+
+    #[test]
+    fn window_content_should_mark_labelled_segments() {
+        let segments = vec![segment(0, "Hi."), segment(1, "Hello.")];
+        let content = window_content(&segments, &[Some(0), None], 0..2);
+        assert_eq!(content, "[0] (speaker 0) Hi.\n[1] Hello.");
+    }
+}
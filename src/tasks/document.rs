@@ -0,0 +1,202 @@
+use crate::{LlmError, LlmSDK};
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const QA_PROMPT: &str = "Answer the user's question using only the document below. If the document doesn't contain the answer, say so and give a low confidence. Rate your confidence in the answer from 0 to 1.";
+/// The documents sent to `LlmSDK::multi_document_qa`, about 12k tokens, so they fit the default
+/// model with room for the question and answer.
+const MAX_QA_CHARS: usize = 48_000;
+
+#[derive(Debug, Clone, Default)]
+pub struct DocumentQaRequest {
+    pub document: String,
+    pub question: String,
+    /// The maximum length of the answer in words.
+    pub max_answer_length: Option<usize>,
+    /// Quote the excerpts of the document the answer is based on.
+    pub cite_sources: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DocumentQaResult {
+    pub answer: String,
+    /// How sure the model is that the document answers the question, from 0 to 1.
+    pub confidence: f32,
+    /// Empty unless `cite_sources` was set.
+    pub citations: Vec<DocumentCitation>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DocumentCitation {
+    /// A verbatim excerpt of the document.
+    pub excerpt: String,
+    /// How much the excerpt supports the answer, from 0 to 1.
+    pub relevance: f32,
+}
+
+impl DocumentQaRequest {
+    pub fn new(document: impl Into<String>, question: impl Into<String>) -> Self {
+        Self {
+            document: document.into(),
+            question: question.into(),
+            ..Default::default()
+        }
+    }
+
+    fn instructions(&self) -> String {
+        let mut prompt = QA_PROMPT.to_string();
+        if let Some(max) = self.max_answer_length {
+            prompt.push_str(&format!(" Answer in at most {} words.", max));
+        }
+        if self.cite_sources {
+            prompt.push_str(" Cite the excerpts of the document that support the answer verbatim, with their relevance from 0 to 1.");
+        } else {
+            prompt.push_str(" Leave the citations empty.");
+        }
+        format!("{}\n\nDocument:\n{}", prompt, self.document)
+    }
+}
+
+impl LlmSDK {
+    /// Answer a question about a document, optionally citing the excerpts the answer is based on.
+    pub async fn document_qa(&self, req: DocumentQaRequest) -> Result<DocumentQaResult> {
+        let mut res: DocumentQaResult = self.extract(&req.instructions(), &req.question).await?;
+        if !req.cite_sources {
+            res.citations.clear();
+        }
+        Ok(res)
+    }
+
+    /// Answer a question about several documents, citing excerpts. Documents that don't fit
+    /// together in the context window are truncated to an equal share each.
+    pub async fn multi_document_qa(
+        &self,
+        documents: Vec<String>,
+        question: &str,
+    ) -> Result<DocumentQaResult> {
+        let req = DocumentQaRequest {
+            cite_sources: true,
+            ..DocumentQaRequest::new(join_documents(&documents, MAX_QA_CHARS), question)
+        };
+        self.document_qa(req).await
+    }
+
+    /// Parse the text of a document into a `T`. `schema_name` tells the model what it is
+    /// extracting, `instructions` can narrow it down further.
+    pub async fn parse_document<T: DeserializeOwned + JsonSchema>(
+        &self,
+        text: &str,
+        schema_name: &str,
+        instructions: &str,
+    ) -> Result<T> {
+        let instructions = format!(
+            "You extract {} from the document given by the user. {}",
+            schema_name, instructions
+        );
+        self.extract(&instructions, text).await
+    }
+
+    /// Read a plain text or PDF file and parse it with `parse_document`. PDF files are detected
+    /// by extension or content and require the `pdf` feature.
+    pub async fn parse_document_from_file<T: DeserializeOwned + JsonSchema>(
+        &self,
+        path: &Path,
+        instructions: &str,
+    ) -> Result<T> {
+        let data = tokio::fs::read(path).await.map_err(|e| {
+            LlmError::DocumentParseError(format!("failed to read {}: {}", path.display(), e))
+        })?;
+        let text = document_text(path, data)?;
+        self.parse_document(&text, &T::schema_name(), instructions)
+            .await
+    }
+}
+
+/// Join `documents` with `--- Document N ---` separators. If they are longer than `max_chars`
+/// together, each is cut to an equal share of it.
+fn join_documents(documents: &[String], max_chars: usize) -> String {
+    let total: usize = documents.iter().map(|d| d.chars().count()).sum();
+    let share = max_chars / documents.len().max(1);
+    documents
+        .iter()
+        .enumerate()
+        .map(|(i, document)| {
+            let text: String = if total > max_chars {
+                document.chars().take(share).collect()
+            } else {
+                document.clone()
+            };
+            format!("--- Document {} ---\n{}", i + 1, text)
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn document_text(path: &Path, data: Vec<u8>) -> Result<String, LlmError> {
+    if is_pdf(path, &data) {
+        return pdf_text(&data);
+    }
+    String::from_utf8(data).map_err(|_| {
+        LlmError::DocumentParseError(format!("{} is not a UTF-8 text file", path.display()))
+    })
+}
+
+fn is_pdf(path: &Path, data: &[u8]) -> bool {
+    data.starts_with(b"%PDF-")
+        || path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("pdf"))
+}
+
+#[cfg(feature = "pdf")]
+fn pdf_text(data: &[u8]) -> Result<String, LlmError> {
+    pdf_extract::extract_text_from_mem(data)
+        .map_err(|e| LlmError::DocumentParseError(format!("failed to extract PDF text: {}", e)))
+}
+
+#[cfg(not(feature = "pdf"))]
+fn pdf_text(_data: &[u8]) -> Result<String, LlmError> {
+    Err(LlmError::DocumentParseError(
+        "PDF documents require the `pdf` feature".into(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn join_documents_should_number_and_truncate() {
+        let documents = vec!["abcdef".to_string(), "xyz".to_string()];
+        assert_eq!(
+            join_documents(&documents, 100),
+            "--- Document 1 ---\nabcdef\n\n--- Document 2 ---\nxyz"
+        );
+        assert_eq!(
+            join_documents(&documents, 6),
+            "--- Document 1 ---\nabc\n\n--- Document 2 ---\nxyz"
+        );
+    }
+
+    #[test]
+    fn document_text_should_read_utf8() {
+        let text = document_text(Path::new("notes.txt"), b"hello".to_vec()).unwrap();
+        assert_eq!(text, "hello");
+    }
+
+    #[test]
+    fn document_text_should_reject_binary() {
+        let err = document_text(Path::new("notes.bin"), vec![0xff, 0xfe]).unwrap_err();
+        assert!(matches!(err, LlmError::DocumentParseError(_)));
+    }
+
+    #[test]
+    fn is_pdf_should_check_extension_and_magic() {
+        assert!(is_pdf(Path::new("a.PDF"), b""));
+        assert!(is_pdf(Path::new("a"), b"%PDF-1.7"));
+        assert!(!is_pdf(Path::new("a.txt"), b"text"));
+    }
+}
@@ -0,0 +1,133 @@
+use crate::LlmSDK;
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+const KEYWORDS_PROMPT: &str = "Extract the keywords and key phrases that best describe the user's text. Rate the relevance of each from 0 to 1 and give an optional category, e.g. topic, technology or place. Return at most {max} keywords, most relevant first.";
+const ENTITIES_PROMPT: &str = "Find all named entities in the user's text: people, organizations, locations, dates, amounts of money, percentages, products and events. For each entity give its text exactly as it appears, its type, and your confidence from 0 to 1.";
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Keywords {
+    pub keywords: Vec<Keyword>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Keyword {
+    pub word: String,
+    /// How well the keyword describes the text, from 0 to 1.
+    pub relevance: f32,
+    pub category: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct NamedEntities {
+    pub entities: Vec<NamedEntity>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct NamedEntity {
+    /// The entity exactly as it appears in the text.
+    pub text: String,
+    pub entity_type: EntityType,
+    /// The character offsets of the first occurrence of the entity, `None` if it isn't found
+    /// verbatim in the text.
+    pub start_char: Option<usize>,
+    pub end_char: Option<usize>,
+    pub confidence: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum EntityType {
+    Person,
+    Organization,
+    Location,
+    Date,
+    Money,
+    Percentage,
+    Product,
+    Event,
+    /// Any other kind of entity, e.g. a law or a work of art.
+    #[serde(untagged)]
+    Other(String),
+}
+
+impl LlmSDK {
+    /// Extract up to `max_keywords` keywords of `text`, most relevant first.
+    pub async fn extract_keywords(&self, text: &str, max_keywords: usize) -> Result<Keywords> {
+        let prompt = KEYWORDS_PROMPT.replace("{max}", &max_keywords.to_string());
+        let mut keywords: Keywords = self.extract(&prompt, text).await?;
+        keywords
+            .keywords
+            .sort_by(|a, b| b.relevance.total_cmp(&a.relevance));
+        keywords.keywords.truncate(max_keywords);
+        Ok(keywords)
+    }
+
+    /// Find the named entities in `text`. The offsets are computed locally, the ones reported by
+    /// the model are not reliable.
+    pub async fn extract_named_entities(&self, text: &str) -> Result<NamedEntities> {
+        let mut entities: NamedEntities = self.extract(ENTITIES_PROMPT, text).await?;
+        locate_entities(text, &mut entities.entities);
+        Ok(entities)
+    }
+}
+
+/// Set the character offsets of each entity to its first occurrence in `text`, searching
+/// repeated entities after the previous occurrence.
+fn locate_entities(text: &str, entities: &mut [NamedEntity]) {
+    let mut searched_from = std::collections::HashMap::new();
+    for entity in entities {
+        let from = searched_from.get(&entity.text).copied().unwrap_or(0);
+        match text[from..]
+            .find(&entity.text)
+            .filter(|_| !entity.text.is_empty())
+        {
+            Some(offset) => {
+                let start = from + offset;
+                let end = start + entity.text.len();
+                entity.start_char = Some(text[..start].chars().count());
+                entity.end_char = Some(text[..end].chars().count());
+                searched_from.insert(entity.text.clone(), end);
+            }
+            None => {
+                entity.start_char = None;
+                entity.end_char = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locate_entities_should_use_char_offsets() {
+        let text = "Zoë met Acme in Paris. Acme paid.";
+        let mut entities: Vec<NamedEntity> = serde_json::from_str(
+            r#"[
+                {"text": "Acme", "entity_type": "ORGANIZATION", "start_char": 0, "end_char": 0, "confidence": 0.9},
+                {"text": "Paris", "entity_type": "LOCATION", "start_char": null, "end_char": null, "confidence": 0.9},
+                {"text": "Acme", "entity_type": "ORGANIZATION", "start_char": 0, "end_char": 0, "confidence": 0.9},
+                {"text": "Lyon", "entity_type": "city", "start_char": 3, "end_char": 7, "confidence": 0.2}
+            ]"#,
+        )
+        .unwrap();
+        locate_entities(text, &mut entities);
+        let offsets: Vec<_> = entities
+            .iter()
+            .map(|e| (e.start_char, e.end_char))
+            .collect();
+        assert_eq!(
+            offsets,
+            vec![
+                (Some(8), Some(12)),
+                (Some(16), Some(21)),
+                (Some(23), Some(27)),
+                (None, None)
+            ]
+        );
+        assert_eq!(entities[3].entity_type, EntityType::Other("city".into()));
+    }
+}
@@ -0,0 +1,113 @@
+use crate::{ListModelsRequest, LlmSDK, ModelList};
+use anyhow::{bail, Result};
+use std::time::{Duration, Instant};
+
+const RATE_LIMIT_REMAINING_HEADER: &str = "x-ratelimit-remaining-requests";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HealthStatus {
+    pub api_reachable: bool,
+    /// The round-trip time of the check.
+    pub latency_ms: u64,
+    /// The requests left in the current rate limit window, if reported.
+    pub rate_limit_remaining: Option<u32>,
+    /// Whether the model asked for by `health_check_model` is available.
+    pub model_available: Option<bool>,
+}
+
+impl LlmSDK {
+    /// Check that the API is reachable by listing the models, which is cheap.
+    pub async fn health_check(&self) -> Result<HealthStatus> {
+        self.check_health(None).await
+    }
+
+    /// Like `health_check`, but also check that `model` is available to the API key.
+    pub async fn health_check_model(&self, model: &str) -> Result<HealthStatus> {
+        self.check_health(Some(model)).await
+    }
+
+    /// Whether `GET /models` succeeds.
+    pub async fn health_check_fast(&self) -> Result<bool> {
+        Ok(self.list_models().await.is_ok())
+    }
+
+    /// Poll `health_check_fast` every `poll_interval` until it succeeds, e.g. in a readiness
+    /// probe. Fails if the API isn't healthy within `timeout`.
+    pub async fn wait_until_healthy(
+        &self,
+        timeout: Duration,
+        poll_interval: Duration,
+    ) -> Result<()> {
+        let start = Instant::now();
+        loop {
+            if self.health_check_fast().await? {
+                return Ok(());
+            }
+            if start.elapsed() + poll_interval > timeout {
+                bail!("API not healthy after {:?}", timeout);
+            }
+            self.sleeper.sleep(poll_interval).await;
+        }
+    }
+
+    async fn check_health(&self, model: Option<&str>) -> Result<HealthStatus> {
+        let start = Instant::now();
+        let res = self.send_with_status::<ModelList>(ListModelsRequest).await;
+        let latency_ms = start.elapsed().as_millis() as u64;
+        let status = match res {
+            Ok(res) => HealthStatus {
+                api_reachable: true,
+                latency_ms,
+                rate_limit_remaining: res
+                    .headers
+                    .get(RATE_LIMIT_REMAINING_HEADER)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse().ok()),
+                model_available: model.map(|model| res.body.contains(model)),
+            },
+            Err(_) => HealthStatus {
+                api_reachable: false,
+                latency_ms,
+                rate_limit_remaining: None,
+                model_available: None,
+            },
+        };
+        Ok(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_server::serve;
+    use crate::LlmSDK;
+    use anyhow::Result;
+    use std::time::Duration;
+
+    fn models() -> String {
+        serde_json::json!({
+            "object": "list",
+            "data": [{"id": "gpt-4o-mini", "object": "model", "created": 1721172741, "owned_by": "system"}]
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn health_check_should_report_model_availability() -> Result<()> {
+        let url = serve(vec![(200, models()), (200, models())]);
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let status = sdk.health_check_model("gpt-4o-mini").await?;
+        assert!(status.api_reachable);
+        assert_eq!(status.model_available, Some(true));
+        let status = sdk.health_check_model("gpt-5").await?;
+        assert_eq!(status.model_available, Some(false));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn wait_until_healthy_should_poll_until_success() -> Result<()> {
+        let url = serve(vec![(401, String::new()), (200, models())]);
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        sdk.wait_until_healthy(Duration::from_secs(5), Duration::from_millis(10))
+            .await
+    }
+}
@@ -0,0 +1,231 @@
+use crate::{
+    ChatCompleteModel, ChatCompletionMessage, ChatCompletionRequestBuilder, ChatResponseFormat,
+    ChatResponseFormatObject, ContentPart, EditImageRequestBuilder, ImageInput,
+    ImageResponseFormat, LlmSDK,
+};
+use anyhow::{anyhow, bail, Result};
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+const DESCRIBE_IMAGE_PROMPT: &str = "Describe the image and list its distinct regions: the main subjects, objects and background areas. Give each region a short label, a description and a bounding box.";
+const SELECT_REGION_PROMPT: &str = "You plan image edits. Given the regions of an image and an edit instruction, pick the region that has to change and write a prompt for an image editing model that describes the whole image after the edit.";
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ImageDescription {
+    /// A one paragraph description of the whole image.
+    pub summary: String,
+    pub regions: Vec<ImageRegion>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ImageRegion {
+    pub label: String,
+    pub description: String,
+    pub bounding_box: BoundingBox,
+}
+
+/// A rectangle in fractions of the image size, from the top left corner.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct BoundingBox {
+    /// The left edge, from 0.0 to 1.0.
+    pub x: f32,
+    /// The top edge, from 0.0 to 1.0.
+    pub y: f32,
+    /// The width, from 0.0 to 1.0.
+    pub width: f32,
+    /// The height, from 0.0 to 1.0.
+    pub height: f32,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct RegionSelection {
+    /// The index of the region to edit in the list of regions.
+    region: usize,
+    /// The prompt for the image editing model.
+    prompt: String,
+}
+
+impl LlmSDK {
+    /// Describe an image and the bounding boxes of its regions with a vision model.
+    pub async fn describe_image_structured(&self, image_bytes: &[u8]) -> Result<ImageDescription> {
+        let image = ImageInput::bytes(image_bytes.to_vec(), image_mime_type(image_bytes));
+        let messages = vec![
+            ChatCompletionMessage::new_system(DESCRIBE_IMAGE_PROMPT, ""),
+            ChatCompletionMessage::new_user_with_parts(vec![ContentPart::image(&image)], ""),
+        ];
+        let req = ChatCompletionRequestBuilder::default()
+            .model(ChatCompleteModel::Gpt4oMini)
+            .messages(messages)
+            .response_format(ChatResponseFormatObject::new(ChatResponseFormat::Json))
+            .build()?;
+        self.chat_completion_typed_with_retry(req, 2).await
+    }
+
+    /// Edit a PNG image following `edit_instruction` without drawing a mask: a vision model
+    /// locates the regions of the image, the chat model picks the one to change, and its
+    /// bounding box becomes the mask. Returns the edited image as PNG.
+    ///
+    /// Bounding boxes are approximate and rectangular, so the edit may spill over into the
+    /// surroundings or miss parts of an irregular object. Draw the mask yourself and use
+    /// `edit_image` when the shape matters. The image must be a square PNG as required by the
+    /// edits endpoint.
+    pub async fn image_edit_auto_mask(
+        &self,
+        image_bytes: Vec<u8>,
+        edit_instruction: &str,
+    ) -> Result<Vec<u8>> {
+        let (width, height) = png_dimensions(&image_bytes)?;
+        let description = self.describe_image_structured(&image_bytes).await?;
+        let mut text = format!(
+            "Image: {}\nEdit instruction: {}\n",
+            description.summary, edit_instruction
+        );
+        for (index, region) in description.regions.iter().enumerate() {
+            text.push_str(&format!(
+                "\nRegion {}: {} - {}",
+                index, region.label, region.description
+            ));
+        }
+        let selection: RegionSelection = self.extract(SELECT_REGION_PROMPT, &text).await?;
+        let region = description
+            .regions
+            .get(selection.region)
+            .ok_or_else(|| anyhow!("region {} does not exist", selection.region))?;
+
+        let req = EditImageRequestBuilder::default()
+            .image(image_bytes)
+            .mask(rectangle_mask(width, height, &region.bounding_box)?)
+            .prompt(selection.prompt)
+            .response_format(ImageResponseFormat::B64Json)
+            .build()?;
+        let res = self.edit_image(req).await?;
+        let image = res
+            .data
+            .first()
+            .ok_or_else(|| anyhow!("no image returned"))?;
+        Ok(self.download_image(image).await?.to_vec())
+    }
+}
+
+/// The MIME type of an image from its magic bytes, for the formats the vision models accept.
+/// Unknown formats are sent as PNG.
+fn image_mime_type(image: &[u8]) -> &'static str {
+    if image.starts_with(b"\xff\xd8\xff") {
+        "image/jpeg"
+    } else if image.starts_with(b"GIF8") {
+        "image/gif"
+    } else if image.len() >= 12 && image.starts_with(b"RIFF") && &image[8..12] == b"WEBP" {
+        "image/webp"
+    } else {
+        "image/png"
+    }
+}
+
+/// The width and height from the IHDR chunk of a PNG.
+fn png_dimensions(png: &[u8]) -> Result<(u32, u32)> {
+    if png.len() < 24 || !png.starts_with(PNG_SIGNATURE) || &png[12..16] != b"IHDR" {
+        bail!("image is not a PNG");
+    }
+    let width = u32::from_be_bytes(png[16..20].try_into()?);
+    let height = u32::from_be_bytes(png[20..24].try_into()?);
+    Ok((width, height))
+}
+
+/// An opaque RGBA PNG that is transparent inside `bbox`, the area to edit.
+fn rectangle_mask(width: u32, height: u32, bbox: &BoundingBox) -> Result<Vec<u8>> {
+    let to_px = |fraction: f32, size: u32| (fraction.clamp(0.0, 1.0) * size as f32).round() as u32;
+    let (left, top) = (to_px(bbox.x, width), to_px(bbox.y, height));
+    let (right, bottom) = (
+        to_px(bbox.x + bbox.width, width),
+        to_px(bbox.y + bbox.height, height),
+    );
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    let mut row = Vec::with_capacity(1 + width as usize * 4);
+    for y in 0..height {
+        row.clear();
+        // filter type none
+        row.push(0);
+        for x in 0..width {
+            let inside = (left..right).contains(&x) && (top..bottom).contains(&y);
+            row.extend_from_slice(&[0, 0, 0, if inside { 0 } else { 255 }]);
+        }
+        encoder.write_all(&row)?;
+    }
+    let data = encoder.finish()?;
+
+    let mut ihdr = Vec::with_capacity(13);
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    // 8 bit RGBA, default compression and filtering, not interlaced
+    ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &ihdr);
+    write_chunk(&mut png, b"IDAT", &data);
+    write_chunk(&mut png, b"IEND", &[]);
+    Ok(png)
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(kind);
+    crc.update(data);
+    png.extend_from_slice(&crc.finalize().to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+
+    #[tokio::test]
+    async fn describe_image_structured_should_send_sniffed_type_and_schema_once() -> Result<()> {
+        let description = r#"{"summary": "A cat.", "regions": []}"#;
+        let (url, received) =
+            test_server::serve_recording(vec![(200, test_server::chat_response(description))]);
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let jpeg = b"\xff\xd8\xff\xe0jpeg";
+        let res = sdk.describe_image_structured(jpeg).await?;
+        assert_eq!(res.summary, "A cat.");
+        let body = received.lock().unwrap()[0].clone();
+        assert!(body.contains("data:image/jpeg;base64,"));
+        assert_eq!(body.matches("JSON schema").count(), 1);
+        assert_eq!(image_mime_type(b"RIFF\0\0\0\0WEBPVP8 "), "image/webp");
+        Ok(())
+    }
+
+    #[test]
+    fn rectangle_mask_should_be_transparent_inside_the_box() -> Result<()> {
+        let bbox = BoundingBox {
+            x: 0.5,
+            y: 0.25,
+            width: 0.5,
+            height: 0.5,
+        };
+        let png = rectangle_mask(4, 4, &bbox)?;
+        assert_eq!(png_dimensions(&png)?, (4, 4));
+
+        // IDAT follows the 8 byte signature and the 25 byte IHDR chunk
+        let len = u32::from_be_bytes(png[33..37].try_into()?) as usize;
+        assert_eq!(&png[37..41], b"IDAT");
+        let mut pixels = vec![];
+        ZlibDecoder::new(&png[41..41 + len]).read_to_end(&mut pixels)?;
+        let alpha = |x: usize, y: usize| pixels[y * 17 + 1 + x * 4 + 3];
+        assert_eq!(alpha(0, 0), 255);
+        assert_eq!(alpha(2, 1), 0);
+        assert_eq!(alpha(3, 2), 0);
+        assert_eq!(alpha(3, 3), 255);
+        assert!(png_dimensions(b"not a png at all, really").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+use crate::{CreateImageRequestBuilder, CreateImageResponse, ImageQuality, ImageStyle, LlmSDK};
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+const VARIATIONS_PROMPT: &str = "You are an expert image prompt engineer. Generate {n} variations of this image prompt, each differing along the specified axes: {axes}. Keep everything else about the prompt, and make the variations differ from each other, not just from the original.";
+const ANALYSIS_PROMPT: &str = "These image prompts are variations of one base prompt. Analyze the space they cover: which styles, moods and settings are explored, what they have in common, and what directions are still missing. Answer in one short paragraph.";
+
+const OPTIMIZE_PROMPT: &str = "You are an expert DALL-E 3 prompt engineer. Turn the user's raw idea into a single detailed image prompt: describe the subject, composition, lighting, colors and medium. Reply with the prompt only.";
+
+/// The result of `LlmSDK::optimize_and_generate_image`, keeping the optimized prompt for review.
+#[derive(Debug, Clone)]
+pub struct PromptOptimizationResult {
+    pub original: String,
+    pub optimized: String,
+    pub image: CreateImageResponse,
+}
+
+/// An aspect of an image prompt to vary, see `LlmSDK::generate_prompt_variations`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PromptAxis {
+    Style,
+    Lighting,
+    Composition,
+    Mood,
+    Subject,
+    Setting,
+    TimeOfDay,
+}
+
+#[derive(Debug, Clone)]
+pub struct PromptExplorationResult {
+    pub prompts: Vec<String>,
+    /// What the prompts cover and what they leave out.
+    pub theme_analysis: String,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct PromptVariations {
+    variations: Vec<String>,
+}
+
+impl PromptAxis {
+    pub const ALL: [PromptAxis; 7] = [
+        PromptAxis::Style,
+        PromptAxis::Lighting,
+        PromptAxis::Composition,
+        PromptAxis::Mood,
+        PromptAxis::Subject,
+        PromptAxis::Setting,
+        PromptAxis::TimeOfDay,
+    ];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            PromptAxis::Style => "style",
+            PromptAxis::Lighting => "lighting",
+            PromptAxis::Composition => "composition",
+            PromptAxis::Mood => "mood",
+            PromptAxis::Subject => "subject",
+            PromptAxis::Setting => "setting",
+            PromptAxis::TimeOfDay => "time of day",
+        }
+    }
+}
+
+impl LlmSDK {
+    /// Rewrite `base_prompt` into `n` variations that differ along `variation_axes`, or along
+    /// any axis if none are given. Returns fewer than `n` prompts if the model does.
+    pub async fn generate_prompt_variations(
+        &self,
+        base_prompt: &str,
+        n: usize,
+        variation_axes: Vec<PromptAxis>,
+    ) -> Result<Vec<String>> {
+        let axes = if variation_axes.is_empty() {
+            PromptAxis::ALL.to_vec()
+        } else {
+            variation_axes
+        };
+        let axes: Vec<_> = axes.iter().map(PromptAxis::as_str).collect();
+        let instructions = VARIATIONS_PROMPT
+            .replace("{n}", &n.to_string())
+            .replace("{axes}", &axes.join(", "));
+        let mut res: PromptVariations = self.extract(&instructions, base_prompt).await?;
+        res.variations.truncate(n);
+        Ok(res.variations)
+    }
+
+    /// Generate `n` variations of `base_prompt` along all axes, with an analysis of the range
+    /// they cover to guide the next round of exploration.
+    pub async fn explore_prompt_space(
+        &self,
+        base_prompt: &str,
+        n: usize,
+    ) -> Result<PromptExplorationResult> {
+        let prompts = self
+            .generate_prompt_variations(base_prompt, n, PromptAxis::ALL.to_vec())
+            .await?;
+        let listed = prompts
+            .iter()
+            .map(|prompt| format!("- {}", prompt))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let theme_analysis = self.complete(ANALYSIS_PROMPT, &listed).await?;
+        Ok(PromptExplorationResult {
+            prompts,
+            theme_analysis,
+        })
+    }
+
+    /// Use GPT to turn a rough idea into a detailed DALL-E prompt for the given style.
+    pub async fn optimize_image_prompt(&self, idea: &str, style: ImageStyle) -> Result<String> {
+        let style = match style {
+            ImageStyle::Vivid => "vivid: hyper-real and dramatic",
+            ImageStyle::Natural => "natural: realistic and not hyper-real",
+        };
+        let system = format!("{} The image style is {}.", OPTIMIZE_PROMPT, style);
+        self.complete(&system, idea).await
+    }
+
+    /// Optimize the prompt for `idea` and generate an image from it.
+    pub async fn generate_image_from_idea(
+        &self,
+        idea: &str,
+        style: ImageStyle,
+        quality: ImageQuality,
+    ) -> Result<CreateImageResponse> {
+        let res = self
+            .optimize_and_generate_image(idea, style, quality)
+            .await?;
+        Ok(res.image)
+    }
+
+    /// Like `generate_image_from_idea`, but also return the original and optimized prompt.
+    pub async fn optimize_and_generate_image(
+        &self,
+        idea: &str,
+        style: ImageStyle,
+        quality: ImageQuality,
+    ) -> Result<PromptOptimizationResult> {
+        let optimized = self.optimize_image_prompt(idea, style).await?;
+        let req = CreateImageRequestBuilder::default()
+            .prompt(optimized.clone())
+            .style(style)
+            .quality(quality)
+            .build()?;
+        let image = self.create_image(req).await?;
+        Ok(PromptOptimizationResult {
+            original: idea.to_owned(),
+            optimized,
+            image,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn explore_prompt_space_should_return_n_prompts_and_analysis() -> Result<()> {
+        let variations =
+            json!({"variations": ["a fox at dawn", "a fox in neon light", "a fox in oil paint"]});
+        let url = test_server::serve(vec![
+            (200, test_server::chat_response(&variations.to_string())),
+            (200, test_server::chat_response("Mostly lighting changes.")),
+        ]);
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let res = sdk.explore_prompt_space("a fox", 2).await?;
+        assert_eq!(res.prompts, vec!["a fox at dawn", "a fox in neon light"]);
+        assert_eq!(res.theme_analysis, "Mostly lighting changes.");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn optimize_image_prompt_should_ask_for_the_style() -> Result<()> {
+        let (url, received) = test_server::serve_recording(vec![(
+            200,
+            test_server::chat_response("A red fox in morning mist"),
+        )]);
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let prompt = sdk
+            .optimize_image_prompt("a fox", ImageStyle::Natural)
+            .await?;
+        assert_eq!(prompt, "A red fox in morning mist");
+        let body = received.lock().unwrap()[0].clone();
+        assert!(body.contains("natural: realistic and not hyper-real"));
+        assert!(body.contains("a fox"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn optimize_and_generate_image_should_generate_the_optimized_prompt() -> Result<()> {
+        let image = json!({"created": 1700000000, "data": [{"url": "https://cdn/fox.png"}]});
+        let responses = vec![
+            (200, test_server::chat_response("A red fox in morning mist")),
+            (200, image.to_string()),
+        ];
+        let (url, received) = test_server::serve_recording(responses.clone());
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let res = sdk
+            .optimize_and_generate_image("a fox", ImageStyle::Natural, ImageQuality::Hd)
+            .await?;
+        assert_eq!(res.original, "a fox");
+        assert_eq!(res.optimized, "A red fox in morning mist");
+        assert_eq!(
+            res.image.data[0].url.as_deref(),
+            Some("https://cdn/fox.png")
+        );
+        let req: serde_json::Value = serde_json::from_str(&received.lock().unwrap()[1])?;
+        assert_eq!(req["prompt"], "A red fox in morning mist");
+        assert_eq!(req["style"], "natural");
+        assert_eq!(req["quality"], "hd");
+
+        let url = test_server::serve(responses);
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let res = sdk
+            .generate_image_from_idea("a fox", ImageStyle::Vivid, ImageQuality::Standard)
+            .await?;
+        assert_eq!(res.data[0].url.as_deref(), Some("https://cdn/fox.png"));
+        Ok(())
+    }
+}
@@ -0,0 +1,47 @@
+mod audio_description;
+mod classify;
+mod cluster;
+mod code;
+mod determinism;
+mod diarize;
+mod document;
+mod entities;
+mod health;
+mod image_edit;
+mod image_prompt;
+mod moderation;
+mod narration;
+mod pii;
+mod rag;
+mod report;
+mod rerank;
+mod speech_timing;
+mod summarize;
+mod tagging;
+mod test_data;
+mod transcription;
+mod translate;
+mod vector_index;
+
+pub use audio_description::*;
+pub use classify::*;
+pub use cluster::*;
+pub use code::*;
+pub use determinism::*;
+pub use diarize::*;
+pub use document::*;
+pub use entities::*;
+pub use health::*;
+pub use image_edit::*;
+pub use image_prompt::*;
+pub use moderation::*;
+pub use pii::*;
+pub use report::*;
+pub use rerank::*;
+pub use speech_timing::*;
+pub use summarize::*;
+pub use tagging::*;
+pub use test_data::*;
+pub use transcription::*;
+pub use translate::*;
+pub use vector_index::*;
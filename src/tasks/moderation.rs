@@ -0,0 +1,158 @@
+use crate::{
+    first_content, ChatCompletionRequest, ChatCompletionResponse, LlmSDK, ModerationRequest,
+    ModerationResult,
+};
+use anyhow::{anyhow, Result};
+
+#[derive(Debug, Clone)]
+pub struct ModerationPipelineResult<T> {
+    /// The output, `None` if it was blocked.
+    pub output: Option<T>,
+    /// The merged moderation of the input and output, empty if neither was moderated.
+    pub moderation: ModerationResult,
+    pub was_blocked: bool,
+}
+
+impl<T> ModerationPipelineResult<T> {
+    fn blocked(moderation: ModerationResult) -> Self {
+        Self {
+            output: None,
+            moderation,
+            was_blocked: true,
+        }
+    }
+}
+
+impl LlmSDK {
+    /// Send `req` with its last user message and/or the reply moderated. The input is moderated
+    /// concurrently with the completion: moderation usually answers first, and a flagged input
+    /// drops the pending completion request instead of paying for a reply that is thrown away.
+    pub async fn moderated_completion(
+        &self,
+        req: ChatCompletionRequest,
+        moderate_input: bool,
+        moderate_output: bool,
+    ) -> Result<ModerationPipelineResult<ChatCompletionResponse>> {
+        let (mut moderation, res) = if moderate_input {
+            let input = req
+                .last_user_text()
+                .ok_or_else(|| anyhow!("no user message to moderate"))?;
+            let moderation = self.moderate_text(input);
+            let completion = self.chat_completion(req);
+            tokio::pin!(moderation, completion);
+            tokio::select! {
+                moderation = &mut moderation => {
+                    let moderation = moderation?;
+                    if moderation.flagged {
+                        return Ok(ModerationPipelineResult::blocked(moderation));
+                    }
+                    (moderation, completion.await?)
+                }
+                res = &mut completion => {
+                    let res = res?;
+                    let moderation = moderation.await?;
+                    if moderation.flagged {
+                        return Ok(ModerationPipelineResult::blocked(moderation));
+                    }
+                    (moderation, res)
+                }
+            }
+        } else {
+            (
+                ModerationResult::default(),
+                self.chat_completion(req).await?,
+            )
+        };
+
+        if moderate_output {
+            let output = self.moderate_text(first_content(res.clone())?).await?;
+            moderation = moderation.merge(output);
+            if moderation.flagged {
+                return Ok(ModerationPipelineResult::blocked(moderation));
+            }
+        }
+        Ok(ModerationPipelineResult {
+            output: Some(res),
+            moderation,
+            was_blocked: false,
+        })
+    }
+
+    async fn moderate_text(&self, text: String) -> Result<ModerationResult> {
+        self.moderation(ModerationRequest::new(text))
+            .await?
+            .results
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("no result returned in moderation"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_server, ChatCompleteModel, ChatCompletionMessage};
+    use serde_json::json;
+    use std::time::{Duration, Instant};
+
+    #[tokio::test]
+    async fn moderated_completion_should_block_flagged_output() -> Result<()> {
+        let moderation = json!({
+            "id": "modr-1",
+            "model": "omni-moderation-latest",
+            "results": [{
+                "flagged": true,
+                "categories": {"violence": true},
+                "category_scores": {"violence": 0.97}
+            }]
+        });
+        let url = test_server::serve(vec![
+            (200, test_server::chat_response("something violent")),
+            (200, moderation.to_string()),
+        ]);
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let req = ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4oMini,
+            vec![ChatCompletionMessage::new_user("Tell me a story", "")],
+        );
+        let res = sdk.moderated_completion(req, false, true).await?;
+        assert!(res.was_blocked);
+        assert!(res.output.is_none());
+        assert_eq!(res.moderation.flagged_categories(), vec!["violence"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn moderated_completion_should_drop_completion_of_flagged_input() -> Result<()> {
+        let moderation = json!({
+            "id": "modr-1",
+            "model": "omni-moderation-latest",
+            "results": [{
+                "flagged": true,
+                "categories": {"harassment": true},
+                "category_scores": {"harassment": 0.91}
+            }]
+        });
+        let url = test_server::serve_routes(vec![
+            ("/moderations", Duration::ZERO, 200, moderation.to_string()),
+            (
+                "/chat/completions",
+                Duration::from_secs(10),
+                200,
+                test_server::chat_response("never returned"),
+            ),
+        ]);
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let req = ChatCompletionRequest::new(
+            ChatCompleteModel::Gpt4oMini,
+            vec![ChatCompletionMessage::new_user("something hateful", "")],
+        );
+        let started = Instant::now();
+        let res = sdk.moderated_completion(req, true, false).await?;
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert!(res.was_blocked);
+        assert!(res.output.is_none());
+        assert_eq!(res.moderation.flagged_categories(), vec!["harassment"]);
+        Ok(())
+    }
+}
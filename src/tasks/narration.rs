@@ -0,0 +1,143 @@
+use super::vector_index::chunk_text;
+use crate::{LlmSDK, SpeechModel, SpeechRequestBuilder, SpeechResponseFormat, SpeechVoice};
+use anyhow::Result;
+use bytes::{Bytes, BytesMut};
+use futures::{stream, StreamExt, TryStreamExt};
+
+/// The maximum length of `SpeechRequest::input` in characters.
+const MAX_SPEECH_CHARS: usize = 4096;
+const SPEECH_CONCURRENCY: usize = 4;
+
+impl LlmSDK {
+    /// Generate mp3 speech for text of any length, see `speech_long_with_format`.
+    pub async fn speech_long(
+        &self,
+        text: &str,
+        voice: SpeechVoice,
+        model: SpeechModel,
+    ) -> Result<Bytes> {
+        self.speech_long_with_format(text, voice, model, SpeechResponseFormat::Mp3)
+            .await
+    }
+
+    /// Generate speech for text longer than the 4096 character limit of a single request. The
+    /// text is split on sentence boundaries, each chunk is synthesized separately and the audio is
+    /// concatenated in order.
+    ///
+    /// Concatenation only yields playable audio for streamable formats: mp3, aac (ADTS) and pcm.
+    /// Opus and flac audio is wrapped in a container with its own header, so the result of
+    /// joining several files plays only the first chunk in most players.
+    pub async fn speech_long_with_format(
+        &self,
+        text: &str,
+        voice: SpeechVoice,
+        model: SpeechModel,
+        format: SpeechResponseFormat,
+    ) -> Result<Bytes> {
+        let chunks = split_sentences(text, MAX_SPEECH_CHARS);
+        let parts: Vec<Bytes> = stream::iter(chunks)
+            .map(|chunk| {
+                let req = SpeechRequestBuilder::default()
+                    .model(model.clone())
+                    .input(chunk)
+                    .voice(voice.clone())
+                    .response_format(format)
+                    .build();
+                async move { self.speech(req?).await }
+            })
+            .buffered(SPEECH_CONCURRENCY)
+            .try_collect()
+            .await?;
+        let mut audio = BytesMut::with_capacity(parts.iter().map(Bytes::len).sum());
+        for part in parts {
+            audio.extend_from_slice(&part);
+        }
+        Ok(audio.freeze())
+    }
+}
+
+/// Split `text` into chunks of at most `max_chars` characters, breaking between sentences where
+/// possible and between words for sentences that are too long on their own.
+fn split_sentences(text: &str, max_chars: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for sentence in sentences(text) {
+        // trailing whitespace is trimmed off the chunk, so it does not count towards the limit
+        let len = sentence.trim_end().chars().count();
+        if !current.is_empty() && current.chars().count() + len > max_chars {
+            chunks.push(current.trim().to_owned());
+            current.clear();
+        }
+        if len > max_chars {
+            chunks.extend(chunk_text(sentence, max_chars));
+        } else {
+            current.push_str(sentence);
+        }
+    }
+    if !current.trim().is_empty() {
+        chunks.push(current.trim().to_owned());
+    }
+    chunks
+}
+
+/// The sentences of `text`, each including its terminating punctuation and trailing whitespace.
+fn sentences(text: &str) -> Vec<&str> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((_, c)) = chars.next() {
+        if !matches!(c, '.' | '!' | '?' | '\n') {
+            continue;
+        }
+        // a sentence ends at punctuation followed by whitespace
+        let mut end = None;
+        while let Some(&(i, next)) = chars.peek() {
+            if !next.is_whitespace() {
+                break;
+            }
+            chars.next();
+            end = Some(i + next.len_utf8());
+        }
+        if let Some(end) = end {
+            sentences.push(&text[start..end]);
+            start = end;
+        }
+    }
+    if start < text.len() {
+        sentences.push(&text[start..]);
+    }
+    sentences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+
+    #[test]
+    fn split_sentences_should_break_between_sentences() {
+        let chunks = split_sentences("One two. Three four! Five six? Seven", 20);
+        assert_eq!(chunks, vec!["One two. Three four!", "Five six? Seven"]);
+        let chunks = split_sentences("a very long sentence without a break", 12);
+        assert_eq!(
+            chunks,
+            vec!["a very long", "sentence", "without a", "break"]
+        );
+        assert_eq!(
+            split_sentences("3.5 is a number.", 20),
+            vec!["3.5 is a number."]
+        );
+    }
+
+    #[tokio::test]
+    async fn speech_long_should_concatenate_chunks() -> Result<()> {
+        let text = format!("{}. {}.", "a".repeat(3000), "b".repeat(3000));
+        let base_url = test_server::serve(vec![(200, "ab".into()), (200, "ab".into())]);
+        let sdk = LlmSDK::new_with_base_url("token", base_url);
+        let audio = sdk
+            .speech_long(&text, SpeechVoice::Nova, SpeechModel::Tts1)
+            .await?;
+        assert_eq!(&audio[..], b"abab");
+        Ok(())
+    }
+}
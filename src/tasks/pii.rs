@@ -0,0 +1,206 @@
+use crate::LlmSDK;
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+const PII_PROMPT: &str = "You are a data protection officer. Find all personally identifiable information (PII) in the user's text. For each entity give its category, the exact value as it appears in the text, and its start and end character offsets. Also return the text with every entity replaced by [CATEGORY_REDACTED], e.g. [EMAIL_REDACTED].";
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PiiDetectionResult {
+    pub contains_pii: bool,
+    pub categories: Vec<PiiCategory>,
+    pub redacted_text: String,
+    pub entities: Vec<PiiEntity>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum PiiCategory {
+    Name,
+    Email,
+    Phone,
+    Address,
+    CreditCard,
+    /// A social security number.
+    Ssn,
+    DateOfBirth,
+    IpAddress,
+    /// Any other kind of PII, e.g. a passport number.
+    #[serde(untagged)]
+    Other(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PiiEntity {
+    pub category: PiiCategory,
+    /// The entity exactly as it appears in the text.
+    pub value: String,
+    pub start_char: usize,
+    pub end_char: usize,
+}
+
+impl PiiCategory {
+    /// The placeholder used by `LlmSDK::redact_pii`, e.g. `[EMAIL_REDACTED]`.
+    pub fn placeholder(&self) -> String {
+        let label = match self {
+            PiiCategory::Name => "NAME".to_string(),
+            PiiCategory::Email => "EMAIL".to_string(),
+            PiiCategory::Phone => "PHONE".to_string(),
+            PiiCategory::Address => "ADDRESS".to_string(),
+            PiiCategory::CreditCard => "CREDIT_CARD".to_string(),
+            PiiCategory::Ssn => "SSN".to_string(),
+            PiiCategory::DateOfBirth => "DATE_OF_BIRTH".to_string(),
+            PiiCategory::IpAddress => "IP_ADDRESS".to_string(),
+            PiiCategory::Other(name) => name.trim().to_uppercase().replace([' ', '-'], "_"),
+        };
+        format!("[{}_REDACTED]", label)
+    }
+}
+
+impl LlmSDK {
+    pub async fn detect_pii(&self, text: &str) -> Result<PiiDetectionResult> {
+        self.extract(PII_PROMPT, text).await
+    }
+
+    /// Replace every PII entity found in `text` with its category placeholder.
+    pub async fn redact_pii(&self, text: &str) -> Result<String> {
+        let res = self.detect_pii(text).await?;
+        Ok(redact(text, &res.entities))
+    }
+}
+
+/// Replace each entity at its reported offsets if the text there is its value. The offsets
+/// reported by the model are not reliable, so otherwise every occurrence of the value as a whole
+/// word is replaced. Overlapping spans keep the one that starts first, or the longer one, so a
+/// value containing another is replaced whole.
+fn redact(text: &str, entities: &[PiiEntity]) -> String {
+    let mut spans = Vec::new();
+    for entity in entities.iter().filter(|e| !e.value.is_empty()) {
+        let range = char_range(text, entity.start_char, entity.end_char);
+        match range.filter(|r| text[r.clone()] == entity.value) {
+            Some(range) => spans.push((range, entity)),
+            None => spans.extend(
+                text.match_indices(&entity.value)
+                    .map(|(start, value)| start..start + value.len())
+                    .filter(|r| is_whole_word(text, r))
+                    .map(|r| (r, entity)),
+            ),
+        }
+    }
+    spans.sort_by_key(|(r, _)| (r.start, std::cmp::Reverse(r.end)));
+    let mut redacted = String::with_capacity(text.len());
+    let mut end = 0;
+    for (range, entity) in spans {
+        if range.start < end {
+            continue;
+        }
+        redacted.push_str(&text[end..range.start]);
+        redacted.push_str(&entity.category.placeholder());
+        end = range.end;
+    }
+    redacted.push_str(&text[end..]);
+    redacted
+}
+
+/// The byte range of the characters `start..end` of `text`, if they are in it.
+fn char_range(text: &str, start: usize, end: usize) -> Option<std::ops::Range<usize>> {
+    let mut offsets = text
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(text.len()));
+    let start_byte = offsets.nth(start)?;
+    let end_byte = if end == start {
+        start_byte
+    } else {
+        offsets.nth(end.checked_sub(start + 1)?)?
+    };
+    Some(start_byte..end_byte)
+}
+
+/// Whether `range` of `text` isn't part of a longer word, e.g. "Ann" in "Annual".
+fn is_whole_word(text: &str, range: &std::ops::Range<usize>) -> bool {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let matched = &text[range.clone()];
+    let joins = |outside: Option<char>, inside: Option<char>| {
+        outside.is_some_and(is_word) && inside.is_some_and(is_word)
+    };
+    !joins(
+        text[..range.start].chars().next_back(),
+        matched.chars().next(),
+    ) && !joins(
+        text[range.end..].chars().next(),
+        matched.chars().next_back(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entity(category: PiiCategory, value: &str) -> PiiEntity {
+        PiiEntity {
+            category,
+            value: value.to_string(),
+            start_char: 0,
+            end_char: 0,
+        }
+    }
+
+    #[test]
+    fn redact_should_replace_entities_with_placeholders() {
+        let text = "Mail John Smith at john@example.com or call John.";
+        let entities = vec![
+            entity(PiiCategory::Name, "John"),
+            entity(PiiCategory::Name, "John Smith"),
+            entity(PiiCategory::Email, "john@example.com"),
+        ];
+        assert_eq!(
+            redact(text, &entities),
+            "Mail [NAME_REDACTED] at [EMAIL_REDACTED] or call [NAME_REDACTED]."
+        );
+    }
+
+    #[test]
+    fn redact_should_not_replace_inside_other_words() {
+        let text = "Ann filed the Annual report with Annabel.";
+        let entities = vec![
+            entity(PiiCategory::Name, "Ann"),
+            entity(PiiCategory::Other("initial".into()), "A"),
+        ];
+        assert_eq!(
+            redact(text, &entities),
+            "[NAME_REDACTED] filed the Annual report with Annabel."
+        );
+    }
+
+    #[test]
+    fn redact_should_prefer_the_reported_offsets() {
+        let text = "Call Sam, not Sam's brother.";
+        let entities = vec![PiiEntity {
+            category: PiiCategory::Name,
+            value: "Sam".to_string(),
+            start_char: 5,
+            end_char: 8,
+        }];
+        assert_eq!(
+            redact(text, &entities),
+            "Call [NAME_REDACTED], not Sam's brother."
+        );
+        let text = "Zoë met Zoë.";
+        let entities = vec![PiiEntity {
+            category: PiiCategory::Name,
+            value: "Zoë".to_string(),
+            start_char: 8,
+            end_char: 11,
+        }];
+        assert_eq!(redact(text, &entities), "Zoë met [NAME_REDACTED].");
+    }
+
+    #[test]
+    fn category_should_accept_unknown_names() {
+        let categories: Vec<PiiCategory> =
+            serde_json::from_str(r#"["CREDIT_CARD", "passport number"]"#).unwrap();
+        assert_eq!(categories[0], PiiCategory::CreditCard);
+        assert_eq!(categories[1].placeholder(), "[PASSPORT_NUMBER_REDACTED]");
+    }
+}
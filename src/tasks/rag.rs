@@ -0,0 +1,141 @@
+use crate::{DocumentQaResult, LlmSDK, RerankResult, RerankStrategy, SearchResult, VectorIndex};
+use anyhow::{anyhow, Result};
+
+const RAG_PROMPT: &str = "Answer the user's question using only the numbered context passages below. If the context doesn't contain the answer, say that you don't know.";
+
+impl LlmSDK {
+    /// Retrieve the `initial_k` entries of `index` most similar to `query`, rerank them with
+    /// `strategy` and return the `rerank_top_k` best, ordered by `rerank_score`.
+    ///
+    /// The embedding strategy reuses the similarity of the retrieval step, so only the LLM
+    /// strategy costs extra calls.
+    pub async fn semantic_search(
+        &self,
+        query: &str,
+        index: &VectorIndex,
+        initial_k: usize,
+        rerank_top_k: usize,
+        strategy: RerankStrategy,
+    ) -> Result<Vec<SearchResult>> {
+        let candidates = self.retrieve(query, index, initial_k).await?;
+        let reranked = match strategy {
+            RerankStrategy::Embedding => candidates
+                .iter()
+                .enumerate()
+                .map(|(index, candidate)| RerankResult {
+                    index,
+                    document: candidate.text.clone(),
+                    relevance_score: candidate.initial_score,
+                })
+                .collect(),
+            RerankStrategy::Llm => {
+                let documents = candidates.iter().map(|c| c.text.clone()).collect();
+                self.rerank_with_strategy(query, documents, rerank_top_k, strategy)
+                    .await?
+            }
+        };
+        Ok(apply_rerank(candidates, reranked, rerank_top_k))
+    }
+
+    /// Answer `query` from the `k` entries of `index` most similar to it.
+    pub async fn rag_answer(&self, query: &str, index: &VectorIndex, k: usize) -> Result<String> {
+        let results = self.retrieve(query, index, k).await?;
+        let mut system = RAG_PROMPT.to_owned();
+        for (i, result) in results.iter().enumerate() {
+            system.push_str(&format!("\n\n[{}] {}", i + 1, result.text));
+        }
+        self.complete(&system, query).await
+    }
+
+    /// Like `rag_answer`, but with the confidence of the answer and the passages it cites.
+    pub async fn rag_qa(
+        &self,
+        question: &str,
+        index: &VectorIndex,
+        k: usize,
+    ) -> Result<DocumentQaResult> {
+        let results = self.retrieve(question, index, k).await?;
+        let passages = results.into_iter().map(|result| result.text).collect();
+        self.multi_document_qa(passages, question).await
+    }
+
+    async fn retrieve(
+        &self,
+        query: &str,
+        index: &VectorIndex,
+        top_k: usize,
+    ) -> Result<Vec<SearchResult>> {
+        let embedding = self
+            .embed_texts(vec![query.to_owned()])
+            .await?
+            .pop()
+            .ok_or_else(|| anyhow!("no embedding returned"))?;
+        Ok(index.search(&embedding, top_k))
+    }
+}
+
+/// Order `candidates` by the scores in `reranked`, which refer to them by index, and keep the
+/// `top_k` best.
+fn apply_rerank(
+    candidates: Vec<SearchResult>,
+    mut reranked: Vec<RerankResult>,
+    top_k: usize,
+) -> Vec<SearchResult> {
+    reranked.sort_by(|a, b| b.relevance_score.total_cmp(&a.relevance_score));
+    reranked
+        .into_iter()
+        .take(top_k)
+        .filter_map(|res| {
+            let mut result = candidates.get(res.index)?.clone();
+            result.rerank_score = Some(res.relevance_score);
+            Some(result)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn candidate(id: &str, score: f32) -> SearchResult {
+        SearchResult {
+            id: id.into(),
+            text: id.into(),
+            metadata: json!({}),
+            initial_score: score,
+            rerank_score: None,
+        }
+    }
+
+    #[test]
+    fn apply_rerank_should_reorder_candidates() {
+        let candidates = vec![
+            candidate("a", 0.9),
+            candidate("b", 0.8),
+            candidate("c", 0.7),
+        ];
+        let reranked = vec![
+            RerankResult {
+                index: 2,
+                document: "c".into(),
+                relevance_score: 1.0,
+            },
+            RerankResult {
+                index: 0,
+                document: "a".into(),
+                relevance_score: 0.3,
+            },
+            RerankResult {
+                index: 1,
+                document: "b".into(),
+                relevance_score: 0.5,
+            },
+        ];
+        let results = apply_rerank(candidates, reranked, 2);
+        let ids: Vec<_> = results.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, vec!["c", "b"]);
+        assert_eq!(results[0].initial_score, 0.7);
+        assert_eq!(results[0].rerank_score, Some(1.0));
+    }
+}
@@ -0,0 +1,150 @@
+use crate::LlmSDK;
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+const REPORT_PROMPT: &str = "You are a data analyst. Write a report on the JSON data given by the user. Base every statement on the data, quote the figures that support it, and don't invent numbers.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportType {
+    Executive,
+    Technical,
+    Marketing,
+    Financial,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AudienceLevel {
+    Expert,
+    General,
+    Children,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Report {
+    pub title: String,
+    pub executive_summary: String,
+    pub sections: Vec<ReportSection>,
+    pub recommendations: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ReportSection {
+    pub heading: String,
+    pub body: String,
+    /// Key takeaways of the section.
+    pub insights: Vec<String>,
+}
+
+impl ReportType {
+    fn focus(&self) -> &'static str {
+        match self {
+            ReportType::Executive => "an executive report focused on outcomes, risks and decisions",
+            ReportType::Technical => "a technical report focused on methods, metrics and anomalies",
+            ReportType::Marketing => "a marketing report focused on customers, channels and growth",
+            ReportType::Financial => {
+                "a financial report focused on revenue, costs, margins and trends"
+            }
+        }
+    }
+}
+
+impl AudienceLevel {
+    fn style(&self) -> &'static str {
+        match self {
+            AudienceLevel::Expert => "domain experts, so use precise terminology",
+            AudienceLevel::General => "a general audience, so explain terms and avoid jargon",
+            AudienceLevel::Children => {
+                "children, so use short sentences, simple words and familiar comparisons"
+            }
+        }
+    }
+}
+
+impl Report {
+    /// Render the report as markdown, with the summary, a section per heading and the
+    /// recommendations as a numbered list.
+    pub fn to_markdown(&self) -> String {
+        let mut md = format!(
+            "# {}\n\n## Summary\n\n{}\n\n",
+            self.title, self.executive_summary
+        );
+        for section in &self.sections {
+            md.push_str(&format!("## {}\n\n{}\n\n", section.heading, section.body));
+            if !section.insights.is_empty() {
+                for insight in &section.insights {
+                    md.push_str(&format!("- {}\n", insight));
+                }
+                md.push('\n');
+            }
+        }
+        if !self.recommendations.is_empty() {
+            md.push_str("## Recommendations\n\n");
+            for (i, recommendation) in self.recommendations.iter().enumerate() {
+                md.push_str(&format!("{}. {}\n", i + 1, recommendation));
+            }
+        }
+        md.trim_end().to_string() + "\n"
+    }
+}
+
+impl LlmSDK {
+    /// Write a narrative report of `data` of the given type for `audience`.
+    pub async fn generate_report(
+        &self,
+        data: Value,
+        report_type: ReportType,
+        audience: AudienceLevel,
+    ) -> Result<Report> {
+        let instructions = format!(
+            "{} Write {} for {}.",
+            REPORT_PROMPT,
+            report_type.focus(),
+            audience.style()
+        );
+        self.extract(&instructions, &serde_json::to_string_pretty(&data)?)
+            .await
+    }
+
+    /// Like `generate_report`, but rendered as markdown.
+    pub async fn generate_report_markdown(
+        &self,
+        data: Value,
+        report_type: ReportType,
+        audience: AudienceLevel,
+    ) -> Result<String> {
+        let report = self.generate_report(data, report_type, audience).await?;
+        Ok(report.to_markdown())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_markdown_should_render_sections_and_recommendations() {
+        let report = Report {
+            title: "Q3 Sales".into(),
+            executive_summary: "Sales grew 12%.".into(),
+            sections: vec![
+                ReportSection {
+                    heading: "Regions".into(),
+                    body: "EMEA led growth.".into(),
+                    insights: vec!["EMEA +20%".into(), "APAC flat".into()],
+                },
+                ReportSection {
+                    heading: "Products".into(),
+                    body: "No changes.".into(),
+                    insights: vec![],
+                },
+            ],
+            recommendations: vec!["Hire in EMEA".into()],
+        };
+        assert_eq!(
+            report.to_markdown(),
+            "# Q3 Sales\n\n## Summary\n\nSales grew 12%.\n\n## Regions\n\nEMEA led growth.\n\n- EMEA +20%\n- APAC flat\n\n## Products\n\nNo changes.\n\n## Recommendations\n\n1. Hire in EMEA\n"
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use crate::{cosine_similarity, LlmSDK};
+use anyhow::{anyhow, Result};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+const LLM_RERANK_PROMPT: &str = "You are a search relevance judge. Score how relevant each numbered document is to the user's query from 0 (unrelated) to 10 (answers the query directly). Score every document.";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RerankResult {
+    /// The index of the document in the input.
+    pub index: usize,
+    pub document: String,
+    /// The relevance from 0.0 to 1.0, higher is more relevant.
+    pub relevance_score: f32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RerankStrategy {
+    /// Cosine similarity between the embeddings of the query and each document. Cheap and fast.
+    #[default]
+    Embedding,
+    /// Let the chat model score each document against the query. Slower but more accurate.
+    Llm,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct RerankScores {
+    scores: Vec<DocumentScore>,
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+struct DocumentScore {
+    /// The number of the document.
+    index: usize,
+    /// The relevance from 0 to 10.
+    score: f32,
+}
+
+impl LlmSDK {
+    /// Rerank `documents` by relevance to `query` using embeddings and return the `top_k` best.
+    pub async fn rerank(
+        &self,
+        query: &str,
+        documents: Vec<String>,
+        top_k: usize,
+    ) -> Result<Vec<RerankResult>> {
+        self.rerank_with_strategy(query, documents, top_k, RerankStrategy::default())
+            .await
+    }
+
+    pub async fn rerank_with_strategy(
+        &self,
+        query: &str,
+        documents: Vec<String>,
+        top_k: usize,
+        strategy: RerankStrategy,
+    ) -> Result<Vec<RerankResult>> {
+        if documents.is_empty() {
+            return Ok(vec![]);
+        }
+        let scores = match strategy {
+            RerankStrategy::Embedding => self.embedding_scores(query, &documents).await?,
+            RerankStrategy::Llm => self.llm_scores(query, &documents).await?,
+        };
+        Ok(top_results(documents, scores, top_k))
+    }
+
+    async fn embedding_scores(&self, query: &str, documents: &[String]) -> Result<Vec<f32>> {
+        let mut texts = vec![query.to_owned()];
+        texts.extend_from_slice(documents);
+        let embeddings = self.embed_texts(texts).await?;
+        match embeddings.split_first() {
+            Some((query, scored)) if scored.len() == documents.len() => Ok(scored
+                .iter()
+                .map(|document| cosine_similarity(query, document))
+                .collect()),
+            _ => Err(anyhow!(
+                "expected {} embeddings, got {}",
+                documents.len() + 1,
+                embeddings.len()
+            )),
+        }
+    }
+
+    async fn llm_scores(&self, query: &str, documents: &[String]) -> Result<Vec<f32>> {
+        let mut text = format!("Query: {}\n", query);
+        for (index, document) in documents.iter().enumerate() {
+            text.push_str(&format!("\nDocument {}:\n{}\n", index, document));
+        }
+        let res: RerankScores = self.extract(LLM_RERANK_PROMPT, &text).await?;
+        // documents the model skipped rank last
+        let mut scores = vec![0.0; documents.len()];
+        for score in res.scores {
+            if let Some(slot) = scores.get_mut(score.index) {
+                *slot = (score.score / 10.0).clamp(0.0, 1.0);
+            }
+        }
+        Ok(scores)
+    }
+}
+
+fn top_results(documents: Vec<String>, scores: Vec<f32>, top_k: usize) -> Vec<RerankResult> {
+    let mut results: Vec<_> = documents
+        .into_iter()
+        .zip(scores)
+        .enumerate()
+        .map(|(index, (document, relevance_score))| RerankResult {
+            index,
+            document,
+            relevance_score,
+        })
+        .collect();
+    results.sort_by(|a, b| b.relevance_score.total_cmp(&a.relevance_score));
+    results.truncate(top_k);
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn top_results_should_sort_and_truncate() {
+        let documents = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let results = top_results(documents, vec![0.2, 0.9, 0.5], 2);
+        let indices: Vec<_> = results.iter().map(|res| res.index).collect();
+        assert_eq!(indices, vec![1, 2]);
+        assert_eq!(results[0].document, "b");
+    }
+
+    #[tokio::test]
+    async fn rerank_should_fail_on_missing_embeddings() {
+        let body = serde_json::json!({
+            "object": "list",
+            "data": [],
+            "model": "text-embedding-3-small",
+            "usage": {"prompt_tokens": 0, "total_tokens": 0}
+        });
+        let url = crate::test_server::serve(vec![(200, body.to_string())]);
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let err = sdk
+            .rerank("query", vec!["a".to_string()], 1)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "expected 2 embeddings, got 0");
+    }
+}
@@ -0,0 +1,371 @@
+use crate::{
+    LlmSDK, SpeechRequestBuilder, SpeechVoice, TimestampGranularity, WhisperRequestBuilder,
+    WhisperRequestType, WhisperResponseFormat, WhisperSegment, WhisperVerboseResponse, WhisperWord,
+};
+use anyhow::Result;
+use bytes::Bytes;
+use std::path::{Path, PathBuf};
+
+/// The longest subtitle line, the usual limit for readability.
+const MAX_CUE_CHARS: usize = 42;
+
+/// Generated speech together with the timing of each word of its text.
+#[derive(Debug, Clone)]
+pub struct SpeechWithTimestamps {
+    pub audio: Bytes,
+    /// One entry per whitespace separated word of the input text, in order.
+    pub word_timings: Vec<WordTiming>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WordTiming {
+    /// The word as written in the input text.
+    pub word: String,
+    pub start_ms: u64,
+    pub end_ms: u64,
+}
+
+/// Generated speech with subtitles for it, e.g. for a video voice-over.
+#[derive(Debug, Clone)]
+pub struct TtsSubtitlePair {
+    pub audio: Bytes,
+    pub srt_content: String,
+    pub vtt_content: String,
+    /// The number of words of the script.
+    pub word_count: usize,
+    /// The duration of the audio as measured by Whisper.
+    pub duration_estimate_secs: f32,
+}
+
+impl LlmSDK {
+    /// Generate speech for `text` and time each of its words, e.g. to highlight the text
+    /// karaoke-style during playback.
+    ///
+    /// TTS doesn't return timings, so the audio is transcribed again with word timestamps and
+    /// the transcript is aligned with `text`. This takes two calls and roughly doubles the
+    /// latency, so cache the result rather than regenerating the audio for the same text.
+    pub async fn speech_with_timestamps(
+        &self,
+        text: &str,
+        voice: SpeechVoice,
+    ) -> Result<SpeechWithTimestamps> {
+        let req = SpeechRequestBuilder::default()
+            .input(text)
+            .voice(voice)
+            .build()?;
+        let audio = self.speech(req).await?;
+        let transcript = self.transcribe_words(&audio, None).await?;
+        Ok(SpeechWithTimestamps {
+            audio,
+            word_timings: align_words(text, &transcript.words),
+        })
+    }
+
+    /// Generate speech for `script` together with SRT and WebVTT subtitles. The subtitles show
+    /// the script itself, timed like `speech_with_timestamps`, so names Whisper would mishear
+    /// are spelled as written. `language` is the ISO-639-1 code of the script.
+    pub async fn tts_with_subtitles(
+        &self,
+        script: &str,
+        voice: SpeechVoice,
+        language: &str,
+    ) -> Result<TtsSubtitlePair> {
+        self.subtitled_speech(script, voice, Some(language)).await
+    }
+
+    /// Generate speech and subtitles for `script` into `dir` as `{base_name}.mp3`, `.srt` and
+    /// `.vtt`. Returns the paths written, in that order.
+    pub async fn tts_with_subtitles_to_dir(
+        &self,
+        script: &str,
+        voice: SpeechVoice,
+        dir: &Path,
+        base_name: &str,
+    ) -> Result<Vec<PathBuf>> {
+        let pair = self.subtitled_speech(script, voice, None).await?;
+        tokio::fs::create_dir_all(dir).await?;
+        let files = [
+            ("mp3", pair.audio.to_vec()),
+            ("srt", pair.srt_content.into_bytes()),
+            ("vtt", pair.vtt_content.into_bytes()),
+        ];
+        let mut paths = Vec::with_capacity(files.len());
+        for (extension, content) in files {
+            let path = dir.join(format!("{}.{}", base_name, extension));
+            tokio::fs::write(&path, content).await?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+
+    async fn subtitled_speech(
+        &self,
+        script: &str,
+        voice: SpeechVoice,
+        language: Option<&str>,
+    ) -> Result<TtsSubtitlePair> {
+        let req = SpeechRequestBuilder::default()
+            .input(script)
+            .voice(voice)
+            .build()?;
+        let audio = self.speech(req).await?;
+        let transcript = self.transcribe_words(&audio, language).await?;
+        let timings = align_words(script, &transcript.words);
+        let subtitles = WhisperVerboseResponse {
+            segments: subtitle_cues(&timings),
+            ..transcript
+        };
+        Ok(TtsSubtitlePair {
+            audio,
+            srt_content: subtitles.to_srt(),
+            vtt_content: subtitles.to_vtt(),
+            word_count: timings.len(),
+            duration_estimate_secs: subtitles.duration,
+        })
+    }
+
+    async fn transcribe_words(
+        &self,
+        audio: &Bytes,
+        language: Option<&str>,
+    ) -> Result<WhisperVerboseResponse> {
+        let mut builder = WhisperRequestBuilder::default();
+        builder
+            .file(audio.to_vec())
+            .request_type(WhisperRequestType::Transcription)
+            .response_format(WhisperResponseFormat::VerboseJson)
+            .timestamp_granularities(vec![TimestampGranularity::Word]);
+        if let Some(language) = language {
+            builder.language(language);
+        }
+        let req = self.prepare_request(builder.build()?);
+        self.send_json(req).await
+    }
+}
+
+/// Group timed words into subtitle cues, breaking after sentences and before a cue grows past
+/// `MAX_CUE_CHARS`.
+fn subtitle_cues(timings: &[WordTiming]) -> Vec<WhisperSegment> {
+    let mut cues: Vec<WhisperSegment> = Vec::new();
+    let mut current: Vec<&WordTiming> = Vec::new();
+    let mut flush = |words: &mut Vec<&WordTiming>| {
+        if let (Some(first), Some(last)) = (words.first(), words.last()) {
+            cues.push(WhisperSegment {
+                id: cues.len(),
+                start: first.start_ms as f32 / 1000.0,
+                end: last.end_ms as f32 / 1000.0,
+                text: words
+                    .iter()
+                    .map(|w| w.word.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            });
+        }
+        words.clear();
+    };
+    for timing in timings {
+        let len: usize = current.iter().map(|w| w.word.len() + 1).sum();
+        if !current.is_empty() && len + timing.word.len() > MAX_CUE_CHARS {
+            flush(&mut current);
+        }
+        current.push(timing);
+        if timing.word.ends_with(['.', '!', '?']) {
+            flush(&mut current);
+        }
+    }
+    flush(&mut current);
+    cues
+}
+
+/// Time the words of `text` with the transcribed `words`. The sequences are aligned by edit
+/// distance, where a substitution costs the normalized Levenshtein distance of the two words, so
+/// misheard words still match. Words missing from the transcript share the gap between their
+/// neighbours.
+fn align_words(text: &str, words: &[WhisperWord]) -> Vec<WordTiming> {
+    let tokens: Vec<&str> = text.split_whitespace().collect();
+    let (n, m) = (tokens.len(), words.len());
+    let normalized_tokens: Vec<_> = tokens.iter().map(|t| normalize(t)).collect();
+    let normalized_words: Vec<_> = words.iter().map(|w| normalize(&w.word)).collect();
+
+    const GAP: f32 = 1.0;
+    let mut cost = vec![vec![0.0; m + 1]; n + 1];
+    for (i, row) in cost.iter_mut().enumerate() {
+        row[0] = i as f32 * GAP;
+    }
+    for (j, cell) in cost[0].iter_mut().enumerate() {
+        *cell = j as f32 * GAP;
+    }
+    for i in 1..=n {
+        for j in 1..=m {
+            let substitute = cost[i - 1][j - 1]
+                + word_distance(&normalized_tokens[i - 1], &normalized_words[j - 1]);
+            cost[i][j] = substitute
+                .min(cost[i - 1][j] + GAP)
+                .min(cost[i][j - 1] + GAP);
+        }
+    }
+
+    let mut matches = vec![None; n];
+    let (mut i, mut j) = (n, m);
+    while i > 0 && j > 0 {
+        let substitute =
+            cost[i - 1][j - 1] + word_distance(&normalized_tokens[i - 1], &normalized_words[j - 1]);
+        if cost[i][j] == substitute {
+            matches[i - 1] = Some(&words[j - 1]);
+            i -= 1;
+            j -= 1;
+        } else if cost[i][j] == cost[i - 1][j] + GAP {
+            i -= 1;
+        } else {
+            j -= 1;
+        }
+    }
+
+    let mut spans: Vec<_> = matches
+        .iter()
+        .map(|word| word.map(|w| (to_ms(w.start), to_ms(w.end))))
+        .collect();
+    let mut start = 0;
+    while start < n {
+        if spans[start].is_some() {
+            start += 1;
+            continue;
+        }
+        let end = (start..n).find(|&k| spans[k].is_some()).unwrap_or(n);
+        let from = start
+            .checked_sub(1)
+            .and_then(|k| spans[k])
+            .map_or(0, |(_, end)| end);
+        let to = spans.get(end).copied().flatten().map_or(from, |(s, _)| s);
+        let step = to.saturating_sub(from) / (end - start) as u64;
+        for (offset, span) in spans[start..end].iter_mut().enumerate() {
+            let begin = from + step * offset as u64;
+            *span = Some((begin, begin + step));
+        }
+        start = end;
+    }
+
+    tokens
+        .into_iter()
+        .zip(spans)
+        .map(|(word, span)| {
+            let (start_ms, end_ms) = span.unwrap_or_default();
+            WordTiming {
+                word: word.to_owned(),
+                start_ms,
+                end_ms,
+            }
+        })
+        .collect()
+}
+
+fn normalize(word: &str) -> Vec<char> {
+    word.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// The Levenshtein distance of `a` and `b` divided by the length of the longer one.
+fn word_distance(a: &[char], b: &[char]) -> f32 {
+    let len = a.len().max(b.len());
+    if len == 0 {
+        return 0.0;
+    }
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let current = row[j + 1];
+            row[j + 1] = if ca == cb {
+                previous
+            } else {
+                1 + previous.min(row[j]).min(row[j + 1])
+            };
+            previous = current;
+        }
+    }
+    row[b.len()] as f32 / len as f32
+}
+
+fn to_ms(seconds: f32) -> u64 {
+    (seconds.max(0.0) * 1000.0).round() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(word: &str, start: f32, end: f32) -> WhisperWord {
+        WhisperWord {
+            word: word.into(),
+            start,
+            end,
+        }
+    }
+
+    fn timing(word: &str, start_ms: u64, end_ms: u64) -> WordTiming {
+        WordTiming {
+            word: word.into(),
+            start_ms,
+            end_ms,
+        }
+    }
+
+    #[test]
+    fn subtitle_cues_should_break_at_sentences_and_length() {
+        let mut timings = vec![timing("Hi.", 0, 400), timing("Welcome", 500, 900)];
+        timings.extend((0..8).map(|i| timing("everyone", 1000 + i * 100, 1100 + i * 100)));
+        let cues = subtitle_cues(&timings);
+        let texts: Vec<_> = cues.iter().map(|cue| cue.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "Hi.",
+                "Welcome everyone everyone everyone",
+                "everyone everyone everyone everyone",
+                "everyone"
+            ]
+        );
+        assert_eq!((cues[1].start, cues[1].end), (0.5, 1.3));
+        assert_eq!(cues[3].id, 3);
+    }
+
+    #[test]
+    fn word_distance_should_be_normalized() {
+        assert_eq!(
+            word_distance(&normalize("Hello,"), &normalize("hello")),
+            0.0
+        );
+        assert_eq!(
+            word_distance(&normalize("kitten"), &normalize("sitten")),
+            1.0 / 6.0
+        );
+        assert_eq!(word_distance(&normalize("abc"), &normalize("xyz")), 1.0);
+    }
+
+    #[test]
+    fn align_words_should_match_misheard_and_fill_missing_words() {
+        let words = [
+            word("Hello", 0.0, 0.4),
+            word("wrld", 0.5, 0.9),
+            word("again", 1.5, 1.9),
+        ];
+        let timings = align_words("Hello, world! Nice day again.", &words);
+        let spans: Vec<_> = timings
+            .iter()
+            .map(|t| (t.word.as_str(), t.start_ms, t.end_ms))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                ("Hello,", 0, 400),
+                ("world!", 500, 900),
+                ("Nice", 900, 1200),
+                ("day", 1200, 1500),
+                ("again.", 1500, 1900),
+            ]
+        );
+    }
+}
@@ -0,0 +1,118 @@
+use crate::LlmSDK;
+use anyhow::Result;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SummarizationStyle {
+    /// A short overview of the conclusions and decisions for a busy reader.
+    #[default]
+    Executive,
+    /// A thorough summary that keeps the supporting details.
+    Detailed,
+    /// A single sentence that fits in a tweet.
+    TweetLength,
+    /// A formal abstract in academic register.
+    Academic,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SummarizationConfig {
+    pub style: SummarizationStyle,
+    pub max_words: Option<usize>,
+    /// The language of the summary, e.g. "French". Defaults to the language of the text.
+    pub language: Option<String>,
+    pub bullet_points: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SummarizationWithCitations {
+    pub summary: String,
+    pub key_points: Vec<String>,
+    /// Passages of the original text that support the summary.
+    pub citations: Vec<TextSpan>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct TextSpan {
+    /// The passage quoted verbatim from the original text.
+    pub text: String,
+    /// Where the passage appears in the text, from 0.0 (start) to 1.0 (end).
+    pub approximate_position: f32,
+}
+
+impl SummarizationConfig {
+    fn system_prompt(&self) -> String {
+        let style = match self.style {
+            SummarizationStyle::Executive => {
+                "an executive summary focusing on the key conclusions, decisions and action items"
+            }
+            SummarizationStyle::Detailed => {
+                "a detailed summary that covers every main point and its supporting details"
+            }
+            SummarizationStyle::TweetLength => {
+                "a single sentence summary no longer than 280 characters"
+            }
+            SummarizationStyle::Academic => {
+                "an academic abstract in formal register covering background, method, results and conclusion"
+            }
+        };
+        let mut prompt = format!(
+            "You are an expert summarizer. Write {} of the user's text.",
+            style
+        );
+        if let Some(max_words) = self.max_words {
+            prompt.push_str(&format!(" Use at most {} words.", max_words));
+        }
+        match &self.language {
+            Some(language) => prompt.push_str(&format!(" Write the summary in {}.", language)),
+            None => prompt.push_str(" Write the summary in the language of the text."),
+        }
+        if self.bullet_points {
+            prompt.push_str(" Format the summary as a bullet point list.");
+        }
+        prompt.push_str(" Reply with the summary only.");
+        prompt
+    }
+}
+
+impl LlmSDK {
+    /// Summarize `text` according to `config`.
+    pub async fn summarize(&self, text: &str, config: SummarizationConfig) -> Result<String> {
+        self.complete(&config.system_prompt(), text).await
+    }
+
+    /// Summarize `text` in at most `max_words` words, with the key points and the passages of the
+    /// text they are based on.
+    pub async fn summarize_with_citations(
+        &self,
+        text: &str,
+        max_words: usize,
+    ) -> Result<SummarizationWithCitations> {
+        let instructions = format!(
+            "You are an expert summarizer. Summarize the user's text in at most {} words, list its key points, and cite the passages of the text that support them verbatim with their approximate relative position (0.0 is the start, 1.0 is the end).",
+            max_words
+        );
+        self.extract(&instructions, text).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn system_prompt_should_include_config() {
+        let config = SummarizationConfig {
+            style: SummarizationStyle::TweetLength,
+            max_words: Some(30),
+            language: Some("French".to_string()),
+            bullet_points: true,
+        };
+        let prompt = config.system_prompt();
+        assert!(prompt.contains("280 characters"));
+        assert!(prompt.contains("at most 30 words"));
+        assert!(prompt.contains("in French"));
+        assert!(prompt.contains("bullet point"));
+    }
+}
@@ -0,0 +1,150 @@
+use crate::LlmSDK;
+use anyhow::Result;
+use futures::{stream, StreamExt};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+const TAG_PROMPT: &str = "You tag content for a content management system. Give the tags that best describe the user's text, each with a confidence from 0 to 1 and an optional category, e.g. topic, audience or format.";
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct AutoTagResult {
+    pub tags: Vec<Tag>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Tag {
+    pub name: String,
+    pub confidence: f32,
+    pub category: Option<String>,
+    /// The broader tag this one belongs to, only set for hierarchical tagging.
+    pub parent: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AutoTagConfig {
+    /// Only use tags from this list. Tags outside of it are dropped.
+    pub taxonomy: Option<Vec<String>>,
+    pub max_tags: usize,
+    /// Drop tags with a lower confidence.
+    pub min_confidence: f32,
+    /// Also return the parent of each tag, e.g. `rust` under `programming languages`.
+    pub hierarchical: bool,
+}
+
+impl Default for AutoTagConfig {
+    fn default() -> Self {
+        Self {
+            taxonomy: None,
+            max_tags: 10,
+            min_confidence: 0.5,
+            hierarchical: false,
+        }
+    }
+}
+
+impl AutoTagConfig {
+    fn instructions(&self) -> String {
+        let mut prompt = format!("{} Return at most {} tags.", TAG_PROMPT, self.max_tags);
+        if let Some(taxonomy) = &self.taxonomy {
+            prompt.push_str(&format!(
+                " Only use tags from this list, spelled exactly as given: {}.",
+                taxonomy.join(", ")
+            ));
+        }
+        if self.hierarchical {
+            prompt.push_str(" Give each tag its broader parent tag, or null for top-level tags.");
+        } else {
+            prompt.push_str(" Set every parent to null.");
+        }
+        prompt
+    }
+
+    /// Enforce the config on the tags returned by the model, which may not follow it exactly.
+    fn apply(&self, mut tags: Vec<Tag>) -> Vec<Tag> {
+        if let Some(taxonomy) = &self.taxonomy {
+            tags.retain_mut(|tag| {
+                match taxonomy
+                    .iter()
+                    .find(|name| name.eq_ignore_ascii_case(tag.name.trim()))
+                {
+                    Some(name) => {
+                        tag.name = name.clone();
+                        true
+                    }
+                    None => false,
+                }
+            });
+        }
+        tags.retain(|tag| tag.confidence >= self.min_confidence);
+        if !self.hierarchical {
+            for tag in &mut tags {
+                tag.parent = None;
+            }
+        }
+        tags.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+        tags.truncate(self.max_tags);
+        tags
+    }
+}
+
+impl LlmSDK {
+    /// Tag `text` for categorization, most confident tags first.
+    pub async fn auto_tag(&self, text: &str, config: AutoTagConfig) -> Result<AutoTagResult> {
+        let res: AutoTagResult = self.extract(&config.instructions(), text).await?;
+        Ok(AutoTagResult {
+            tags: config.apply(res.tags),
+        })
+    }
+
+    /// Tag `texts` with at most `concurrency` requests in flight. Results are returned in input
+    /// order.
+    pub async fn auto_tag_batch(
+        &self,
+        texts: Vec<String>,
+        config: AutoTagConfig,
+        concurrency: usize,
+    ) -> Vec<Result<AutoTagResult>> {
+        stream::iter(texts)
+            .map(|text| {
+                let config = config.clone();
+                async move { self.auto_tag(&text, config).await }
+            })
+            .buffered(concurrency.max(1))
+            .collect()
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tag(name: &str, confidence: f32, parent: Option<&str>) -> Tag {
+        Tag {
+            name: name.into(),
+            confidence,
+            category: None,
+            parent: parent.map(Into::into),
+        }
+    }
+
+    #[test]
+    fn apply_should_enforce_taxonomy_confidence_and_limit() {
+        let config = AutoTagConfig {
+            taxonomy: Some(vec!["Rust".into(), "Web".into(), "Databases".into()]),
+            max_tags: 2,
+            min_confidence: 0.5,
+            hierarchical: false,
+        };
+        let tags = config.apply(vec![
+            tag("web", 0.7, Some("Programming")),
+            tag("Cooking", 0.9, None),
+            tag("Rust", 0.95, None),
+            tag("Databases", 0.6, None),
+            tag("Web", 0.4, None),
+        ]);
+        let names: Vec<_> = tags.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, vec!["Rust", "Web"]);
+        assert!(tags.iter().all(|t| t.parent.is_none()));
+    }
+}
@@ -0,0 +1,176 @@
+use crate::{cosine_similarity, LlmSDK};
+use anyhow::{bail, Result};
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// The maximum number of items requested in one call.
+const TEST_DATA_BATCH_SIZE: usize = 20;
+/// Items more similar than this to an already accepted item are dropped as near duplicates.
+const DIVERSITY_THRESHOLD: f32 = 0.95;
+/// Extra batches `generate_diverse_test_data` may request to make up for dropped duplicates.
+const DIVERSITY_EXTRA_ROUNDS: usize = 3;
+/// The number of the most recent accepted items shown to the model as examples to avoid, so
+/// the prompt doesn't grow with `count`.
+const MAX_AVOID_EXAMPLES: usize = 40;
+
+/// Wrapper to generate several items with one structured output call.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct DataBatch<T> {
+    pub items: Vec<T>,
+}
+
+impl LlmSDK {
+    /// Generate `count` synthetic instances of `T`. `constraints` describes extra requirements,
+    /// e.g. "all names must be fictional".
+    pub async fn generate_test_data<T: DeserializeOwned + JsonSchema>(
+        &self,
+        count: usize,
+        constraints: &str,
+    ) -> Result<Vec<T>> {
+        let mut items = Vec::with_capacity(count);
+        while items.len() < count {
+            let size = (count - items.len()).min(TEST_DATA_BATCH_SIZE);
+            let batch: DataBatch<T> = self.generate_batch(size, constraints, &[]).await?;
+            if batch.items.is_empty() {
+                bail!("no test data was generated");
+            }
+            items.extend(batch.items);
+        }
+        items.truncate(count);
+        Ok(items)
+    }
+
+    /// Like `generate_test_data`, but drop items whose embeddings are near duplicates of items
+    /// generated before, and ask for more until there are `count` distinct ones. Fails if the
+    /// model keeps repeating itself and fewer than `count` distinct items were generated after
+    /// a few extra batches.
+    pub async fn generate_diverse_test_data<T: Serialize + DeserializeOwned + JsonSchema>(
+        &self,
+        count: usize,
+        constraints: &str,
+    ) -> Result<Vec<T>> {
+        let mut items = Vec::with_capacity(count);
+        let mut examples: Vec<String> = Vec::new();
+        let mut embeddings: Vec<Vec<f32>> = Vec::new();
+        let rounds = count.div_ceil(TEST_DATA_BATCH_SIZE) + DIVERSITY_EXTRA_ROUNDS;
+        for _ in 0..rounds {
+            if items.len() >= count {
+                break;
+            }
+            let size = (count - items.len()).min(TEST_DATA_BATCH_SIZE);
+            let avoid = &examples[examples.len().saturating_sub(MAX_AVOID_EXAMPLES)..];
+            let batch: DataBatch<T> = self.generate_batch(size, constraints, avoid).await?;
+            let texts = batch
+                .items
+                .iter()
+                .map(serde_json::to_string)
+                .collect::<Result<Vec<_>, _>>()?;
+            let candidates = self.embed_texts(texts.clone()).await?;
+            let selected = select_diverse(&mut embeddings, candidates, DIVERSITY_THRESHOLD);
+            let mut batch: Vec<_> = batch.items.into_iter().map(Some).collect();
+            for index in selected {
+                examples.push(texts[index].clone());
+                items.extend(batch[index].take());
+            }
+        }
+        if items.len() < count {
+            bail!(
+                "only {} of {} test data items were distinct after {} batches",
+                items.len(),
+                count,
+                rounds
+            );
+        }
+        items.truncate(count);
+        Ok(items)
+    }
+
+    async fn generate_batch<T: DeserializeOwned + JsonSchema>(
+        &self,
+        size: usize,
+        constraints: &str,
+        avoid: &[String],
+    ) -> Result<DataBatch<T>> {
+        let mut instructions = format!(
+            "You generate realistic synthetic test data. Generate exactly {} varied items.",
+            size
+        );
+        if !constraints.is_empty() {
+            instructions.push_str(&format!(" Requirements: {}", constraints));
+        }
+        if !avoid.is_empty() {
+            instructions.push_str(&format!(
+                "\nThe items must be clearly different from these existing ones:\n{}",
+                avoid.join("\n")
+            ));
+        }
+        self.extract(&instructions, "Generate the items.").await
+    }
+}
+
+/// Return the indices of `candidates` that are not near duplicates of `accepted` or of each
+/// other, and add their embeddings to `accepted`.
+fn select_diverse(
+    accepted: &mut Vec<Vec<f32>>,
+    candidates: Vec<Vec<f32>>,
+    threshold: f32,
+) -> Vec<usize> {
+    let mut selected = Vec::new();
+    for (index, candidate) in candidates.into_iter().enumerate() {
+        if accepted
+            .iter()
+            .all(|existing| cosine_similarity(existing, &candidate) < threshold)
+        {
+            accepted.push(candidate);
+            selected.push(index);
+        }
+    }
+    selected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server;
+
+    #[tokio::test]
+    async fn generate_diverse_test_data_should_fail_on_too_few_distinct_items() {
+        let embeddings = serde_json::json!({
+            "object": "list",
+            "data": [
+                {"index": 0, "embedding": [1.0, 0.0], "object": "embedding"},
+                {"index": 1, "embedding": [1.0, 0.0], "object": "embedding"}
+            ],
+            "model": "text-embedding-3-small",
+            "usage": {"prompt_tokens": 2, "total_tokens": 2}
+        });
+        let round = [
+            (
+                200,
+                test_server::chat_response(r#"{"items": ["same", "same"]}"#),
+            ),
+            (200, embeddings.to_string()),
+        ];
+        let url = test_server::serve(round.iter().cloned().cycle().take(8).collect());
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let err = sdk
+            .generate_diverse_test_data::<String>(2, "")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("only 1 of 2"));
+    }
+
+    #[test]
+    fn select_diverse_should_drop_near_duplicates() {
+        let mut accepted = vec![vec![1.0, 0.0]];
+        let candidates = vec![
+            vec![0.99, 0.01],
+            vec![0.0, 1.0],
+            vec![0.01, 0.99],
+            vec![0.7, 0.7],
+        ];
+        assert_eq!(select_diverse(&mut accepted, candidates, 0.95), vec![1, 3]);
+        assert_eq!(accepted.len(), 3);
+    }
+}
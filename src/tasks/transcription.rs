@@ -0,0 +1,260 @@
+use crate::{
+    LlmSDK, WhisperRequestBuilder, WhisperRequestType, WhisperResponse, WhisperResponseFormat,
+    WhisperVerboseResponse,
+};
+use anyhow::Result;
+use futures::{stream, StreamExt};
+use std::path::{Path, PathBuf};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tracing::info;
+
+/// The audio formats accepted by Whisper.
+const AUDIO_EXTENSIONS: &[&str] = &[
+    "flac", "m4a", "mp3", "mp4", "mpeg", "mpga", "oga", "ogg", "wav", "webm",
+];
+/// Directories with more files than this get a cost estimate logged before transcribing.
+const LARGE_BATCH: usize = 100;
+/// Whisper price in USD per minute of audio.
+const WHISPER_PRICE_PER_MINUTE: f64 = 0.006;
+/// Rough size of a minute of audio, assuming 128 kbps.
+const BYTES_PER_MINUTE: f64 = 960_000.0;
+
+/// The subject of a recording. Whisper uses the prompt of the domain to get its vocabulary right.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TranscriptionDomain {
+    Medical,
+    Legal,
+    Technical,
+    Financial,
+    Casual,
+    /// A prompt of your own, e.g. listing product names or speakers.
+    Custom(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranscriptionStats {
+    /// The duration of the audio in seconds.
+    pub total_duration_secs: f32,
+    pub word_count: usize,
+    /// The language detected by Whisper, or the one given.
+    pub language: String,
+}
+
+impl TranscriptionDomain {
+    /// The prompt passed to Whisper for the domain.
+    pub fn custom_prompt(&self) -> &str {
+        match self {
+            TranscriptionDomain::Medical => "The following is a medical dictation containing anatomical terms, drug names, and medical procedures.",
+            TranscriptionDomain::Legal => "The following is a legal recording containing statutes, case citations, contract clauses, and courtroom terminology.",
+            TranscriptionDomain::Technical => "The following is a technical discussion containing software, engineering, and product terminology, acronyms, and code identifiers.",
+            TranscriptionDomain::Financial => "The following is a financial discussion containing company names, ticker symbols, accounting terms, percentages, and currency amounts.",
+            TranscriptionDomain::Casual => "Umm, so, like, the following is a casual conversation between friends, you know?",
+            TranscriptionDomain::Custom(prompt) => prompt,
+        }
+    }
+}
+
+impl LlmSDK {
+    /// Transcribe `audio_data` with the vocabulary prompt of `domain`.
+    pub async fn transcribe_with_domain_hint(
+        &self,
+        audio_data: Vec<u8>,
+        domain: TranscriptionDomain,
+        language: Option<String>,
+    ) -> Result<String> {
+        let mut builder = WhisperRequestBuilder::default();
+        builder
+            .file(audio_data)
+            .request_type(WhisperRequestType::Transcription)
+            .prompt(domain.custom_prompt());
+        if let Some(language) = language {
+            builder.language(language);
+        }
+        let res = self.whisper(builder.build()?).await?;
+        Ok(res.text)
+    }
+
+    /// Transcribe `files` with at most `concurrency` requests in flight. Results are returned in
+    /// input order.
+    pub async fn transcribe_batch(
+        &self,
+        files: Vec<Vec<u8>>,
+        language: Option<String>,
+        concurrency: usize,
+    ) -> Vec<Result<WhisperResponse>> {
+        let mut results: Vec<_> = stream::iter(files.into_iter().enumerate())
+            .map(|(index, file)| {
+                let language = language.clone();
+                async move { (index, self.transcribe(file, language).await) }
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+            .await;
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, res)| res).collect()
+    }
+
+    /// Transcribe every audio file in `dir` (not recursively), ordered by path. Fails if any
+    /// file fails, after all files were attempted.
+    pub async fn transcribe_directory(
+        &self,
+        dir: &Path,
+        language: Option<String>,
+        concurrency: usize,
+    ) -> Result<Vec<(PathBuf, WhisperResponse)>> {
+        let paths = audio_files(dir).await?;
+        if paths.len() > LARGE_BATCH {
+            let mut bytes = 0;
+            for path in &paths {
+                bytes += tokio::fs::metadata(path).await?.len();
+            }
+            let minutes = bytes as f64 / BYTES_PER_MINUTE;
+            info!(
+                "transcribing {} files, about {:.0} minutes of audio, estimated cost ${:.2}",
+                paths.len(),
+                minutes,
+                minutes * WHISPER_PRICE_PER_MINUTE
+            );
+        }
+        // read each file only once its turn comes, so at most `concurrency` are in memory
+        let mut results: Vec<_> = stream::iter(paths.into_iter().enumerate())
+            .map(|(index, path)| {
+                let language = language.clone();
+                async move {
+                    let res = match tokio::fs::read(&path).await {
+                        Ok(file) => self.transcribe(file, language).await,
+                        Err(e) => Err(e.into()),
+                    };
+                    (index, path, res)
+                }
+            })
+            .buffer_unordered(concurrency.max(1))
+            .collect()
+            .await;
+        results.sort_by_key(|(index, ..)| *index);
+        results
+            .into_iter()
+            .map(|(_, path, res)| Ok((path, res?)))
+            .collect()
+    }
+
+    /// Transcribe `audio_data` and write it as SubRip subtitles to `output`, e.g. a
+    /// `tokio::fs::File`, a socket or an HTTP response body.
+    pub async fn transcribe_to_srt_file(
+        &self,
+        audio_data: Vec<u8>,
+        mut output: impl AsyncWrite + Unpin,
+        language: Option<String>,
+    ) -> Result<TranscriptionStats> {
+        let mut builder = WhisperRequestBuilder::default();
+        builder
+            .file(audio_data)
+            .request_type(WhisperRequestType::Transcription)
+            .response_format(WhisperResponseFormat::VerboseJson);
+        if let Some(language) = language {
+            builder.language(language);
+        }
+        let req = self.prepare_request(builder.build()?);
+        let res: WhisperVerboseResponse = self.send_json(req).await?;
+        output.write_all(res.to_srt().as_bytes()).await?;
+        output.flush().await?;
+        Ok(TranscriptionStats {
+            total_duration_secs: res.duration,
+            word_count: res.text.split_whitespace().count(),
+            language: res.language,
+        })
+    }
+
+    /// Like `transcribe_to_srt_file`, writing to a new file at `path`.
+    pub async fn transcribe_to_srt_path(
+        &self,
+        audio_data: Vec<u8>,
+        path: &Path,
+        language: Option<String>,
+    ) -> Result<TranscriptionStats> {
+        let file = tokio::fs::File::create(path).await?;
+        self.transcribe_to_srt_file(audio_data, file, language)
+            .await
+    }
+
+    async fn transcribe(&self, file: Vec<u8>, language: Option<String>) -> Result<WhisperResponse> {
+        let mut builder = WhisperRequestBuilder::default();
+        builder
+            .file(file)
+            .request_type(WhisperRequestType::Transcription);
+        if let Some(language) = language {
+            builder.language(language);
+        }
+        self.whisper(builder.build()?).await
+    }
+}
+
+/// The audio files directly in `dir`, sorted by path.
+async fn audio_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::new();
+    let mut entries = tokio::fs::read_dir(dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        let is_audio = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext.to_lowercase().as_str()));
+        if is_audio && tokio::fs::metadata(&path).await?.is_file() {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn audio_files_should_filter_by_extension() -> Result<()> {
+        let dir =
+            std::env::temp_dir().join(format!("llm-sdk-audio-files-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir)?;
+        for name in ["b.MP3", "a.wav", "notes.txt"] {
+            std::fs::write(dir.join(name), b"")?;
+        }
+        let files = audio_files(&dir).await?;
+        std::fs::remove_dir_all(&dir)?;
+        assert_eq!(files, vec![dir.join("a.wav"), dir.join("b.MP3")]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn transcribe_to_srt_file_should_write_subtitles() -> Result<()> {
+        let res = serde_json::json!({
+            "task": "transcribe",
+            "language": "english",
+            "duration": 2.5,
+            "text": "Hello there, friend.",
+            "segments": [{"id": 0, "seek": 0, "start": 0.0, "end": 2.5, "text": " Hello there, friend."}]
+        });
+        let url = crate::test_server::serve(vec![(200, res.to_string())]);
+        let sdk = LlmSDK::new_with_base_url("token", url);
+        let mut srt = Vec::new();
+        let stats = sdk
+            .transcribe_to_srt_file(b"audio".to_vec(), &mut srt, None)
+            .await?;
+        assert_eq!(
+            String::from_utf8(srt)?,
+            "1\n00:00:00,000 --> 00:00:02,500\nHello there, friend.\n\n"
+        );
+        assert_eq!(stats.word_count, 3);
+        assert_eq!(stats.language, "english");
+        Ok(())
+    }
+
+    #[test]
+    fn custom_prompt_should_use_domain_vocabulary() {
+        assert!(TranscriptionDomain::Medical
+            .custom_prompt()
+            .contains("drug names"));
+        let domain = TranscriptionDomain::Custom("Speakers: Alice, Bob.".to_string());
+        assert_eq!(domain.custom_prompt(), "Speakers: Alice, Bob.");
+    }
+}
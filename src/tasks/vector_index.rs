@@ -0,0 +1,257 @@
+use crate::LlmSDK;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A simple in-memory vector store, searched by brute-force cosine similarity. Good enough for
+/// prototypes and small corpora.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VectorIndex {
+    pub entries: Vec<IndexEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    pub id: String,
+    pub text: String,
+    pub embedding: Vec<f32>,
+    pub metadata: Value,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    pub id: String,
+    pub text: String,
+    pub metadata: Value,
+    /// The cosine similarity between the query and the entry, before any reranking.
+    pub initial_score: f32,
+    /// The relevance from 0.0 to 1.0 assigned by reranking, see `LlmSDK::semantic_search`.
+    pub rerank_score: Option<f32>,
+}
+
+/// Only keep entries whose metadata has all the given fields set to the given values.
+#[derive(Debug, Clone, Default)]
+pub struct MetadataFilter {
+    fields: Vec<(String, Value)>,
+}
+
+impl VectorIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(
+        &mut self,
+        id: impl Into<String>,
+        text: impl Into<String>,
+        embedding: Vec<f32>,
+        metadata: Value,
+    ) {
+        self.entries.push(IndexEntry {
+            id: id.into(),
+            text: text.into(),
+            embedding,
+            metadata,
+        });
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Return the `top_k` entries most similar to `query`.
+    pub fn search(&self, query: &[f32], top_k: usize) -> Vec<SearchResult> {
+        self.search_text(query, None, top_k)
+    }
+
+    /// Return the `top_k` entries most similar to `query_embedding` that match `filter`.
+    pub fn search_text(
+        &self,
+        query_embedding: &[f32],
+        filter: Option<MetadataFilter>,
+        top_k: usize,
+    ) -> Vec<SearchResult> {
+        let mut results: Vec<_> = self
+            .entries
+            .iter()
+            .filter(|entry| filter.as_ref().is_none_or(|f| f.matches(&entry.metadata)))
+            .map(|entry| SearchResult {
+                id: entry.id.clone(),
+                text: entry.text.clone(),
+                metadata: entry.metadata.clone(),
+                initial_score: cosine_similarity(query_embedding, &entry.embedding),
+                rerank_score: None,
+            })
+            .collect();
+        results.sort_by(|a, b| b.initial_score.total_cmp(&a.initial_score));
+        results.truncate(top_k);
+        results
+    }
+
+    #[cfg(feature = "persistence")]
+    pub fn save_to_file(&self, path: impl AsRef<std::path::Path>) -> Result<()> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer(std::io::BufWriter::new(file), self)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "persistence")]
+    pub fn load_from_file(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+    }
+}
+
+impl MetadataFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require `key` in the metadata to equal `value`.
+    pub fn field(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.fields.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn matches(&self, metadata: &Value) -> bool {
+        self.fields
+            .iter()
+            .all(|(key, value)| metadata.get(key) == Some(value))
+    }
+}
+
+impl LlmSDK {
+    /// Embed and index `documents`, given as `(id, text, metadata)`. Texts longer than
+    /// `chunk_size` characters are split into several entries with ids `{id}#{n}` that share
+    /// the document metadata. A `chunk_size` of 0 disables chunking.
+    pub async fn build_index(
+        &self,
+        documents: Vec<(String, String, Value)>,
+        chunk_size: usize,
+    ) -> Result<VectorIndex> {
+        let mut pending = Vec::new();
+        for (id, text, metadata) in documents {
+            let chunks = chunk_text(&text, chunk_size);
+            let single = chunks.len() == 1;
+            for (i, chunk) in chunks.into_iter().enumerate() {
+                let id = if single {
+                    id.clone()
+                } else {
+                    format!("{}#{}", id, i)
+                };
+                pending.push((id, chunk, metadata.clone()));
+            }
+        }
+
+        let texts = pending.iter().map(|(_, text, _)| text.clone()).collect();
+        let embeddings = self.embed_texts(texts).await?;
+        let mut index = VectorIndex::new();
+        for ((id, text, metadata), embedding) in pending.into_iter().zip(embeddings) {
+            index.insert(id, text, embedding, metadata);
+        }
+        Ok(index)
+    }
+}
+
+/// Cosine similarity of two vectors, 0 if either of them is a zero vector.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+/// Split `text` into chunks of at most `chunk_size` characters, breaking at whitespace where
+/// possible.
+pub(super) fn chunk_text(text: &str, chunk_size: usize) -> Vec<String> {
+    if chunk_size == 0 || text.chars().count() <= chunk_size {
+        return vec![text.to_owned()];
+    }
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for word in text.split_inclusive(char::is_whitespace) {
+        if !current.is_empty() && current.chars().count() + word.chars().count() > chunk_size {
+            chunks.push(current.trim().to_owned());
+            current.clear();
+        }
+        // a single word longer than the chunk size is split hard
+        let mut word = word;
+        while word.chars().count() > chunk_size {
+            let split = word
+                .char_indices()
+                .nth(chunk_size)
+                .map_or(word.len(), |(i, _)| i);
+            chunks.push(word[..split].to_owned());
+            word = &word[split..];
+        }
+        current.push_str(word);
+    }
+    if !current.trim().is_empty() {
+        chunks.push(current.trim().to_owned());
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn get_index() -> VectorIndex {
+        let mut index = VectorIndex::new();
+        index.insert("a", "apple", vec![1.0, 0.0], json!({"kind": "fruit"}));
+        index.insert("b", "banana", vec![0.8, 0.2], json!({"kind": "fruit"}));
+        index.insert("c", "carrot", vec![0.0, 1.0], json!({"kind": "vegetable"}));
+        index
+    }
+
+    #[test]
+    fn search_should_rank_by_cosine_similarity() {
+        let results = get_index().search(&[1.0, 0.1], 2);
+        let ids: Vec<_> = results.iter().map(|r| r.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "b"]);
+        assert!(results[0].initial_score > results[1].initial_score);
+    }
+
+    #[test]
+    fn search_text_should_apply_metadata_filter() {
+        let filter = MetadataFilter::new().field("kind", "vegetable");
+        let results = get_index().search_text(&[1.0, 0.0], Some(filter), 10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].id, "c");
+    }
+
+    #[test]
+    fn chunk_text_should_respect_chunk_size() {
+        let chunks = chunk_text("the quick brown fox jumps", 10);
+        assert_eq!(chunks, vec!["the quick", "brown fox", "jumps"]);
+        assert!(chunks.iter().all(|c| c.chars().count() <= 10));
+        assert_eq!(chunk_text("short", 0), vec!["short"]);
+    }
+
+    #[test]
+    fn cosine_similarity_should_handle_zero_vectors() {
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+        assert!((cosine_similarity(&[1.0, 1.0], &[2.0, 2.0]) - 1.0).abs() < 1e-6);
+    }
+
+    #[cfg(feature = "persistence")]
+    #[test]
+    fn index_should_save_and_load() -> Result<()> {
+        let path = std::env::temp_dir().join("llm-sdk-index-test.json");
+        let index = get_index();
+        index.save_to_file(&path)?;
+        let loaded = VectorIndex::load_from_file(&path)?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(loaded.entries, index.entries);
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+//! A minimal HTTP server for tests that can't reach the real API.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Serve one response per `(status, body)` on a local port, then stop. Returns the base url.
+pub(crate) fn serve(responses: Vec<(u16, String)>) -> String {
+    serve_recording(responses).0
+}
+
+/// Like `serve`, but also returns the bodies of the requests received so far, in order.
+pub(crate) fn serve_recording(responses: Vec<(u16, String)>) -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let recorder = received.clone();
+    std::thread::spawn(move || {
+        for (status, body) in responses {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let (_, request_body) = read_request(&mut reader);
+            recorder
+                .lock()
+                .unwrap()
+                .push(String::from_utf8_lossy(&request_body).into_owned());
+            respond(reader.get_mut(), status, &body);
+        }
+    });
+    (format!("http://{}", addr), received)
+}
+
+/// Serve concurrent requests, each on its own thread, with the response of the route whose
+/// path ends the request path, e.g. `/moderations`, after the route's delay. Stops after one
+/// request per route. Returns the base url.
+pub(crate) fn serve_routes(routes: Vec<(&'static str, Duration, u16, String)>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let routes = Arc::new(routes);
+    std::thread::spawn(move || {
+        for _ in 0..routes.len() {
+            let (stream, _) = listener.accept().unwrap();
+            let routes = routes.clone();
+            std::thread::spawn(move || {
+                let mut reader = BufReader::new(stream);
+                let (path, _) = read_request(&mut reader);
+                let (_, delay, status, body) = routes
+                    .iter()
+                    .find(|(route, ..)| path.ends_with(route))
+                    .unwrap_or_else(|| panic!("no route for {}", path));
+                std::thread::sleep(*delay);
+                respond(reader.get_mut(), *status, body);
+            });
+        }
+    });
+    format!("http://{}", addr)
+}
+
+/// Serve `audio` once with an `audio/mpeg` content type, e.g. for speech. Returns the base url.
+pub(crate) fn serve_audio(audio: Vec<u8>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        read_request(&mut reader);
+        respond_with(reader.get_mut(), 200, "audio/mpeg", &audio);
+    });
+    format!("http://{}", addr)
+}
+
+/// Read a request, returning its path and body.
+fn read_request(reader: &mut impl BufRead) -> (String, Vec<u8>) {
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+    let path = request_line
+        .split_whitespace()
+        .nth(1)
+        .unwrap_or_default()
+        .to_owned();
+    let mut content_length = 0;
+    let mut chunked = false;
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap();
+            } else if name.eq_ignore_ascii_case("transfer-encoding") {
+                chunked = value.trim().eq_ignore_ascii_case("chunked");
+            }
+        }
+    }
+    let body = if chunked {
+        read_chunked(reader)
+    } else {
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).unwrap();
+        body
+    };
+    (path, body)
+}
+
+fn respond(stream: &mut impl Write, status: u16, body: &str) {
+    respond_with(stream, status, "application/json", body.as_bytes());
+}
+
+fn respond_with(stream: &mut impl Write, status: u16, content_type: &str, body: &[u8]) {
+    let head = format!(
+        "HTTP/1.1 {} X\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    );
+    // the client may have given up on the response
+    let _ = stream.write_all(head.as_bytes());
+    let _ = stream.write_all(body);
+}
+
+/// Read a body sent with `transfer-encoding: chunked`, e.g. a streamed upload.
+fn read_chunked(reader: &mut impl BufRead) -> Vec<u8> {
+    let mut body = Vec::new();
+    loop {
+        let mut size = String::new();
+        reader.read_line(&mut size).unwrap();
+        let size = usize::from_str_radix(size.trim(), 16).unwrap();
+        let mut chunk = vec![0; size + 2];
+        reader.read_exact(&mut chunk).unwrap();
+        if size == 0 {
+            return body;
+        }
+        body.extend_from_slice(&chunk[..size]);
+    }
+}
+
+/// A chat completion response whose only choice has `content`.
+pub(crate) fn chat_response(content: &str) -> String {
+    serde_json::json!({
+        "id": "chatcmpl-1",
+        "object": "chat.completion",
+        "created": 1700000000,
+        "model": "gpt-3.5-turbo-1106",
+        "system_fingerprint": null,
+        "choices": [{
+            "index": 0,
+            "finish_reason": "stop",
+            "message": {"role": "assistant", "content": content}
+        }],
+        "usage": {"prompt_tokens": 1, "completion_tokens": 1, "total_tokens": 2}
+    })
+    .to_string()
+}
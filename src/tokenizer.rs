@@ -0,0 +1,165 @@
+use crate::ChatCompleteModel;
+#[cfg(feature = "tokenizer")]
+use once_cell::sync::Lazy;
+#[cfg(feature = "tokenizer")]
+use std::collections::HashMap;
+#[cfg(feature = "tokenizer")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "tokenizer")]
+use tiktoken_rs::CoreBPE;
+
+/// Encodings are expensive to build, so each is built once and shared.
+#[cfg(feature = "tokenizer")]
+static ENCODINGS: Lazy<Mutex<HashMap<Encoding, Option<Arc<CoreBPE>>>>> =
+    Lazy::new(Default::default);
+
+#[cfg(feature = "tokenizer")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Encoding {
+    Cl100kBase,
+    O200kBase,
+}
+
+/// Counts tokens with the encoding of a model. Without the `tokenizer` feature this is an
+/// estimate of one token per four characters.
+#[derive(Clone, Default)]
+pub(crate) struct Tokenizer {
+    #[cfg(feature = "tokenizer")]
+    bpe: Option<Arc<CoreBPE>>,
+}
+
+impl Tokenizer {
+    pub(crate) fn for_model(model: &ChatCompleteModel) -> Self {
+        #[cfg(feature = "tokenizer")]
+        {
+            let encoding = Encoding::for_model(model);
+            let mut encodings = ENCODINGS.lock().unwrap();
+            let bpe = encodings
+                .entry(encoding)
+                .or_insert_with(|| encoding.build())
+                .clone();
+            Self { bpe }
+        }
+        #[cfg(not(feature = "tokenizer"))]
+        {
+            let _ = model;
+            Self {}
+        }
+    }
+
+    /// The tokenizer of the OpenAI embedding models, which all use cl100k_base.
+    #[cfg(feature = "tokenizer")]
+    pub(crate) fn for_embeddings() -> Self {
+        Self::for_model(&ChatCompleteModel::Gpt4Turbo)
+    }
+
+    /// The longest prefix of `text` with at most `max_tokens` tokens, `None` if all of `text`
+    /// fits.
+    #[cfg(feature = "tokenizer")]
+    pub(crate) fn truncate(&self, text: &str, max_tokens: usize) -> Option<String> {
+        let Some(bpe) = &self.bpe else {
+            let chars = max_tokens * 4;
+            return (text.chars().count() > chars).then(|| text.chars().take(chars).collect());
+        };
+        let tokens = bpe.encode_with_special_tokens(text);
+        if tokens.len() <= max_tokens {
+            return None;
+        }
+        // a cut inside a multi-byte character doesn't decode, so drop tokens until it does
+        (0..=max_tokens)
+            .rev()
+            .find_map(|n| bpe.decode(tokens[..n].to_vec()).ok())
+    }
+
+    pub(crate) fn count(&self, text: &str) -> usize {
+        #[cfg(feature = "tokenizer")]
+        if let Some(bpe) = &self.bpe {
+            return bpe.encode_with_special_tokens(text).len();
+        }
+        text.chars().count().div_ceil(4)
+    }
+}
+
+#[cfg(feature = "tokenizer")]
+impl Encoding {
+    fn for_model(model: &ChatCompleteModel) -> Self {
+        match model {
+            ChatCompleteModel::Gpt4oMini => Encoding::O200kBase,
+            ChatCompleteModel::Custom(name)
+                if ["gpt-4o", "gpt-4.1", "o1", "o3", "o4"]
+                    .iter()
+                    .any(|prefix| name.starts_with(prefix)) =>
+            {
+                Encoding::O200kBase
+            }
+            _ => Encoding::Cl100kBase,
+        }
+    }
+
+    fn build(self) -> Option<Arc<CoreBPE>> {
+        let bpe = match self {
+            Encoding::Cl100kBase => tiktoken_rs::cl100k_base(),
+            Encoding::O200kBase => tiktoken_rs::o200k_base(),
+        };
+        bpe.ok().map(Arc::new)
+    }
+}
+
+/// Load the encoding of `model` ahead of time, e.g. at startup, so the first token count in a
+/// hot path doesn't pay for it. Does nothing without the `tokenizer` feature.
+pub fn warm_tokenizer(model: &ChatCompleteModel) {
+    Tokenizer::for_model(model);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn count_should_count_short_text() {
+        let tokenizer = Tokenizer::for_model(&ChatCompleteModel::default());
+        assert_eq!(tokenizer.count(""), 0);
+        let count = tokenizer.count("Hello, world!");
+        assert!((3..=5).contains(&count), "got {}", count);
+    }
+
+    #[cfg(feature = "tokenizer")]
+    #[test]
+    fn truncate_should_keep_a_prefix_within_the_limit() {
+        let tokenizer = Tokenizer::for_embeddings();
+        let text = "The quick brown fox jumps over the lazy dog. ".repeat(10);
+        assert!(tokenizer.truncate(&text, 1000).is_none());
+        let prefix = tokenizer.truncate(&text, 7).unwrap();
+        assert!(text.starts_with(&prefix));
+        assert_eq!(tokenizer.count(&prefix), 7);
+    }
+
+    #[cfg(feature = "tokenizer")]
+    #[test]
+    fn truncate_should_not_split_characters() {
+        let tokenizer = Tokenizer::for_embeddings();
+        let text = "🦀🦀🦀🦀";
+        for max_tokens in 1..tokenizer.count(text) {
+            let prefix = tokenizer.truncate(text, max_tokens).unwrap();
+            assert!(text.starts_with(&prefix));
+            assert!(tokenizer.count(&prefix) <= max_tokens);
+        }
+    }
+
+    #[cfg(feature = "tokenizer")]
+    #[test]
+    fn encodings_should_be_cached_per_model() {
+        warm_tokenizer(&ChatCompleteModel::Gpt4oMini);
+        let a = Tokenizer::for_model(&ChatCompleteModel::Gpt4oMini);
+        let b = Tokenizer::for_model(&ChatCompleteModel::Custom("gpt-4o-2024-08-06".into()));
+        let c = Tokenizer::for_model(&ChatCompleteModel::Gpt4Turbo);
+        assert!(Arc::ptr_eq(
+            a.bpe.as_ref().unwrap(),
+            b.bpe.as_ref().unwrap()
+        ));
+        assert!(!Arc::ptr_eq(
+            a.bpe.as_ref().unwrap(),
+            c.bpe.as_ref().unwrap()
+        ));
+    }
+}